@@ -12,6 +12,7 @@ from typing import (
     Dict,
     Any,
     Union,
+    Callable,
 )
 from pyzx.graph.base import BaseGraph, VertexType, EdgeType, FractionLike, FloatInt
 from pyzx.simplify import Simplifier
@@ -171,6 +172,12 @@ class SimpFunc(Enum):
     CliffordSimp = ...
     NoSimp = ...
 
+@final
+class CancelToken:
+    def __init__(self) -> None: ...
+    def cancel(self) -> None: ...
+    def is_cancelled(self) -> bool: ...
+
 @final
 class Decomposer:
     @staticmethod
@@ -188,7 +195,18 @@ class Decomposer:
     def with_full_simp(self) -> None: ...
     def with_clifford_simp(self) -> None: ...
     def with_split_graph_components(self, b: bool) -> None: ...
+    def with_hybrid(self, b: bool) -> None: ...
     def with_save(self, b: bool) -> None: ...
+    def with_memoize(self, b: bool) -> None: ...
+    def memo_stats(self) -> Optional[Tuple[int, int, int]]: ...
+    def with_term_budget(self, terms: int) -> None: ...
+    def with_time_budget(self, seconds: float) -> None: ...
+    def with_cancel_token(self, token: CancelToken) -> None: ...
+    def cancel_token(self) -> CancelToken: ...
+    def with_progress(
+        self, callback: Callable[[int, float, float], None], interval: float = 1.0
+    ) -> None: ...
+    def is_truncated(self) -> bool: ...
     def max_terms(self) -> int: ...
     def decompose(
         self,
@@ -9,6 +9,12 @@ pub fn to_pyzx_circuit(py: Python<'_>, c: Circuit) -> PyResult<PyObject> {
     let c1 = m.getattr("Circuit")?.call((c.num_qubits(),), None)?;
 
     for g in c.gates {
+        if g.is_conditional() {
+            return Err(PyValueError::new_err(
+                "PyZX doesn't support classically controlled gates",
+            ));
+        }
+
         match g.t {
             XPhase => {
                 let kwargs = [("phase", g.phase.to_rational())].into_py_dict(py)?;
@@ -76,7 +82,7 @@ pub fn to_pyzx_circuit(py: Python<'_>, c: Circuit) -> PyResult<PyObject> {
                     "PyZX doesn't support gate: MeasureReset",
                 ));
             }
-            UnknownGate => {}
+            Barrier | UnknownGate => {}
         }
     }
 
@@ -120,10 +126,13 @@ impl Circuit {
     fn to_qasm(&self) -> String {
         self.c.to_qasm()
     }
-    fn to_graph(&self) -> VecGraph {
-        VecGraph {
+    fn to_graph(&self) -> PyResult<VecGraph> {
+        self.c
+            .check_graph_conditions()
+            .map_err(PyValueError::new_err)?;
+        Ok(VecGraph {
             g: self.c.to_graph(),
-        }
+        })
     }
 
     fn num_gates(&self) -> usize {
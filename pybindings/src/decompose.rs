@@ -1,6 +1,14 @@
 use crate::vec_graph::VecGraph;
 use crate::Scalar;
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use std::time::Duration;
+
+/// Converts a number of seconds from Python, raising `ValueError` if it is negative or too large
+fn duration(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| PyValueError::new_err(format!("invalid duration: {seconds} seconds")))
+}
 
 #[pyclass]
 #[derive(Clone, Debug)]
@@ -20,6 +28,29 @@ impl From<SimpFunc> for ::quizx::decompose::SimpFunc {
     }
 }
 
+/// A handle to stop a running decomposition, e.g. from another Python thread.
+#[pyclass]
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    t: ::quizx::decompose::CancelToken,
+}
+
+#[pymethods]
+impl CancelToken {
+    #[new]
+    fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    fn cancel(&self) {
+        self.t.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.t.is_cancelled()
+    }
+}
+
 #[pyclass]
 pub struct Decomposer {
     d: ::quizx::decompose::Decomposer<::quizx::vec_graph::Graph>,
@@ -74,17 +105,74 @@ impl Decomposer {
         self.d.with_split_graphs_components(b);
     }
 
+    fn with_hybrid(&mut self, b: bool) {
+        self.d.with_hybrid(b);
+    }
+
     fn with_save(&mut self, b: bool) {
         self.d.with_save(b);
     }
 
+    fn with_memoize(&mut self, b: bool) {
+        self.d.with_memoize(b);
+    }
+
+    /// Returns `(hits, misses, entries)` of the memoization table, if enabled.
+    fn memo_stats(&self) -> Option<(usize, usize, usize)> {
+        self.d.memo_stats().map(|m| (m.hits, m.misses, m.entries))
+    }
+
+    fn with_term_budget(&mut self, terms: usize) {
+        self.d.with_term_budget(terms);
+    }
+
+    fn with_time_budget(&mut self, seconds: f64) -> PyResult<()> {
+        self.d.with_time_budget(duration(seconds)?);
+        Ok(())
+    }
+
+    fn with_cancel_token(&mut self, token: &CancelToken) {
+        self.d.with_cancel_token(token.t.clone());
+    }
+
+    fn cancel_token(&self) -> CancelToken {
+        CancelToken {
+            t: self.d.cancel_token(),
+        }
+    }
+
+    /// Calls `callback(terms, remaining, elapsed_seconds)` at most every `interval` seconds.
+    #[pyo3(signature = (callback, interval = 1.0))]
+    fn with_progress(&mut self, callback: PyObject, interval: f64) -> PyResult<()> {
+        self.d.with_progress(duration(interval)?, move |p| {
+            Python::with_gil(|py| {
+                if let Err(e) = callback.call1(py, (p.terms, p.remaining, p.elapsed.as_secs_f64()))
+                {
+                    e.print(py);
+                }
+            });
+        });
+        Ok(())
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.d.is_truncated()
+    }
+
     fn max_terms(&self) -> f64 {
         self.d.max_terms()
     }
 
     #[pyo3(signature = (driver_type = "BssWithCats", random_t = false, sherlock_tries = Vec::new()))]
-    fn decompose(&mut self, driver_type: &str, random_t: bool, sherlock_tries: Vec<usize>) {
-        match driver_type {
+    fn decompose(
+        &mut self,
+        py: Python<'_>,
+        driver_type: &str,
+        random_t: bool,
+        sherlock_tries: Vec<usize>,
+    ) {
+        // Release the GIL, so other threads can cancel or report progress
+        py.allow_threads(|| match driver_type {
             "BssTOnly" => {
                 self.d
                     .decompose(&quizx::decompose::BssTOnlyDriver { random_t });
@@ -107,7 +195,7 @@ impl Decomposer {
             _ => {
                 println!("Driver Not Supported!");
             }
-        };
+        });
     }
 
     #[pyo3(signature = (/, *, allow_threads=true, driver_type, random_t = false, sherlock_tries = Vec::new()))]
@@ -206,9 +294,10 @@ impl Decomposer {
 
     fn __repr__(&self) -> String {
         format!(
-            "Decomposer(nterms={}, done_size={})",
+            "Decomposer(nterms={}, done_size={}, truncated={})",
             self.d.nterms,
-            self.d.done.len()
+            self.d.done.len(),
+            self.d.is_truncated()
         )
     }
 }
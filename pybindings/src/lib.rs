@@ -9,7 +9,7 @@ pub mod util;
 pub mod vec_graph;
 
 use crate::circuit::to_pyzx_circuit;
-use crate::decompose::{Decomposer, SimpFunc};
+use crate::decompose::{CancelToken, Decomposer, SimpFunc};
 use crate::scalar::Scalar;
 use crate::vec_graph::VecGraph;
 
@@ -29,6 +29,7 @@ fn quizx(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(qasm, m)?)?;
     m.add_class::<VecGraph>()?;
     m.add_class::<Decomposer>()?;
+    m.add_class::<CancelToken>()?;
     m.add_class::<SimpFunc>()?;
     m.add_class::<Scalar>()?;
     Ok(())
@@ -37,6 +38,7 @@ fn quizx(m: &Bound<'_, PyModule>) -> PyResult<()> {
 #[pyfunction]
 fn qasm(source: &str) -> PyResult<VecGraph> {
     let c = ::quizx::circuit::Circuit::from_qasm(source).map_err(PyValueError::new_err)?;
+    c.check_graph_conditions().map_err(PyValueError::new_err)?;
     Ok(VecGraph { g: c.to_graph() })
 }
 
@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use quizx::circuit::Circuit;
+use quizx::extract::ToCircuit;
 use quizx::simplify::{clifford_simp, flow_simp, full_simp, interior_clifford_simp};
 use quizx::vec_graph::*;
 
@@ -66,5 +67,27 @@ fn simp_surface_code(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, simp_surface_code);
+fn simp_random_clifford(c: &mut Criterion) {
+    // initial setup
+    let circuit = Circuit::random_clifford().seed(1337).qubits(50).build();
+    let g: Graph = circuit.to_graph();
+
+    // benchmarking code
+    let mut group = c.benchmark_group("random_clifford");
+    group.sample_size(10);
+
+    group.bench_function("random_clifford_simp_and_extract", |b| {
+        b.iter_batched_ref(
+            || g.clone(), // clone the graph before timing
+            |g1| {
+                // timed simplification and extraction
+                clifford_simp(g1);
+                g1.to_circuit().unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, simp_surface_code, simp_random_clifford);
 criterion_main!(benches);
@@ -131,6 +131,7 @@ pub fn spider_fusion_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
 
     g.add_to_phase(v0, g.phase(v1));
     g.add_to_vars(v0, &g.vars(v1));
+    g.fuse_phases(v0, v1);
     g.remove_vertex(v1);
 }
 
@@ -217,6 +218,7 @@ pub fn pi_copy_unchecked(g: &mut impl GraphLike, v: V) {
     let phase = g.phase(v);
     g.scalar_mut().mul_phase(phase);
     g.set_phase(v, -phase);
+    g.phase_negate(v);
 
     let vars = g.vars(v);
     if !vars.is_empty() {
@@ -474,6 +476,7 @@ fn unfuse_gadget(g: &mut impl GraphLike, v: V) {
     let v1 = g.add_vertex_with_data(vd1);
     let v2 = g.add_vertex_with_data(vd2);
     g.set_phase(v, Phase::zero());
+    g.move_phase(v, v2);
     // note if v has any boolean vars, we just leave them there, rather than moving
     // them on to v1. This should be fine, since Paulis don't interfere with any
     // of the Clifford simplifications.
@@ -689,6 +692,7 @@ pub fn gadget_fusion_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
         .expect("v1 isn't a gadget");
     g.add_to_phase(gphase0, g.phase(gphase1));
     g.add_to_vars(gphase0, &g.vars(gphase1));
+    g.fuse_phases(gphase0, gphase1);
     g.remove_vertex(v1);
     g.remove_vertex(gphase1);
 
@@ -19,16 +19,261 @@ use crate::graph::*;
 use crate::linalg::RowOps;
 use crate::params::Parity;
 use crate::params::Var;
+use crate::phase::utils::limit_denominator;
 use crate::phase::Phase;
 use crate::simplify::local_ap_simp;
 use crate::util::pmax;
-use num::{Rational64, Zero};
-use openqasm::{ast::Symbol, translate::Value, GenericError, ProgramVisitor};
+use num::{FromPrimitive, Rational64, ToPrimitive, Zero};
+use openqasm::translate::{LinearizeError, Value};
+use openqasm::{Decl, GenericError, ProgramVisitor, Reg, Span, Stmt, Symbol};
 use rustc_hash::FxHashMap;
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::fmt;
+use std::rc::Rc;
 use std::str;
 
+/// Options for reading QASM circuits
+///
+/// Angles given as symbolic multiples of pi are always read exactly. These
+/// options control what happens to any other angles, such as `rz(0.1)`.
+#[derive(Clone, Copy, Debug)]
+pub struct QasmOptions {
+    /// Fail on angles that are not rational multiples of pi, instead of rounding them.
+    pub exact: bool,
+    /// The largest denominator used when rounding an angle to a rational multiple of pi.
+    pub max_denom: i64,
+}
+
+impl Default for QasmOptions {
+    fn default() -> Self {
+        QasmOptions {
+            exact: false,
+            max_denom: 1_000_000,
+        }
+    }
+}
+
+impl QasmOptions {
+    /// converts the angle `a + b * pi` to a number of half-turns, without reducing
+    /// modulo 2
+    ///
+    /// If the angle needs rounding, it is recorded in `angles` against the given gate
+    /// index. If the angle needs rounding and `exact` is set, returns the angle in
+    /// half-turns as an error.
+    pub(crate) fn read_angle(
+        &self,
+        a: f64,
+        b: Rational64,
+        gate: usize,
+        angles: &mut Vec<QasmAngle>,
+    ) -> Result<Rational64, f64> {
+        let angle = a / std::f64::consts::PI + b.to_f64().unwrap_or(0.0);
+        if a == 0.0 {
+            Ok(b)
+        } else if self.exact {
+            Err(angle)
+        } else {
+            let x = a / std::f64::consts::PI;
+            let r = Rational64::from_f64(x)
+                .or_else(|| Rational64::approximate_float(x))
+                .map_or(Rational64::zero(), |r| limit_denominator(r, self.max_denom));
+            angles.push(QasmAngle { gate, angle });
+            Ok(r + b)
+        }
+    }
+}
+
+/// An angle that had to be rounded when reading a QASM circuit, recorded by [`QasmImport`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QasmAngle {
+    /// The index of the first gate in the circuit produced by the instruction
+    /// containing the angle.
+    pub gate: usize,
+    /// The angle as written, in half-turns.
+    pub angle: f64,
+}
+
+/// A circuit read from QASM, along with any angles that could not be read exactly
+#[derive(Clone, Debug)]
+pub struct QasmImport {
+    pub circuit: Circuit,
+    pub angles: Vec<QasmAngle>,
+}
+
+impl QasmImport {
+    /// Returns `true` if every angle in the source was read exactly.
+    pub fn is_exact(&self) -> bool {
+        self.angles.is_empty()
+    }
+
+    /// Returns the angles that had to be rounded.
+    pub fn rounded_angles(&self) -> impl Iterator<Item = &QasmAngle> {
+        self.angles.iter()
+    }
+}
+
+/// The condition of an `if` statement, while reading OpenQASM
+#[derive(Clone, Debug)]
+pub(crate) enum QasmCondition {
+    /// The parity of some bits is 1. A zero parity never holds, and a parity of one
+    /// always does.
+    Parity(Parity),
+    /// A register of more than one bit has a given value
+    Register(RegisterCondition),
+}
+
+impl QasmCondition {
+    /// The condition `c == value`, for the register `c` of bits `start..start + len`
+    pub(crate) fn equals(start: Var, len: usize, value: u64) -> Self {
+        if len < 64 && value >> len != 0 {
+            QasmCondition::Parity(Parity::zero())
+        } else if len == 1 {
+            let bit = Parity::single(start);
+            QasmCondition::Parity(if value == 0 { bit.negated() } else { bit })
+        } else {
+            QasmCondition::Register(RegisterCondition { start, len, value })
+        }
+    }
+
+    /// Returns `true` if the condition never holds
+    pub(crate) fn is_never(&self) -> bool {
+        matches!(self, QasmCondition::Parity(p) if p.is_zero())
+    }
+
+    /// Makes `g` controlled by the condition, or returns what isn't supported about it
+    pub(crate) fn control(&self, g: &mut Gate) -> Result<(), &'static str> {
+        if matches!(g.t, GType::Measure | GType::MeasureReset) {
+            return Err("classically controlled measurements and resets");
+        }
+        if g.is_conditional() {
+            return Err("nested conditions");
+        }
+        match self {
+            QasmCondition::Parity(p) => {
+                if *p != Parity::one() {
+                    g.vars = p.clone();
+                }
+            }
+            QasmCondition::Register(c) => g.condition = Some(*c),
+        }
+        Ok(())
+    }
+}
+
+/// The classical registers used to write a circuit as OpenQASM
+///
+/// OpenQASM can only compare whole registers with a value, so the bits of each
+/// [`RegisterCondition`] form a register, merged with any others they overlap. Every
+/// other bit is a register of its own.
+pub(crate) struct ClassicalRegisters {
+    /// The first bit and the size of each register, in order
+    pub(crate) regs: Vec<(usize, usize)>,
+    /// The index in `regs` of the register each bit is in
+    reg_of: Vec<usize>,
+}
+
+impl ClassicalRegisters {
+    pub(crate) fn new(c: &Circuit, nbits: usize) -> Self {
+        let mut ranges: Vec<(usize, usize)> = c
+            .gates
+            .iter()
+            .filter_map(|g| g.condition)
+            .map(|c| (c.start as usize, c.start as usize + c.len))
+            .collect();
+        ranges.sort();
+        let mut ranges = ranges.into_iter().peekable();
+
+        let mut regs = vec![];
+        let mut reg_of = vec![];
+        let mut start = 0;
+        while start < nbits {
+            let mut end = start + 1;
+            while let Some((_, e)) = ranges.next_if(|&(s, _)| s < end) {
+                end = end.max(e);
+            }
+            reg_of.extend(std::iter::repeat(regs.len()).take(end - start));
+            regs.push((start, end - start));
+            start = end;
+        }
+        ClassicalRegisters { regs, reg_of }
+    }
+
+    /// The register a bit is in, and its index in that register
+    pub(crate) fn locate(&self, bit: usize) -> ((usize, usize), usize) {
+        let reg = self.regs[self.reg_of[bit]];
+        (reg, bit - reg.0)
+    }
+
+    /// The comparisons to write a classically controlled gate with
+    ///
+    /// Writing the gate once for each element, as `if (reg == value) gate` for
+    /// `Some((reg, value))` and unconditionally for `None`, applies it under the same
+    /// condition. Registers are given by their first bit and size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a gate other than a Pauli is controlled by bits in different registers.
+    pub(crate) fn conditions(&self, g: &Gate) -> Vec<Option<((usize, usize), u64)>> {
+        let mut conds = vec![];
+        if g.condition.is_none() && g.is_pauli() {
+            // a Pauli controlled on b1 ⊕ b2 ⊕ .. is applied once for each bit, with
+            // the constant folded into the first condition
+            let mut flip = g.vars.flip();
+            for v in g.vars.iter() {
+                let p = Parity::new([v], flip);
+                conds.extend(self.values(&p, None).into_iter().map(Some));
+                flip = false;
+            }
+            if flip {
+                conds.push(None);
+            }
+        } else if g.condition.is_none() && g.vars.is_empty() {
+            conds.push(None);
+        } else {
+            conds.extend(self.values(&g.vars, g.condition).into_iter().map(Some));
+        }
+        conds
+    }
+
+    /// The values of the register containing the given bits for which the parity `vars`
+    /// is 1, unless it is zero, and `condition` holds, if there is one
+    fn values(
+        &self,
+        vars: &Parity,
+        condition: Option<RegisterCondition>,
+    ) -> Vec<((usize, usize), u64)> {
+        let bits: Vec<usize> = vars
+            .iter()
+            .chain(condition.iter().flat_map(|c| c.bits()))
+            .map(|v| v as usize)
+            .collect();
+        let r = self.reg_of[bits[0]];
+        assert!(
+            bits.iter().all(|&b| self.reg_of[b] == r),
+            "only Pauli gates can be written with a condition on bits of different registers"
+        );
+        let reg @ (start, len) = self.regs[r];
+
+        // a condition on the whole register holds for a single value
+        if let Some(c) = condition.filter(|c| vars.is_zero() && c.len == len) {
+            return vec![(reg, c.value)];
+        }
+        let bit = |k: u64, v: Var| (k >> (v as usize - start)) & 1 == 1;
+        (0..1u64 << len)
+            .filter(|&k| vars.is_zero() || vars.eval(|v| bit(k, v)))
+            .filter(|&k| {
+                condition.map_or(true, |c| {
+                    c.bits()
+                        .enumerate()
+                        .all(|(i, v)| bit(k, v) == ((c.value >> i) & 1 == 1))
+                })
+            })
+            .map(|k| (reg, k))
+            .collect()
+    }
+}
+
 /// A type for quantum circuits
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Circuit {
@@ -45,20 +290,26 @@ pub struct CircuitStats {
     pub moreq: usize,
     pub cliff: usize,
     pub non_cliff: usize,
+    /// The number of T gates, counting phases that are odd multiples of pi/4, and
+    /// 7 for each CCZ or Toffoli gate
+    pub tcount: usize,
 }
 
 impl CircuitStats {
     pub fn make(c: &Circuit) -> Self {
         let mut s = CircuitStats {
             qubits: c.num_qubits(),
-            total: c.num_gates(),
+            total: 0,
             oneq: 0,
             twoq: 0,
             moreq: 0,
             cliff: 0,
             non_cliff: 0,
+            tcount: 0,
         };
-        for g in &c.gates {
+        // barriers aren't gates, so they aren't counted
+        for g in c.gates.iter().filter(|g| g.t != Barrier) {
+            s.total += 1;
             match g.qs.len() {
                 1 => {
                     s.oneq += 1;
@@ -75,22 +326,25 @@ impl CircuitStats {
                 NOT | Z | S | Sdg | CNOT | CZ | SWAP | HAD => {
                     s.cliff += 1;
                 }
-                ZPhase | XPhase => {
-                    if g.phase.is_clifford() {
-                        s.cliff += 1;
-                    } else {
-                        s.non_cliff += 1;
-                    }
+                ZPhase | XPhase if g.phase.is_clifford() => {
+                    s.cliff += 1;
                 }
                 _ => {
                     s.non_cliff += 1;
                 }
             }
+
+            match g.t {
+                T | Tdg => s.tcount += 1,
+                ZPhase | XPhase | ParityPhase if g.phase.is_t() => s.tcount += 1,
+                CCZ | TOFF => s.tcount += 7,
+                _ => {}
+            }
         }
         s
     }
 
-    pub fn into_array(self) -> [usize; 7] {
+    pub fn into_array(self) -> [usize; 8] {
         [
             self.qubits,
             self.total,
@@ -99,13 +353,14 @@ impl CircuitStats {
             self.moreq,
             self.cliff,
             self.non_cliff,
+            self.tcount,
         ]
     }
 }
 
 impl fmt::Display for CircuitStats {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Circuit with {} qubits, {} gates\n  1-qubit: {}\n  2-qubit: {}\n  n-qubit: {}\n  clifford: {}\n  non-clifford: {}", self.qubits, self.total, self.oneq, self.twoq, self.moreq, self.cliff, self.non_cliff)
+        write!(f, "Circuit with {} qubits, {} gates\n  1-qubit: {}\n  2-qubit: {}\n  n-qubit: {}\n  clifford: {}\n  non-clifford: {}\n  T-count: {}", self.qubits, self.total, self.oneq, self.twoq, self.moreq, self.cliff, self.non_cliff, self.tcount)
     }
 }
 
@@ -135,6 +390,14 @@ impl Circuit {
         n
     }
 
+    /// Returns `true` if the circuit has measurements, resets or classically controlled
+    /// gates
+    pub fn is_dynamic(&self) -> bool {
+        self.gates
+            .iter()
+            .any(|g| matches!(g.t, GType::Measure | GType::MeasureReset) || g.is_conditional())
+    }
+
     pub fn push(&mut self, g: Gate) {
         self.gates.push_back(g);
     }
@@ -191,7 +454,10 @@ impl Circuit {
         String::from("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n") + &self.to_string()
     }
 
-    fn from_qasm_parser(read: impl FnOnce(&mut openqasm::Parser)) -> Result<Circuit, String> {
+    fn from_qasm_parser(
+        read: impl FnOnce(&mut openqasm::Parser),
+        options: &QasmOptions,
+    ) -> Result<QasmImport, String> {
         let mut cache = openqasm::SourceCache::new();
         let mut parser = openqasm::Parser::new(&mut cache)
             .with_file_policy(openqasm::parser::FilePolicy::Ignore);
@@ -217,7 +483,8 @@ impl Circuit {
             opaque post_sel a;
             opaque measure_d q;
         "
-            .to_string(),
+            .to_string()
+                + &std_gate_decls(),
             None,
         );
 
@@ -227,24 +494,53 @@ impl Circuit {
             .to_errors()
             .map_err(|e| e.to_string())?;
 
+        let condition = Rc::new(RefCell::new(None));
         let mut writer = CircuitWriter {
             circuit: Circuit::new(0),
+            options: *options,
+            angles: vec![],
+            condition: condition.clone(),
         };
-        let mut linearize = openqasm::Linearize::new(&mut writer, usize::MAX);
-        linearize
+        let mut visitor = ConditionalVisitor {
+            linearize: openqasm::Linearize::new(&mut writer, usize::MAX),
+            cregs: FxHashMap::default(),
+            nbits: 0,
+            condition,
+        };
+        visitor
             .visit_program(&program)
-            .to_errors()
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| format!("{e}: {}", e.kind))?;
 
-        Ok(writer.circuit)
+        Ok(QasmImport {
+            circuit: writer.circuit,
+            angles: writer.angles,
+        })
     }
 
     pub fn from_qasm(source: &str) -> Result<Circuit, String> {
-        Circuit::from_qasm_parser(|parser| parser.parse_source::<String>(source.to_string(), None))
+        Circuit::from_qasm_with_options(source, &QasmOptions::default()).map(|imp| imp.circuit)
     }
 
     pub fn from_file(name: &str) -> Result<Circuit, String> {
-        Circuit::from_qasm_parser(|parser| parser.parse_file(name))
+        Circuit::from_file_with_options(name, &QasmOptions::default()).map(|imp| imp.circuit)
+    }
+
+    /// Reads a circuit from OpenQASM 2 source, reporting any angles that could not be
+    /// read exactly
+    pub fn from_qasm_with_options(
+        source: &str,
+        options: &QasmOptions,
+    ) -> Result<QasmImport, String> {
+        Circuit::from_qasm_parser(
+            |parser| parser.parse_source::<String>(source.to_string(), None),
+            options,
+        )
+    }
+
+    /// Reads a circuit from an OpenQASM 2 file, reporting any angles that could not be
+    /// read exactly
+    pub fn from_file_with_options(name: &str, options: &QasmOptions) -> Result<QasmImport, String> {
+        Circuit::from_qasm_parser(|parser| parser.parse_file(name), options)
     }
 
     /// returns a copy of the circuit, decomposed into 1- and 2-qubit Clifford +
@@ -263,8 +559,28 @@ impl Circuit {
         c
     }
 
+    /// Converts the circuit to a ZX-diagram, optionally simplifying it as gates are added
+    ///
+    /// Panics if a classically controlled gate can't be represented, see
+    /// [`Circuit::check_graph_conditions`].
     pub fn to_graph_with_options<G: GraphLike>(&self, simplify: bool, postselect: bool) -> G {
+        self.build_graph(simplify, postselect, false)
+    }
+
+    /// Converts the circuit to a graph, tracking the phases of its non-Clifford phase
+    /// gates
+    ///
+    /// The phase of each non-Clifford `ZPhase`, `XPhase`, `T` or `Tdg` gate is
+    /// tracked with the index of that gate in the circuit. See [`PhaseTracker`].
+    pub fn to_graph_with_phase_tracking<G: GraphLike>(&self) -> G {
+        self.build_graph(false, false, true)
+    }
+
+    fn build_graph<G: GraphLike>(&self, simplify: bool, postselect: bool, track: bool) -> G {
         let mut graph = G::new();
+        if track {
+            graph.set_phase_tracker(Some(PhaseTracker::new()));
+        }
         let mut qs = FxHashMap::default();
         let mut inputs = Vec::with_capacity(self.nqubits);
         let mut outputs = Vec::with_capacity(self.nqubits);
@@ -300,14 +616,63 @@ impl Circuit {
             .max()
             .map_or(0, |fr| fr + 1);
 
-        for g in &self.gates {
-            let vs = g.add_to_graph(&mut fresh_var, &mut graph, &mut qs, postselect);
+        // a measured qubit that is used again later is left in the post-measurement
+        // state, rather than being discarded
+        let mut used = vec![false; self.nqubits];
+        let mut keep_qubit = vec![false; self.gates.len()];
+        for (i, g) in self.gates.iter().enumerate().rev() {
+            match g.t {
+                GType::Barrier => {}
+                GType::Measure => {
+                    keep_qubit[i] = used[g.qs[0]];
+                    used[g.qs[0]] = true;
+                }
+                _ => g.qs.iter().for_each(|&q| used[q] = true),
+            }
+        }
+
+        for (i, (g, &keep)) in self.gates.iter().zip(&keep_qubit).enumerate() {
+            let vs = if keep {
+                let vars = if g.vars.is_zero() {
+                    fresh_var += 1;
+                    Parity::single(fresh_var - 1)
+                } else {
+                    g.vars.clone()
+                };
+                let mut vs = Vec::new();
+                for t in [GType::MeasureReset, GType::NOT] {
+                    let g1 =
+                        Gate::new_with_phase_and_vars(t, g.qs.clone(), Phase::zero(), vars.clone());
+                    vs.extend(g1.add_to_graph(&mut fresh_var, &mut graph, &mut qs, postselect));
+                }
+                vs
+            } else {
+                g.add_to_graph(&mut fresh_var, &mut graph, &mut qs, postselect)
+            };
+
+            if let Some(t) = graph.phase_tracker_mut() {
+                let non_clifford = match g.t {
+                    GType::T | GType::Tdg => true,
+                    GType::ZPhase | GType::XPhase => !g.phase.is_clifford(),
+                    _ => false,
+                };
+                if non_clifford && !g.is_conditional() {
+                    t.track(vs[0], i);
+                }
+            }
 
             if simplify {
                 local_ap_simp(&mut graph, vs);
             }
         }
 
+        // SWAP gates permute the wires rather than adding vertices, so put the outputs
+        // back in qubit order
+        let mut wires: Vec<(usize, usize)> = qs.into_iter().collect();
+        wires.sort();
+        let outputs = graph.outputs().clone();
+        graph.set_outputs(wires.iter().map(|&(_, i)| outputs[i]).collect());
+
         let last_row = pmax(graph.outputs().iter().map(|&o| graph.row(o))).unwrap_or(2.0);
 
         for outp in graph.outputs().clone() {
@@ -317,6 +682,14 @@ impl Circuit {
         graph
     }
 
+    /// Returns an error if [`Circuit::to_graph`] can't represent a classically controlled
+    /// gate of the circuit
+    ///
+    /// See [`Gate::check_graph_condition`].
+    pub fn check_graph_conditions(&self) -> Result<(), String> {
+        self.gates.iter().try_for_each(Gate::check_graph_condition)
+    }
+
     pub fn to_graph<G: GraphLike>(&self) -> G {
         self.to_graph_with_options(false, false)
     }
@@ -324,14 +697,77 @@ impl Circuit {
     pub fn stats(&self) -> CircuitStats {
         CircuitStats::make(self)
     }
+
+    /// Returns the number of classical bits used by the circuit, and for each gate the
+    /// bit that it writes a measurement outcome to
+    ///
+    /// The classical bits are the variables of measurements and classically controlled
+    /// gates, and the bits of register conditions. Measurements whose outcome isn't
+    /// stored in a single bit are given a fresh one, except `MeasureReset` gates without
+    /// variables, which are written as plain resets.
+    pub(crate) fn classical_bits(&self) -> (usize, Vec<Option<usize>>) {
+        let mut nbits = self
+            .gates
+            .iter()
+            .filter_map(|g| g.bits().max())
+            .max()
+            .map_or(0, |v| v as usize + 1);
+        let bits = self
+            .gates
+            .iter()
+            .map(|g| match g.t {
+                GType::Measure | GType::MeasureReset => {
+                    if g.vars.len() == 1 && !g.vars.flip() {
+                        Some(g.vars[0] as usize)
+                    } else if g.t == GType::MeasureReset && g.vars.is_zero() {
+                        None
+                    } else {
+                        nbits += 1;
+                        Some(nbits - 1)
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+        (nbits, bits)
+    }
 }
 
 impl fmt::Display for Circuit {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "qreg q[{}];", self.num_qubits())?;
 
-        for g in &self.gates {
-            writeln!(f, "{};", g.to_qasm())?;
+        // QASM 2 can only condition on whole registers, so most bits get a register of
+        // their own
+        let (nbits, bits) = self.classical_bits();
+        let regs = ClassicalRegisters::new(self, nbits);
+        for &(start, len) in &regs.regs {
+            writeln!(f, "creg c{start}[{len}];")?;
+        }
+
+        for (g, b) in self.gates.iter().zip(bits) {
+            match g.t {
+                GType::Measure | GType::MeasureReset => {
+                    if let Some(b) = b {
+                        let ((start, _), i) = regs.locate(b);
+                        writeln!(f, "measure q[{}] -> c{start}[{i}];", g.qs[0])?;
+                    }
+                    if g.t == GType::MeasureReset {
+                        writeln!(f, "reset q[{}];", g.qs[0])?;
+                    }
+                }
+                _ if g.is_conditional() => {
+                    for cond in regs.conditions(g) {
+                        match cond {
+                            Some(((start, _), k)) => {
+                                writeln!(f, "if(c{start}=={k}) {};", g.to_qasm())?
+                            }
+                            None => writeln!(f, "{};", g.to_qasm())?,
+                        }
+                    }
+                }
+                _ => writeln!(f, "{};", g.to_qasm())?,
+            }
         }
 
         Ok(())
@@ -405,30 +841,69 @@ impl RowOps for Circuit {
     }
 }
 
+/// opaque declarations for the gates in [`STD_GATES`], other than the built-in `U`
+fn std_gate_decls() -> String {
+    let mut decls = String::new();
+    for &(name, nparams, nqubits) in STD_GATES.iter().filter(|(n, _, _)| *n != "U") {
+        let params: Vec<String> = (0..nparams).map(|i| format!("p{i}")).collect();
+        let qs: Vec<String> = (0..nqubits).map(|i| format!("q{i}")).collect();
+        if params.is_empty() {
+            decls += &format!("opaque {name} {};\n", qs.join(", "));
+        } else {
+            decls += &format!("opaque {name}({}) {};\n", params.join(", "), qs.join(", "));
+        }
+    }
+    decls
+}
+
 struct CircuitWriter {
     circuit: Circuit,
+    options: QasmOptions,
+    angles: Vec<QasmAngle>,
+    /// the condition that the gates currently being written are controlled by, if any
+    condition: Rc<RefCell<Option<QasmCondition>>>,
+}
+
+impl CircuitWriter {
+    /// applies the current condition to the gates written since `start`
+    ///
+    /// Gates whose condition never holds are dropped.
+    fn condition_gates(&mut self, start: usize) -> Result<(), CircuitWriterError> {
+        if let Some(cond) = &*self.condition.borrow() {
+            for g in self.circuit.gates.range_mut(start..) {
+                cond.control(g).map_err(CircuitWriterError::Conditional)?;
+            }
+            if cond.is_never() {
+                self.circuit.gates.truncate(start);
+            }
+        }
+        Ok(())
+    }
+
+    /// converts a parameter to an angle in units of pi, without reducing modulo 2
+    fn param_to_angle(&mut self, value: Value) -> Result<Rational64, CircuitWriterError> {
+        let a = value.a.to_f64().unwrap_or(0.0);
+        let b = Rational64::new(*value.b.numer(), *value.b.denom());
+        let gate = self.circuit.num_gates();
+        self.options
+            .read_angle(a, b, gate, &mut self.angles)
+            .map_err(CircuitWriterError::InexactAngle)
+    }
 }
 
 #[derive(Debug)]
-#[allow(clippy::enum_variant_names)]
 enum CircuitWriterError {
-    UnitaryNotSupported,
-    BarrierNotSupported,
-    ResetNotSupported,
-    ConditionalNotSupported,
+    InexactAngle(f64),
+    Conditional(&'static str),
 }
 
 impl std::fmt::Display for CircuitWriterError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            CircuitWriterError::UnitaryNotSupported => {
-                write!(f, "arbitrary unitaries are not supported")
-            }
-            CircuitWriterError::BarrierNotSupported => write!(f, "barriers are not supported"),
-            CircuitWriterError::ResetNotSupported => write!(f, "resets are not supported"),
-            CircuitWriterError::ConditionalNotSupported => {
-                write!(f, "conditionals are not supported")
+            CircuitWriterError::InexactAngle(angle) => {
+                write!(f, "angle {angle}*pi is not a rational multiple of pi")
             }
+            CircuitWriterError::Conditional(what) => write!(f, "{what} are not supported"),
         }
     }
 }
@@ -444,8 +919,9 @@ impl openqasm::GateWriter for &mut CircuitWriter {
     }
 
     fn write_cx(&mut self, a: usize, b: usize) -> Result<(), Self::Error> {
+        let start = self.circuit.num_gates();
         self.circuit.push(Gate::new(GType::CNOT, vec![a, b]));
-        Ok(())
+        self.condition_gates(start)
     }
 
     fn write_opaque(
@@ -454,65 +930,136 @@ impl openqasm::GateWriter for &mut CircuitWriter {
         params: &[Value],
         regs: &[usize],
     ) -> Result<(), Self::Error> {
-        fn param_to_phase(value: Value) -> Phase {
-            if value.a.is_zero() {
-                Rational64::new(*value.b.numer(), *value.b.denom()).into()
-            } else {
-                let a = *value.a.numer() as f32 / *value.a.denom() as f32;
-                let mut r =
-                    Rational64::approximate_float(a / std::f32::consts::PI).unwrap_or(0.into());
-                r += Rational64::new(*value.b.numer(), *value.b.denom());
-                Phase::new(r)
-            }
+        let params = params
+            .iter()
+            .map(|&p| self.param_to_angle(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let start = self.circuit.num_gates();
+        if Gate::push_std_gate_decomp(&mut self.circuit, name.as_str(), &params, regs) {
+            return self.condition_gates(start);
         }
 
         let mut g = Gate::from_qasm_name(name.as_str());
         g.qs.extend_from_slice(regs);
         if !params.is_empty() {
-            g.phase = param_to_phase(params[0]);
+            g.phase = Phase::new(params[0]);
         }
 
         self.circuit.push(g);
 
-        Ok(())
+        self.condition_gates(start)
     }
 
-    fn write_u(&mut self, _: Value, _: Value, _: Value, _: usize) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::UnitaryNotSupported)
+    fn write_u(
+        &mut self,
+        theta: Value,
+        phi: Value,
+        lambda: Value,
+        reg: usize,
+    ) -> Result<(), Self::Error> {
+        let params = [
+            self.param_to_angle(theta)?,
+            self.param_to_angle(phi)?,
+            self.param_to_angle(lambda)?,
+        ];
+        let start = self.circuit.num_gates();
+        Gate::push_std_gate_decomp(&mut self.circuit, "U", &params, &[reg]);
+        self.condition_gates(start)
     }
 
-    fn write_barrier(&mut self, _: &[usize]) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::BarrierNotSupported)
+    fn write_barrier(&mut self, regs: &[usize]) -> Result<(), Self::Error> {
+        let mut qs = regs.to_vec();
+        qs.sort();
+        self.circuit.push(Gate::new(GType::Barrier, qs));
+        Ok(())
     }
 
-    fn write_reset(&mut self, _: usize) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ResetNotSupported)
+    fn write_reset(&mut self, reg: usize) -> Result<(), Self::Error> {
+        let start = self.circuit.num_gates();
+        self.circuit.push(Gate::new(GType::MeasureReset, vec![reg]));
+        self.condition_gates(start)
     }
 
     fn write_measure(&mut self, from: usize, to: usize) -> Result<(), Self::Error> {
+        let start = self.circuit.num_gates();
         self.circuit.push(Gate::new_with_phase_and_vars(
             GType::Measure,
             vec![from],
             Phase::zero(),
             Parity::single(to as Var),
         ));
-        Ok(())
+        self.condition_gates(start)
     }
 
+    // conditions are set by ConditionalVisitor instead
     fn start_conditional(&mut self, _: usize, _: usize, _: u64) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ConditionalNotSupported)
+        unreachable!()
     }
 
     fn end_conditional(&mut self) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ConditionalNotSupported)
+        unreachable!()
+    }
+}
+
+/// Passes a program on to [`openqasm::Linearize`], except for `if` statements,
+/// which it handles itself
+///
+/// `Linearize` looks up the register of a condition among the quantum registers,
+/// so it can't be used for these. Instead, the condition is handed to the
+/// [`CircuitWriter`] directly.
+struct ConditionalVisitor<'a> {
+    linearize: openqasm::Linearize<&'a mut CircuitWriter>,
+    cregs: FxHashMap<Symbol, (usize, usize)>,
+    nbits: usize,
+    condition: Rc<RefCell<Option<QasmCondition>>>,
+}
+
+impl ProgramVisitor for ConditionalVisitor<'_> {
+    type Error = LinearizeError;
+
+    fn visit_decl(&mut self, decl: &Span<Decl>) -> Result<(), Self::Error> {
+        match &*decl.inner {
+            Decl::CReg { reg } => {
+                // registers are numbered in order of declaration, as in Linearize
+                let size = reg.index.unwrap_or(1) as usize;
+                self.cregs.insert(reg.name.clone(), (self.nbits, size));
+                self.nbits += size;
+                self.linearize.visit_decl(decl)
+            }
+            Decl::Stmt(stmt) => self.visit_stmt(stmt),
+            _ => self.linearize.visit_decl(decl),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Span<Stmt>) -> Result<(), Self::Error> {
+        if let Stmt::Conditional { reg, val, then } = &*stmt.inner {
+            self.visit_conditional(reg, val, then)
+        } else {
+            self.linearize.visit_stmt(stmt)
+        }
+    }
+
+    fn visit_conditional(
+        &mut self,
+        reg: &Span<Reg>,
+        val: &Span<u64>,
+        then: &Span<Stmt>,
+    ) -> Result<(), Self::Error> {
+        let (base, size) = self.cregs[&reg.name];
+        *self.condition.borrow_mut() = Some(QasmCondition::equals(base as Var, size, **val));
+        let result = self.linearize.visit_stmt(then);
+        *self.condition.borrow_mut() = None;
+        result
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scalar::*;
     use crate::tensor::*;
     use crate::vec_graph::Graph;
+    use std::f64::consts::PI;
 
     #[test]
     fn mk_circuit() {
@@ -574,6 +1121,37 @@ mod tests {
         assert_eq!(c1, Ok(c));
     }
 
+    #[test]
+    fn inexact_angles() {
+        let qasm = r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            rz(pi/4) q[0];
+            rz(pi/1024 + 0.000001) q[0];
+            crz(pi/1024) q[0], q[1];
+            rx(0.5) q[1];
+        "#;
+
+        let imp = Circuit::from_qasm_with_options(qasm, &QasmOptions::default()).unwrap();
+        assert!(!imp.is_exact());
+        let rounded: Vec<_> = imp.rounded_angles().map(|a| a.gate).collect();
+        assert_eq!(rounded, vec![1, 6]);
+        assert!((imp.angles[0].angle - (1.0 / 1024.0 + 1e-6 / PI)).abs() < 1e-12);
+        assert_eq!(imp.circuit, Circuit::from_qasm(qasm).unwrap());
+
+        let options = QasmOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let err = Circuit::from_qasm_with_options(qasm, &options).unwrap_err();
+        assert!(err.contains("is not a rational multiple of pi"), "{err}");
+        let exact = "qreg q[1]; rz(3*pi/1024) q[0]; u3(pi, -pi/2, 0.5*pi) q[0];";
+        assert!(Circuit::from_qasm_with_options(exact, &options)
+            .unwrap()
+            .is_exact());
+    }
+
     #[test]
     fn mk_circuit_2reg() {
         let mut c = Circuit::new(5);
@@ -597,6 +1175,89 @@ mod tests {
         assert_eq!(c1, Ok(c));
     }
 
+    #[test]
+    fn std_gates() {
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[3];
+            u3(pi/4, pi/2, 3*pi/4) q[0];
+            u2(0, pi) q[1];
+            U(pi/2, 0, pi/4) q[2];
+            ry(0.25*pi) q[0];
+            sx q[1];
+            cu1(pi/2) q[0], q[1];
+            crz(-pi/2) q[1], q[2];
+            rzz(pi/2) q[0], q[2];
+            cswap q[0], q[1], q[2];
+            id q[0];
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(c.num_qubits(), 3);
+        assert_eq!(c.num_gates_of_type(UnknownGate), 0);
+        let g: Graph = c.to_graph();
+        assert!(Tensor4::scalar_compare(&c, &g));
+    }
+
+    #[test]
+    fn std_gate_decomps() {
+        fn circ(qasm: &str) -> Circuit {
+            Circuit::from_qasm(&format!("qreg q[3];\n{qasm}")).unwrap()
+        }
+
+        let equal = [
+            ("y q[0];", "u3(pi, pi/2, pi/2) q[0];"),
+            ("u2(0, pi) q[0];", "h q[0];"),
+            ("u3(pi/2, -pi/2, pi/2) q[0];", "rx(pi/2) q[0];"),
+            ("u3(pi/2, 0, 0) q[0];", "ry(pi/2) q[0];"),
+            ("U(0, 0, pi/2) q[0];", "u1(pi/2) q[0];"),
+            ("sx q[0]; sx q[0];", "x q[0];"),
+            ("sx q[0]; sxdg q[0];", "id q[0];"),
+            ("cy q[0], q[1];", "sdg q[1]; cx q[0], q[1]; s q[1];"),
+            ("ch q[0], q[1]; ch q[0], q[1];", "id q[0];"),
+            ("csx q[0], q[1]; csx q[0], q[1];", "cx q[0], q[1];"),
+            ("cp(3*pi/2) q[0], q[1];", "cu1(-pi/2) q[0], q[1];"),
+            ("cu1(pi) q[0], q[1];", "cz q[0], q[1];"),
+            ("crz(3*pi) q[0], q[1];", "crz(pi) q[0], q[1]; z q[0];"),
+            (
+                "crx(pi/2) q[0], q[1];",
+                "cu3(pi/2, -pi/2, pi/2) q[0], q[1];",
+            ),
+            ("cry(pi/2) q[0], q[1];", "cu3(pi/2, 0, 0) q[0], q[1];"),
+            (
+                "cu(pi/2, pi/2, 3*pi/2, pi/2) q[0], q[1];",
+                "cu3(pi/2, pi/2, 3*pi/2) q[0], q[1]; s q[0];",
+            ),
+            (
+                "rzz(pi/2) q[0], q[1];",
+                "u1(pi/2) q[0]; u1(pi/2) q[1]; cu1(-2*pi/2) q[0], q[1];",
+            ),
+            (
+                "rxx(pi/2) q[0], q[1];",
+                "h q[0]; h q[1]; rzz(pi/2) q[0], q[1]; h q[0]; h q[1];",
+            ),
+            (
+                "cswap q[0], q[1], q[2];",
+                "ccx q[0], q[2], q[1]; ccx q[0], q[1], q[2]; ccx q[0], q[2], q[1];",
+            ),
+        ];
+
+        for (q1, q2) in equal {
+            assert!(
+                Tensor4::scalar_compare(&circ(q1), &circ(q2)),
+                "expected {q1} == {q2}"
+            );
+        }
+
+        assert!(!Tensor4::scalar_compare(
+            &circ("crz(3*pi) q[0], q[1];"),
+            &circ("crz(pi) q[0], q[1];")
+        ));
+    }
+
     #[test]
     fn tograph_cz() {
         let c = Circuit::from_qasm(
@@ -678,6 +1339,23 @@ mod tests {
         assert_eq!(c.to_tensor4(), g.to_tensor4());
     }
 
+    #[test]
+    fn tograph_swap() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[3];
+            h q[0];
+            swap q[0], q[2];
+            s q[0];
+            cx q[2], q[1];
+        "#,
+        )
+        .unwrap();
+
+        let g: Graph = c.to_graph();
+        assert_eq!(c.to_tensor4(), g.to_tensor4());
+    }
+
     #[test]
     fn tograph_postsel() {
         let c = Circuit::from_qasm(
@@ -712,4 +1390,184 @@ mod tests {
         let g2 = c.to_graph_with_options::<Graph>(true, false);
         assert_eq!(g1.to_tensor4(), g2.to_tensor4());
     }
+
+    #[test]
+    fn dynamic_circuits() {
+        let qasm = r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            creg c[1];
+            h q[0];
+            measure q[0] -> c[0];
+            barrier q;
+            if(c==1) x q[1];
+            if(c==0) y q[1];
+            reset q[0];
+        "#;
+        let c = Circuit::from_qasm(qasm).unwrap();
+        assert_eq!(c.num_gates_of_type(GType::Barrier), 1);
+        assert_eq!(c.gates[2].qs, vec![0, 1]);
+        assert_eq!(c.gates[3].t, GType::NOT);
+        assert_eq!(c.gates[3].vars, Parity::single(0));
+        assert!(c
+            .gates
+            .iter()
+            .skip(4)
+            .take(2)
+            .all(|g| g.vars == Parity::single(0).negated()));
+        assert_eq!(c.gates[6].t, GType::MeasureReset);
+        assert!(c.gates[6].vars.is_zero());
+        assert_eq!(c.num_gates(), 7);
+        assert_eq!(c.stats().total, 6);
+
+        let s = c.to_qasm();
+        assert!(s.contains("creg c0[1];"), "{s}");
+        assert!(s.contains("measure q[0] -> c0[0];"), "{s}");
+        assert!(s.contains("barrier q[0], q[1];"), "{s}");
+        assert!(s.contains("if(c0==1) x q[1];"), "{s}");
+        let c1 = Circuit::from_qasm(&s).unwrap();
+        assert_eq!(c1.num_gates_of_type(GType::Barrier), 1);
+        assert_eq!(c1.num_gates_of_type(GType::MeasureReset), 1);
+        assert_eq!(c, c1);
+
+        let err = Circuit::from_qasm("qreg q[1]; creg c[1]; if(c==1) reset q[0];").unwrap_err();
+        assert!(err.contains("measurements and resets"), "{err}");
+    }
+
+    #[test]
+    fn register_conditions() {
+        let qasm = r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            creg c[2];
+            creg d[1];
+            measure q[0] -> c[1];
+            measure q[1] -> d[0];
+            if(c==2) x q[0];
+            if(d==1) h q[1];
+            if(c==3) cx q[0], q[1];
+        "#;
+        let c = Circuit::from_qasm(qasm).unwrap();
+        let cond = |value| {
+            Some(RegisterCondition {
+                start: 0,
+                len: 2,
+                value,
+            })
+        };
+        assert_eq!(c.gates[2].t, GType::NOT);
+        assert_eq!(c.gates[2].condition, cond(2));
+        assert!(c.gates[2].vars.is_zero());
+        assert_eq!(c.gates[3].t, GType::HAD);
+        assert_eq!(c.gates[3].vars, Parity::single(2));
+        assert_eq!(c.gates[4].condition, cond(3));
+        assert_eq!(c.gates[2].bits().collect::<Vec<_>>(), vec![0, 1]);
+
+        let s = c.to_qasm();
+        assert!(s.contains("creg c0[2];"), "{s}");
+        assert!(s.contains("creg c2[1];"), "{s}");
+        assert!(s.contains("measure q[0] -> c0[1];"), "{s}");
+        assert!(s.contains("if(c0==2) x q[0];"), "{s}");
+        assert!(s.contains("if(c2==1) h q[1];"), "{s}");
+        assert_eq!(Circuit::from_qasm(&s).unwrap(), c);
+        assert_eq!(Circuit::from_qasm3(&c.to_qasm3()).unwrap(), c);
+
+        let err = c.check_graph_conditions().unwrap_err();
+        assert!(err.contains("register of more than one bit"), "{err}");
+        let mut c1 = c.clone();
+        c1.gates.retain(|g| g.condition.is_none());
+        let err = c1.check_graph_conditions().unwrap_err();
+        assert!(
+            err.contains("only represent classically controlled Pauli"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn register_conditions_on_bits() {
+        // a bit of a compared register is written as the values of the register
+        let mut c = Circuit::new(1);
+        let cond = RegisterCondition {
+            start: 0,
+            len: 2,
+            value: 1,
+        };
+        c.push(Gate {
+            condition: Some(cond),
+            ..Gate::new(GType::HAD, vec![0])
+        });
+        c.add_gate_with_phase_and_vars("z", vec![0], Phase::zero(), Parity::single(1));
+        let s = c.to_qasm();
+        assert!(s.contains("if(c0==1) h q[0];"), "{s}");
+        assert!(s.contains("if(c0==2) z q[0];"), "{s}");
+        assert!(s.contains("if(c0==3) z q[0];"), "{s}");
+        let c1 = Circuit::from_qasm(&s).unwrap();
+        assert_eq!(c1.num_gates(), 3);
+        assert!(c1.gates.iter().all(|g| g.condition.is_some()));
+    }
+
+    #[test]
+    fn teleportation() {
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[3];
+            creg c0[1];
+            creg c1[1];
+            h q[1];
+            cx q[1], q[2];
+            cx q[0], q[1];
+            h q[0];
+            measure q[0] -> c0[0];
+            measure q[1] -> c1[0];
+            if(c1==1) x q[2];
+            if(c0==1) z q[2];
+        "#,
+        )
+        .unwrap();
+
+        for outcome in 0..4 {
+            let mut g: Graph = c.to_graph();
+            assert_eq!(g.outputs().len(), 1);
+            g.plug_vars(|v| outcome & (1 << v) != 0);
+            g.plug_input(1, BasisElem::Z0);
+            g.plug_input(1, BasisElem::Z0);
+            assert!(Tensor4::scalar_compare(&g, &Circuit::new(1)));
+        }
+    }
+
+    #[test]
+    fn measure_and_continue() {
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[1];
+            creg c[1];
+            h q[0];
+            measure q[0] -> c[0];
+            barrier q[0];
+            h q[0];
+        "#,
+        )
+        .unwrap();
+
+        // H |b><b| H is proportional to 1 + (-1)^b X
+        for b in [false, true] {
+            let mut g: Graph = c.to_graph();
+            assert_eq!(g.outputs().len(), 1);
+            g.plug_vars(|_| b);
+            let x = if b {
+                Scalar4::minus_one()
+            } else {
+                Scalar4::one()
+            };
+            let t = Tensor4::from_shape_vec(vec![2, 2], vec![Scalar4::one(), x, x, Scalar4::one()])
+                .unwrap();
+            assert!(Tensor4::scalar_eq(&g.to_tensor4(), &t));
+        }
+    }
 }
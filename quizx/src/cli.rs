@@ -1,6 +1,13 @@
 //! The QuiZX command line interface.
 
 use clap::{crate_version, Parser};
+use std::fs;
+use std::path::Path;
+
+use crate::circuit::{Circuit, QasmOptions};
+use crate::decompose::CheckpointError;
+use crate::hamiltonian::HamiltonianError;
+use crate::routing::RoutingError;
 
 pub mod opt;
 pub mod sim;
@@ -13,7 +20,9 @@ pub enum Cli {
     /// Run the circuit optimizer.
     Opt(opt::OptArgs),
     /// Run the circuit simulator.
-    Sim(sim::SimArgs),
+    Sim(Box<sim::SimArgs>),
+    /// Compute tasks of a distributed simulation.
+    SimWorker(sim::SimWorkerArgs),
 }
 
 /// Error type for the CLI.
@@ -25,9 +34,30 @@ pub enum CliError {
     /// Error parsing a QASM file.
     #[display("Error parsing input circuit: {_0}")]
     CircuitParse(String),
+    /// Error routing a circuit, or reading a coupling map.
+    #[display("Error routing circuit: {_0}")]
+    Routing(RoutingError),
     /// Provided bit/Pauli string has the wrong length
     #[display("Circuit has {_0} qubits, but the provided {_2} string has length {_1}")]
     StringWrongLen(usize, usize, String),
+    /// Error saving or loading a decomposition checkpoint.
+    #[display("Error with checkpoint: {_0}")]
+    Checkpoint(CheckpointError),
+    /// Error reading a Hamiltonian file.
+    #[display("Error reading Hamiltonian: {_0}")]
+    Hamiltonian(HamiltonianError),
+    /// The circuit can't be converted to a ZX-diagram.
+    #[display("Unsupported circuit: {_0}")]
+    #[from(skip)]
+    UnsupportedCircuit(String),
+    /// The given combination of arguments is not supported.
+    #[display("Invalid arguments: {_0}")]
+    #[from(skip)]
+    InvalidArgs(String),
+    /// A worker process of a distributed simulation failed.
+    #[display("Worker process failed: {_0}")]
+    #[from(skip)]
+    Worker(String),
 }
 
 impl Cli {
@@ -35,6 +65,39 @@ impl Cli {
         match self {
             Cli::Opt(args) => args.run(),
             Cli::Sim(args) => args.run(),
+            Cli::SimWorker(args) => args.run(),
         }
     }
 }
+
+/// Read a circuit from a QASM file, using the OpenQASM 3 reader if the file
+/// declares version 3 in its header.
+///
+/// Angles that are not rational multiples of pi are rounded, with a warning, unless
+/// `exact_angles` is set, in which case they are an error.
+pub fn read_circuit(path: &Path, exact_angles: bool) -> Result<Circuit, CliError> {
+    let options = QasmOptions {
+        exact: exact_angles,
+        ..Default::default()
+    };
+    let qasm3_source = fs::read_to_string(path).ok().filter(|source| {
+        source
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with("//"))
+            .and_then(|l| l.strip_prefix("OPENQASM"))
+            .is_some_and(|v| v.trim_start().starts_with('3'))
+    });
+    let import = if let Some(source) = qasm3_source {
+        Circuit::from_qasm3_with_options(&source, &options)
+            .map_err(|e| CliError::CircuitParse(e.to_string()))?
+    } else {
+        Circuit::from_file_with_options(path.to_str().unwrap(), &options)?
+    };
+
+    let rounded = import.rounded_angles().count();
+    if rounded > 0 {
+        eprintln!("Warning: rounded {rounded} angle(s) that are not rational multiples of pi");
+    }
+    Ok(import.circuit)
+}
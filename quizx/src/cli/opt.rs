@@ -2,21 +2,25 @@
 
 use clap::{Args, Parser};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::circuit::Circuit;
 use crate::extract::ToCircuit;
+use crate::routing::{CouplingMap, Router};
 use crate::simplify;
 use crate::vec_graph::Graph;
 
-use super::CliError;
+use super::{read_circuit, CliError};
 
 /// Run the circuit optimizer.
 #[derive(Parser, Debug)]
 pub struct OptArgs {
-    /// QASM file to optimize.
+    /// QASM file to optimize (OpenQASM 2 or 3).
     input: PathBuf,
 
+    /// Fail on angles that are not rational multiples of pi, instead of rounding them.
+    #[arg(long)]
+    exact_angles: bool,
+
     /// Output to a file instead of printing the result.
     #[arg(long, short)]
     out: Option<PathBuf>,
@@ -24,18 +28,94 @@ pub struct OptArgs {
     /// Switch to select the optimization method. Defaults to `--full`.
     #[command(flatten)]
     method: Option<OptMethod>,
+
+    /// Skip the gate-level cleanup pass on the extracted circuit.
+    #[arg(long)]
+    no_basic_opt: bool,
+
+    /// Reduce the T-count further by optimizing the phase polynomials of CNOT-phase
+    /// regions of the extracted circuit.
+    #[arg(long)]
+    phase_poly: bool,
+
+    /// Write the result as OpenQASM 3 instead of OpenQASM 2.
+    #[arg(long)]
+    qasm3: bool,
+
+    /// Route the result for a device with the given coupling map.
+    ///
+    /// This is either a file or an inline list of edges (`0-1,1-2,...` or a JSON list
+    /// of pairs), or one of `line:N`, `ring:N`, `grid:RxC`, `heavy-hex:RxC` and
+    /// `all:N`. The circuit is extracted using CNOTs between neighbouring qubits where
+    /// possible, and any gates left over are routed with SWAPs. SWAPs are added at the
+    /// end to return every qubit to where it started.
+    #[arg(long, value_name = "MAP")]
+    coupling_map: Option<String>,
+
+    /// When routing, use bridge gates for CNOTs between qubits at distance 2.
+    #[arg(long, requires = "coupling_map")]
+    bridges: bool,
 }
 
 impl OptArgs {
     /// Run the `opt` command using the provided arguments.
     pub fn run(self) -> Result<(), CliError> {
-        let circ = Circuit::from_file(self.input.to_str().unwrap())?;
-        let mut g = circ.to_graph();
-        self.method.unwrap_or_default().simp(&mut g);
-        let qasm = g
-            .to_circuit()
-            .expect("Extraction should succeed since we start from a circuit")
-            .to_qasm();
+        let circ = read_circuit(&self.input, self.exact_angles)?;
+        circ.check_graph_conditions()
+            .map_err(CliError::UnsupportedCircuit)?;
+        let map = self
+            .coupling_map
+            .as_deref()
+            .map(read_coupling_map)
+            .transpose()?;
+        let method = self.method.unwrap_or_default();
+        // extraction can't reproduce measurements or classically controlled gates, but
+        // phase teleportation keeps the gates of the circuit
+        let teleport = method.teleport || circ.is_dynamic();
+        if teleport && !method.teleport {
+            eprintln!(
+                "Warning: using --teleport, as the circuit has measurements or classically \
+                 controlled gates"
+            );
+        }
+        let mut circ = if teleport {
+            circ.to_teleport_reduced()
+        } else {
+            let mut g = circ.to_graph();
+            method.simp(&mut g);
+            let mut extractor = g.extractor();
+            // extract with CNOTs between neighbours on the device, when the circuit fits
+            if let Some(map) = map.as_ref().filter(|m| m.num_qubits() >= circ.num_qubits()) {
+                extractor.gflow_steiner_gauss(map.clone());
+            }
+            extractor
+                .extract()
+                .expect("Extraction should succeed since we start from a circuit")
+        };
+        if self.phase_poly {
+            circ.phase_poly_optimize();
+        }
+        if !self.no_basic_opt {
+            circ.optimize();
+        }
+        if let Some(map) = &map {
+            let mut router = Router::new(map);
+            router.restore_layout();
+            if self.bridges {
+                router.bridges();
+            }
+            circ = router.route(&circ)?.circuit;
+            // the optimizer only ever merges gates on the same qubits, so this keeps
+            // the circuit routed
+            if !self.no_basic_opt {
+                circ.optimize();
+            }
+        }
+        let qasm = if self.qasm3 {
+            circ.to_qasm3()
+        } else {
+            circ.to_qasm()
+        };
         if let Some(out_path) = self.out {
             fs::write(out_path, qasm)?;
         } else {
@@ -45,6 +125,17 @@ impl OptArgs {
     }
 }
 
+/// Read a coupling map from a file, or parse it from `spec` if there is no such file.
+fn read_coupling_map(spec: &str) -> Result<CouplingMap, CliError> {
+    let path = Path::new(spec);
+    let map = if path.is_file() {
+        fs::read_to_string(path)?.parse()?
+    } else {
+        spec.parse()?
+    };
+    Ok(map)
+}
+
 /// Optimization method.
 #[derive(Args, Debug)]
 #[group(multiple = false)]
@@ -63,6 +154,11 @@ pub struct OptMethod {
     /// Optimize using the `clifford_simp` method.
     #[arg(long)]
     clifford: bool,
+
+    /// Reduce the T-count with `full_simp`, then write the reduced phases back into
+    /// the original circuit instead of extracting a new one.
+    #[arg(long)]
+    teleport: bool,
 }
 
 impl Default for OptMethod {
@@ -71,6 +167,7 @@ impl Default for OptMethod {
             full: true,
             flow: false,
             clifford: false,
+            teleport: false,
         }
     }
 }
@@ -1,29 +1,45 @@
 //! The `sim` CLI subcommand.
 
-use clap::{Args, Parser};
+use clap::{Args, Parser, ValueEnum};
 use itertools::Itertools;
-use num::rational::Ratio;
 // use num::Zero;
+use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
 
 use crate::circuit::Circuit;
-use crate::decompose::{BssTOnlyDriver, BssWithCatsDriver, Decomposer, Driver};
-use crate::graph::{BasisElem, GraphLike, VType};
+use crate::decompose::{
+    release_unfinished_tasks, BssTOnlyDriver, BssWithCatsDriver, Decomposer, Driver,
+};
+use crate::gate::GType;
+use crate::graph::{BasisElem, GraphLike};
+use crate::hamiltonian::{expval_graph, Hamiltonian};
+use crate::marginals::{density_entry_graph, reduced_density_graph};
+use crate::noise::{NoiseModel, PauliChannel, PauliError};
+use crate::pauli::PauliString;
 use crate::scalar::Scalar4;
 use crate::simplify;
 use crate::vec_graph::Graph;
 
-use super::CliError;
+use super::{read_circuit, CliError};
 
 /// Run the circuit simulator.
 #[derive(Parser, Debug)]
 pub struct SimArgs {
-    /// QASM file to simulate.
+    /// QASM file to simulate (OpenQASM 2 or 3).
     input: PathBuf,
 
+    /// Fail on angles that are not rational multiples of pi, instead of rounding them.
+    #[arg(long)]
+    exact_angles: bool,
+
     /// Output to a file instead of printing the results.
     #[arg(long, short)]
     out: Option<PathBuf>,
@@ -39,19 +55,162 @@ pub struct SimArgs {
     /// Distribute computation across available CPU cores up to the given depth.
     #[arg(long, short)]
     parallel: Option<usize>,
+
+    /// Reuse the scalars of isomorphic graphs met during the decomposition. This can greatly
+    /// reduce the number of terms for highly symmetric circuits.
+    #[arg(long)]
+    memoize: bool,
+
+    /// Print the sampled bit strings as a histogram of counts in the given format, instead of
+    /// one shot per line.
+    #[arg(long, value_enum, conflicts_with_all = ["bit_string", "pauli_string"])]
+    counts: Option<CountsFormat>,
+
+    /// Add Pauli noise, given as `[GATE:]KIND:P` where KIND is one of `depolarizing`,
+    /// `bit-flip` and `phase-flip`, and P is the probability of an error. The noise is applied
+    /// after every gate named GATE (e.g. `cx`), or after every gate if GATE is omitted. Use
+    /// `measure` as GATE for errors before the final measurements. Can be given several times.
+    /// Noisy circuits are simulated by sampling Pauli errors, so this is not supported when
+    /// computing an amplitude.
+    #[arg(
+        long,
+        value_parser = parse_noise,
+        conflicts_with_all = ["bit_string", "checkpoint", "distribute"]
+    )]
+    noise: Vec<NoiseSpec>,
+
+    /// Number of samples of the Pauli errors used to estimate a noisy expectation value.
+    #[arg(long, default_value_t = 1000, requires = "noise")]
+    trajectories: usize,
+
+    /// Periodically save the state of the decomposition to this file. Only supported when
+    /// computing an amplitude or an expectation value.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Number of seconds between checkpoints.
+    #[arg(long, default_value_t = 60, requires = "checkpoint")]
+    checkpoint_interval: u64,
+
+    /// Resume the decomposition from the file given by `--checkpoint`, or from the tasks in
+    /// the directory given by `--distribute`. The circuit and task must be the same as in the
+    /// run that wrote them.
+    #[arg(long)]
+    resume: bool,
+
+    /// Split the decomposition into tasks in this directory, and compute them with local
+    /// `sim-worker` processes. Only supported when computing an amplitude or an expectation
+    /// value.
+    #[arg(long, conflicts_with_all = ["checkpoint", "parallel"])]
+    distribute: Option<PathBuf>,
+
+    /// Depth to which the decomposition is expanded before splitting it into tasks.
+    #[arg(long, default_value_t = 4, requires = "distribute")]
+    distribute_depth: i64,
+
+    /// Number of worker processes. Defaults to the number of available CPU cores.
+    #[arg(long, requires = "distribute")]
+    workers: Option<NonZeroUsize>,
+}
+
+/// Output formats for histograms of samples.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CountsFormat {
+    /// A JSON object mapping bit strings to counts.
+    Json,
+    /// Comma-separated bit strings and counts, with a header.
+    Csv,
+}
+
+/// Noise to simulate, see [`NoiseModel`].
+#[derive(Clone, Copy)]
+pub struct Noise<'a> {
+    model: &'a NoiseModel,
+    trajectories: usize,
+}
+
+/// How the decomposition of a graph is run.
+pub struct RunOptions<'a> {
+    parallel: Option<usize>,
+    resume: bool,
+    checkpoint: Option<&'a Path>,
+    distribute: Option<Distribute<'a>>,
+}
+
+/// Settings for running the decomposition in worker processes.
+pub struct Distribute<'a> {
+    dir: &'a Path,
+    depth: i64,
+    workers: usize,
+    worker_args: Vec<&'static str>,
 }
 
 impl SimArgs {
     /// Run the `sim` command using the provided arguments.
     pub fn run(self) -> Result<(), CliError> {
-        let circ = Circuit::from_file(self.input.to_str().unwrap())?;
-        let (mut d, use_cats) = self.method.unwrap_or_default().build_decomposer();
+        let circ = read_circuit(&self.input, self.exact_angles)?;
+        circ.check_graph_conditions()
+            .map_err(CliError::UnsupportedCircuit)?;
+        let method = self.method.unwrap_or_default();
+        let (mut d, use_cats) = method.build_decomposer();
+        d.with_memoize(self.memoize);
+        let task = self.task.unwrap_or_default();
+        if let Some(path) = &self.checkpoint {
+            if !task.is_single_scalar() {
+                return Err(CliError::InvalidArgs(
+                    "checkpoints are not supported when sampling, computing marginals or \
+                     evaluating a Hamiltonian"
+                        .to_string(),
+                ));
+            }
+            d.with_checkpoint(path, Duration::from_secs(self.checkpoint_interval));
+        } else if self.distribute.is_some() {
+            if !task.is_single_scalar() {
+                return Err(CliError::InvalidArgs(
+                    "distributed decomposition is not supported when sampling, computing \
+                     marginals or evaluating a Hamiltonian"
+                        .to_string(),
+                ));
+            }
+        } else if self.resume {
+            return Err(CliError::InvalidArgs(
+                "--resume requires --checkpoint or --distribute".to_string(),
+            ));
+        }
+        let mut noise = NoiseModel::new();
+        for spec in &self.noise {
+            match spec.target {
+                NoiseTarget::All => noise.with_default(spec.channel),
+                NoiseTarget::Gate(t) => noise.with_gate(t, spec.channel),
+                NoiseTarget::Measure => noise.with_measurement(spec.channel),
+            };
+        }
+        let noise = (!noise.is_noiseless()).then_some(Noise {
+            model: &noise,
+            trajectories: self.trajectories,
+        });
+        let mut worker_args = method.to_args();
+        if self.memoize {
+            worker_args.push("--memoize");
+        }
+        let opts = RunOptions {
+            parallel: self.parallel,
+            resume: self.resume,
+            checkpoint: self.checkpoint.as_deref(),
+            distribute: self.distribute.as_deref().map(|dir| Distribute {
+                dir,
+                depth: self.distribute_depth,
+                workers: self
+                    .workers
+                    .or_else(|| thread::available_parallelism().ok())
+                    .map_or(1, NonZeroUsize::get),
+                worker_args,
+            }),
+        };
+
         if use_cats {
             let driver = BssWithCatsDriver { random_t: false };
-            let result =
-                self.task
-                    .unwrap_or_default()
-                    .run(&circ, &mut d, &driver, self.parallel)?;
+            let result = task.run(&circ, &mut d, &driver, &opts, self.counts, noise)?;
 
             if let Some(out_path) = self.out {
                 fs::write(out_path, result)?;
@@ -61,10 +220,7 @@ impl SimArgs {
             Ok(())
         } else {
             let driver = BssTOnlyDriver { random_t: false };
-            let result =
-                self.task
-                    .unwrap_or_default()
-                    .run(&circ, &mut d, &driver, self.parallel)?;
+            let result = task.run(&circ, &mut d, &driver, &opts, self.counts, noise)?;
 
             if let Some(out_path) = self.out {
                 fs::write(out_path, result)?;
@@ -90,6 +246,10 @@ pub struct SimMethod {
     /// Use the BSS decomposition strategy.
     #[arg(long)]
     bss: bool,
+
+    /// Use the cat state decomposition strategy, but contract components with small treewidth.
+    #[arg(long)]
+    hybrid: bool,
 }
 
 impl Default for SimMethod {
@@ -97,6 +257,7 @@ impl Default for SimMethod {
         SimMethod {
             bss: false,
             cats: true,
+            hybrid: false,
         }
     }
 }
@@ -104,8 +265,53 @@ impl Default for SimMethod {
 impl SimMethod {
     fn build_decomposer(&self) -> (Decomposer<Graph>, bool) {
         let mut decomposer = Decomposer::empty();
-        decomposer.with_full_simp();
-        (decomposer, self.cats)
+        decomposer.with_full_simp().with_hybrid(self.hybrid);
+        (decomposer, self.cats || self.hybrid)
+    }
+
+    /// The command line arguments selecting this method.
+    fn to_args(&self) -> Vec<&'static str> {
+        if self.bss {
+            vec!["--bss"]
+        } else if self.hybrid {
+            vec!["--hybrid"]
+        } else {
+            vec!["--cats"]
+        }
+    }
+}
+
+/// Compute the tasks written by `sim --distribute`.
+///
+/// Any number of workers can be run on the same directory, e.g. as jobs on a batch system
+/// with a shared file system. Each picks tasks that are not taken by another worker, until no
+/// tasks are left. Afterwards, `sim --distribute <DIR> --resume` combines the results.
+#[derive(Parser, Debug)]
+pub struct SimWorkerArgs {
+    /// Directory containing the tasks.
+    dir: PathBuf,
+
+    /// Switch to select the decomposition method. This should match the one used to write
+    /// the tasks. Defaults to `--cats`.
+    #[command(flatten)]
+    method: Option<SimMethod>,
+
+    /// Reuse the scalars of isomorphic graphs met during the decomposition.
+    #[arg(long)]
+    memoize: bool,
+}
+
+impl SimWorkerArgs {
+    /// Run the `sim-worker` command using the provided arguments.
+    pub fn run(self) -> Result<(), CliError> {
+        let (mut d, use_cats) = self.method.unwrap_or_default().build_decomposer();
+        d.with_memoize(self.memoize);
+        if use_cats {
+            d.run_tasks(&self.dir, &BssWithCatsDriver { random_t: false })?;
+        } else {
+            d.run_tasks(&self.dir, &BssTOnlyDriver { random_t: false })?;
+        }
+        Ok(())
     }
 }
 
@@ -127,6 +333,17 @@ pub struct SimTask {
     /// Compute an expectation value.
     #[arg(long = "expval", short = 'e', value_parser = parse_pauli_string)]
     pauli_string: Option<PauliString>,
+
+    /// Compute the probabilities of all outcomes of measuring the given qubits, e.g.
+    /// `q0,q3,q7`, with the other qubits traced out.
+    #[arg(long = "marginals", value_parser = parse_qubits)]
+    qubits: Option<Qubits>,
+
+    /// Compute the expectation value of a Hamiltonian, given as a file with a weighted
+    /// Pauli string on each line, e.g. `0.5 XZIY` or `-0.2 X0 Z3`, or as a JSON list of
+    /// terms like `{"coeff": 0.5, "pauli": "XZIY"}`.
+    #[arg(long)]
+    hamiltonian: Option<PathBuf>,
 }
 
 impl Default for SimTask {
@@ -135,41 +352,174 @@ impl Default for SimTask {
             shots: Some(1),
             bit_string: None,
             pauli_string: None,
+            qubits: None,
+            hamiltonian: None,
         }
     }
 }
 
 impl SimTask {
+    /// Returns true if the task computes a single scalar, so the decomposition can be
+    /// checkpointed or distributed.
+    fn is_single_scalar(&self) -> bool {
+        self.bit_string.is_some() || self.pauli_string.is_some()
+    }
+
     pub fn run(
         &self,
         circ: &Circuit,
         decomposer: &mut Decomposer<Graph>,
         driver: &impl Driver,
-        parallel: Option<usize>,
+        opts: &RunOptions,
+        counts_format: Option<CountsFormat>,
+        noise: Option<Noise>,
     ) -> Result<String, CliError> {
         if let Some(shots) = self.shots {
-            Ok((0..shots)
-                .map(|_| sample(circ, decomposer, driver, parallel))
-                .join("\n")
-                .to_string())
+            let counts = if let Some(noise) = noise {
+                let mut counts = BTreeMap::new();
+                for (c, n) in sample_noisy_circuits(circ, noise.model, shots) {
+                    for (bits, m) in sample(&c, decomposer, driver, n, opts.parallel) {
+                        *counts.entry(bits).or_default() += m;
+                    }
+                }
+                counts
+            } else {
+                sample(circ, decomposer, driver, shots, opts.parallel)
+            };
+            Ok(match counts_format {
+                None => {
+                    let mut samples = counts
+                        .into_iter()
+                        .flat_map(|(bits, n)| std::iter::repeat(bits).take(n))
+                        .collect_vec();
+                    samples.shuffle(&mut thread_rng());
+                    samples.join("\n")
+                }
+                Some(CountsFormat::Json) => serde_json::to_string(&counts).unwrap(),
+                Some(CountsFormat::Csv) => std::iter::once("bitstring,count".to_string())
+                    .chain(counts.iter().map(|(bits, n)| format!("{bits},{n}")))
+                    .join("\n"),
+            })
         } else if let Some(ref bit_str) = self.bit_string {
-            Ok(format!(
-                "{}",
-                amplitude(circ, decomposer, driver, bit_str, parallel)?
-            )
-            .to_string())
+            Ok(format!("{}", amplitude(circ, decomposer, driver, bit_str, opts)?).to_string())
         } else if let Some(ref pauli_str) = self.pauli_string {
-            Ok(format!(
-                "{}",
-                expectation_value(circ, decomposer, driver, pauli_str, parallel)?
-            )
-            .to_string())
+            let ev = if let Some(noise) = noise {
+                let mut ev = 0.0;
+                for (c, n) in sample_noisy_circuits(circ, noise.model, noise.trajectories) {
+                    ev += n as f64 * expectation_value(&c, decomposer, driver, pauli_str, opts)?;
+                }
+                ev / noise.trajectories as f64
+            } else {
+                expectation_value(circ, decomposer, driver, pauli_str, opts)?
+            };
+            Ok(format!("{ev}").to_string())
+        } else if let Some(ref qubits) = self.qubits {
+            let ps = if let Some(noise) = noise {
+                let mut ps = vec![0.0; 1 << qubits.len()];
+                for (c, n) in sample_noisy_circuits(circ, noise.model, noise.trajectories) {
+                    for (p, p1) in ps
+                        .iter_mut()
+                        .zip(marginals(&c, decomposer, driver, qubits, opts)?)
+                    {
+                        *p += n as f64 * p1;
+                    }
+                }
+                ps.iter().map(|p| p / noise.trajectories as f64).collect()
+            } else {
+                marginals(circ, decomposer, driver, qubits, opts)?
+            };
+            Ok(ps
+                .iter()
+                .enumerate()
+                .map(|(i, p)| format!("{:0width$b} {p}", i, width = qubits.len()))
+                .join("\n"))
+        } else if let Some(ref path) = self.hamiltonian {
+            let h = Hamiltonian::from_file(path)?;
+            if h.num_qubits() > circ.num_qubits() {
+                return Err(CliError::InvalidArgs(format!(
+                    "circuit has {} qubits, but the Hamiltonian acts on {}",
+                    circ.num_qubits(),
+                    h.num_qubits()
+                )));
+            }
+            let ev = if let Some(noise) = noise {
+                let mut ev = 0.0;
+                for (c, n) in sample_noisy_circuits(circ, noise.model, noise.trajectories) {
+                    ev += n as f64 * h.expval(&c, decomposer, driver);
+                }
+                ev / noise.trajectories as f64
+            } else {
+                h.expval(circ, decomposer, driver)
+            };
+            Ok(format!("{ev}"))
         } else {
             unreachable!()
         }
     }
 }
 
+/// Where a noise channel given on the command line is applied.
+#[derive(Clone, Copy, Debug)]
+enum NoiseTarget {
+    All,
+    Gate(GType),
+    Measure,
+}
+
+#[derive(Clone, Debug)]
+pub struct NoiseSpec {
+    target: NoiseTarget,
+    channel: PauliChannel,
+}
+
+#[derive(Debug, derive_more::Display)]
+#[display("'{_0}' is not a valid noise channel. Expected [GATE:]KIND:P, where KIND is one of 'depolarizing', 'bit-flip' and 'phase-flip', and P is a probability.")]
+struct NoiseParseError(String);
+
+impl Error for NoiseParseError {}
+
+fn parse_noise(s: &str) -> Result<NoiseSpec, NoiseParseError> {
+    let err = || NoiseParseError(s.to_string());
+    let parts = s.split(':').collect_vec();
+    let (target, kind, p) = match parts.as_slice() {
+        [kind, p] => (NoiseTarget::All, kind, p),
+        ["measure", kind, p] => (NoiseTarget::Measure, kind, p),
+        [gate, kind, p] => match GType::from_qasm_name(gate) {
+            GType::UnknownGate => return Err(err()),
+            t => (NoiseTarget::Gate(t), kind, p),
+        },
+        _ => return Err(err()),
+    };
+    let p: f64 = p.parse().map_err(|_| err())?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(err());
+    }
+    let channel = match *kind {
+        "depolarizing" => PauliChannel::depolarizing(p),
+        "bit-flip" => PauliChannel::bit_flip(p),
+        "phase-flip" => PauliChannel::phase_flip(p),
+        _ => return Err(err()),
+    };
+    Ok(NoiseSpec { target, channel })
+}
+
+/// Draws the Pauli errors of `runs` runs of a noisy circuit.
+///
+/// Returns each of the resulting circuits, with the number of runs that gave it.
+fn sample_noisy_circuits(circ: &Circuit, noise: &NoiseModel, runs: usize) -> Vec<(Circuit, usize)> {
+    let mut rng = thread_rng();
+    let mut errors: HashMap<Vec<PauliError>, usize> = HashMap::new();
+    for _ in 0..runs {
+        *errors
+            .entry(noise.sample_errors(circ, &mut rng))
+            .or_default() += 1;
+    }
+    errors
+        .into_iter()
+        .map(|(e, n)| (NoiseModel::apply_errors(circ, &e), n))
+        .collect()
+}
+
 // Need to wrap the vector into a type alias, otherwise clap tries to do some
 // varag parsing magic that breaks our custom parser below.
 type BitString = Vec<bool>;
@@ -190,60 +540,164 @@ fn parse_bit_string(s: &str) -> Result<BitString, BitStringParseError> {
         .collect()
 }
 
-#[derive(Clone, Copy, Debug)]
-enum Pauli {
-    I,
-    X,
-    Y,
-    Z,
+/// Parses a Pauli string, allowing lower case letters
+fn parse_pauli_string(s: &str) -> Result<PauliString, String> {
+    s.chars()
+        .map(|c| {
+            if "ixyz".contains(c) {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect::<String>()
+        .parse()
 }
 
 // Need to wrap the vector into a type alias, otherwise clap tries to do some
 // varag parsing magic that breaks our custom parser below.
-type PauliString = Vec<Pauli>;
+type Qubits = Vec<usize>;
 
 #[derive(Debug, derive_more::Display)]
-#[display("'{_0}' is not a Pauli. Expected one of 'I', 'X', 'Y', 'Z'.")]
-struct PauliStringParseError(char);
-
-impl Error for PauliStringParseError {}
-
-fn parse_pauli_string(s: &str) -> Result<PauliString, PauliStringParseError> {
-    s.chars()
-        .map(|c| match c.to_ascii_uppercase() {
-            'I' => Ok(Pauli::I),
-            'X' => Ok(Pauli::X),
-            'Y' => Ok(Pauli::Y),
-            'Z' => Ok(Pauli::Z),
-            _ => Err(PauliStringParseError(c)),
+#[display("'{_0}' is not a valid list of qubits. Expected a comma-separated list like 'q0,q3,q7'.")]
+struct QubitsParseError(String);
+
+impl Error for QubitsParseError {}
+
+fn parse_qubits(s: &str) -> Result<Qubits, QubitsParseError> {
+    s.split(',')
+        .map(|q| {
+            let q = q.trim();
+            q.strip_prefix('q')
+                .unwrap_or(q)
+                .parse()
+                .map_err(|_| QubitsParseError(s.to_string()))
         })
         .collect()
 }
 
 /// Sample from a circuit by computing marginals via doubling of the diagram.
+///
+/// Qubits are sampled one at a time, conditioned on the values of the previous qubits. Rather
+/// than sampling each shot separately, the shots are split between the two values of each
+/// qubit, so the marginal of each prefix of the sampled bit strings is only computed once. The
+/// branches of this prefix tree are computed in parallel.
+///
+/// Returns the number of times each bit string was sampled.
 fn sample(
     circ: &Circuit,
     decomposer: &mut Decomposer<Graph>,
     driver: &impl Driver,
+    shots: usize,
     parallel: Option<usize>,
-) -> String {
+) -> BTreeMap<String, usize> {
     let qs = circ.num_qubits();
-    let mut xs: Vec<bool> = vec![];
-    let mut rng = thread_rng();
-    for _ in 0..qs {
-        let mut g: Graph = circ.to_graph();
-        g.plug_inputs(&vec![BasisElem::Z0; qs]);
-        for x in &xs {
+    let mut g: Graph = circ.to_graph();
+    g.plug_inputs(&vec![BasisElem::Z0; qs]);
+    let sampler = Sampler {
+        g,
+        driver,
+        parallel,
+    };
+    sampler
+        .sample(decomposer.clone(), vec![], 1.0, shots)
+        .into_iter()
+        .map(|(xs, n)| (xs.iter().map(|x| if *x { '1' } else { '0' }).collect(), n))
+        .collect()
+}
+
+/// Samples the outputs of a state, see [`sample`].
+struct Sampler<'a, D: Driver> {
+    g: Graph,
+    driver: &'a D,
+    parallel: Option<usize>,
+}
+
+impl<D: Driver> Sampler<'_, D> {
+    /// Computes the probability of measuring the first outputs as `xs`.
+    fn marginal(&self, decomposer: &mut Decomposer<Graph>, xs: &[bool]) -> f64 {
+        let mut g = self.g.clone();
+        for x in xs {
             // Plug removes the output, so we have to keep using index 0
             g.plug_output(0, if *x { BasisElem::Z1 } else { BasisElem::Z0 });
         }
-        g.plug_output(0, BasisElem::Z1);
         g.plug(&g.to_adjoint());
+        let opts = RunOptions {
+            parallel: self.parallel,
+            resume: false,
+            checkpoint: None,
+            distribute: None,
+        };
+        let scalar = decomp_graph(g, decomposer, self.driver, &opts).unwrap();
+        scalar.complex_value().re
+    }
 
-        let scalar = decomp_graph(g, decomposer, driver, parallel);
-        xs.push(rng.gen_bool(scalar.complex_value().re));
+    /// Samples `shots` bit strings starting with `xs`, where `p` is the marginal of `xs`.
+    fn sample(
+        &self,
+        mut decomposer: Decomposer<Graph>,
+        mut xs: Vec<bool>,
+        p: f64,
+        shots: usize,
+    ) -> Vec<(Vec<bool>, usize)> {
+        if shots == 0 {
+            return vec![];
+        } else if xs.len() == self.g.outputs().len() {
+            return vec![(xs, shots)];
+        }
+        xs.push(true);
+        let p1 = self.marginal(&mut decomposer, &xs);
+        let cond = if p > 0.0 {
+            (p1 / p).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        let mut rng = thread_rng();
+        let shots1 = (0..shots).filter(|_| rng.gen_bool(cond)).count();
+        let mut xs0 = xs.clone();
+        *xs0.last_mut().unwrap() = false;
+
+        let (mut samples, samples1) = rayon::join(
+            || self.sample(decomposer.clone(), xs0, p - p1, shots - shots1),
+            || self.sample(decomposer.clone(), xs, p1, shots1),
+        );
+        samples.extend(samples1);
+        samples
     }
-    xs.iter().map(|x| if *x { '1' } else { '0' }).join("")
+}
+
+/// Compute the probabilities of all outcomes of measuring the given qubits.
+///
+/// The outcomes are ordered as binary numbers, with the first qubit as the most significant
+/// bit.
+fn marginals(
+    circ: &Circuit,
+    decomposer: &mut Decomposer<Graph>,
+    driver: &impl Driver,
+    qubits: &Qubits,
+    opts: &RunOptions,
+) -> Result<Vec<f64>, CliError> {
+    let qs = circ.num_qubits();
+    if let Some(&q) = qubits.iter().find(|&&q| q >= qs) {
+        return Err(CliError::InvalidArgs(format!(
+            "circuit has {qs} qubits, but qubit {q} was given"
+        )));
+    }
+    if !qubits.iter().all_unique() {
+        return Err(CliError::InvalidArgs("qubits must be distinct".to_string()));
+    }
+
+    let rho: Graph = reduced_density_graph(circ, qubits);
+    let k = qubits.len();
+    (0..1 << k)
+        .map(|i| {
+            let bits = (0..k).rev().map(|j| i >> j & 1 == 1).collect_vec();
+            let g = density_entry_graph(&rho, &bits, &bits);
+            Ok(decomp_graph(g, decomposer, driver, opts)?
+                .complex_value()
+                .re)
+        })
+        .collect()
 }
 
 /// Compute an amplitude.
@@ -252,7 +706,7 @@ fn amplitude(
     decomposer: &mut Decomposer<Graph>,
     driver: &impl Driver,
     bit_str: &BitString,
-    parallel: Option<usize>,
+    opts: &RunOptions,
 ) -> Result<f64, CliError> {
     let qs = circ.num_qubits();
     let bit_str = match bit_str.as_slice() {
@@ -276,81 +730,106 @@ fn amplitude(
             .collect_vec(),
     );
 
-    let scalar = decomp_graph(g, decomposer, driver, parallel);
+    let scalar = decomp_graph(g, decomposer, driver, opts)?;
     let amp = scalar * scalar.conj();
     Ok(amp.complex_value().re)
 }
 
 /// Computes an expectation value by doubling the diagram.
+///
+/// For Clifford circuits, this pushes the Pauli through the circuit instead.
 fn expectation_value(
     circ: &Circuit,
     decomposer: &mut Decomposer<Graph>,
     driver: &impl Driver,
     pauli_str: &PauliString,
-    parallel: Option<usize>,
+    opts: &RunOptions,
 ) -> Result<f64, CliError> {
     let qs = circ.num_qubits();
-    let pauli_str = match pauli_str.as_slice() {
-        [p] => &vec![*p; qs],
-        ps if ps.len() == qs => pauli_str,
-        _ => {
-            return Err(CliError::StringWrongLen(
-                qs,
-                pauli_str.len(),
-                "Pauli".to_string(),
-            ))
-        }
-    };
-
-    let mut g: Graph = circ.to_graph();
-    g.plug_inputs(&vec![BasisElem::Z0; qs]);
-    let g_adj = g.to_adjoint();
-    for (i, p) in pauli_str.iter().enumerate() {
-        let b = g.outputs()[i];
-        let [(v, _)] = g.incident_edge_vec(b).try_into().unwrap();
-        match p {
-            Pauli::I => {}
-            Pauli::X => {
-                let x = g.add_vertex_with_phase(VType::X, 1);
-                g.remove_edge(v, b);
-                g.add_edge(v, x);
-                g.add_edge(x, b);
-            }
-            Pauli::Y => {
-                let x = g.add_vertex_with_phase(VType::X, 1);
-                let z = g.add_vertex_with_phase(VType::Z, 1);
-                g.remove_edge(v, b);
-                g.add_edge(v, z);
-                g.add_edge(z, x);
-                g.add_edge(x, b);
-                g.scalar_mut().mul_phase(Ratio::new(1, 2));
-            }
-            Pauli::Z => {
-                let z = g.add_vertex_with_phase(VType::Z, 1);
-                g.remove_edge(v, b);
-                g.add_edge(v, z);
-                g.add_edge(z, b);
+    let p = match pauli_str.num_qubits() {
+        // a single Pauli is applied to every qubit
+        1 => {
+            let mut p = PauliString::identity(qs);
+            p.mul_phase(pauli_str.sign());
+            for q in 0..qs {
+                p.set(q, pauli_str.get(0));
             }
+            p
         }
+        n if n == qs => pauli_str.clone(),
+        n => return Err(CliError::StringWrongLen(qs, n, "Pauli".to_string())),
+    };
+    if let Some(p) = circ.to_adjoint().conjugate_pauli(&p) {
+        return Ok(p.zero_state_expval().complex_value().re);
     }
-    g.plug(&g_adj);
 
-    let scalar = decomp_graph(g, decomposer, driver, parallel);
+    let mut state: Graph = circ.to_graph();
+    state.plug_inputs(&vec![BasisElem::Z0; qs]);
+    let g = expval_graph(&state, &p);
+
+    let scalar = decomp_graph(g, decomposer, driver, opts)?;
     Ok(scalar.complex_value().re)
 }
 
-/// Run the provided decomposer on a graph.
+/// Run the provided decomposer on a graph, or continue from a checkpoint if `resume` is set.
 fn decomp_graph(
     mut g: Graph,
     decomposer: &mut Decomposer<Graph>,
     driver: &impl Driver,
-    parallel: Option<usize>,
-) -> Scalar4 {
-    simplify::full_simp(&mut g);
-    decomposer.set_target(g);
-    if let Some(_depth) = parallel {
-        decomposer.decompose_parallel(driver).scalar()
+    opts: &RunOptions,
+) -> Result<Scalar4, CliError> {
+    if let Some(dist) = &opts.distribute {
+        return decomp_graph_distributed(g, decomposer, driver, dist, opts.resume);
+    }
+    if let Some(path) = opts.checkpoint.filter(|_| opts.resume) {
+        decomposer.load_checkpoint(path)?;
     } else {
-        decomposer.decompose(driver).scalar()
+        simplify::full_simp(&mut g);
+        decomposer.set_target(g);
+    }
+    if let Some(_depth) = opts.parallel {
+        Ok(decomposer.decompose_parallel(driver).scalar())
+    } else {
+        Ok(decomposer.decompose(driver).scalar())
+    }
+}
+
+/// Split the decomposition of a graph into tasks, compute them in worker processes and
+/// combine the results.
+///
+/// If `resume` is set, the tasks already in the directory are used instead, and only those
+/// without a result are computed.
+fn decomp_graph_distributed(
+    mut g: Graph,
+    decomposer: &mut Decomposer<Graph>,
+    driver: &impl Driver,
+    dist: &Distribute,
+    resume: bool,
+) -> Result<Scalar4, CliError> {
+    if resume {
+        // tasks claimed by workers of an earlier run will not be finished by them
+        release_unfinished_tasks(dist.dir)?;
+    } else {
+        simplify::full_simp(&mut g);
+        decomposer.set_target(g);
+        decomposer.write_tasks(dist.dir, dist.depth, driver)?;
+    }
+
+    let exe = std::env::current_exe()?;
+    let workers = (0..dist.workers)
+        .map(|_| {
+            Command::new(&exe)
+                .arg("sim-worker")
+                .arg(dist.dir)
+                .args(&dist.worker_args)
+                .spawn()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for mut worker in workers {
+        let status = worker.wait()?;
+        if !status.success() {
+            return Err(CliError::Worker(status.to_string()));
+        }
     }
+    Ok(decomposer.reduce_tasks(dist.dir)?.scalar())
 }
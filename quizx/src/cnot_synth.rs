@@ -0,0 +1,519 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Synthesis of CNOT circuits from parity matrices
+//!
+//! [`Mat2::gauss_x`] produces CNOTs between arbitrary pairs of qubits. The methods
+//! here only ever add together rows that are neighbours in a [`CouplingMap`], where
+//! row `i` is qubit `i`. To eliminate a column, the rows involved are joined up by
+//! a Steiner tree in the coupling map, and the elimination runs along its edges, as
+//! in Kissinger and Meijer-van de Griend, "CNOT circuit extraction for
+//! topologically-constrained quantum memories" (2019).
+//!
+//! Rows are eliminated in an order where the rows that are left stay connected, so
+//! later steps never need to touch rows that are done. [`Mat2::rowcol`] eliminates
+//! the row and column of one qubit at a time, which reduces an invertible matrix
+//! to the identity, whereas [`Mat2::steiner_gauss`] works through the columns in
+//! order and works for any matrix.
+
+use crate::circuit::Circuit;
+use crate::linalg::{Mat2, RowOps};
+use crate::routing::CouplingMap;
+use std::collections::VecDeque;
+
+impl Mat2 {
+    /// Gaussian elimination using only row operations between neighbours in `map`
+    ///
+    /// Row `i` of the matrix is qubit `i` of `map`, so the matrix can't have more rows
+    /// than `map` has qubits. The row operations are also applied to `x`, as in
+    /// [`Mat2::gauss_x`].
+    ///
+    /// Each connected component of `map` (restricted to the rows of the matrix) is
+    /// reduced separately. If `full_reduce` is set, the rows of each component are
+    /// in reduced echelon form, up to the order of the rows, otherwise they are in
+    /// echelon form up to the order of the rows. Returns the rank of the matrix, if
+    /// `map` is connected.
+    pub fn steiner_gauss(
+        &mut self,
+        map: &CouplingMap,
+        full_reduce: bool,
+        x: &mut impl RowOps,
+    ) -> usize {
+        let rows = self.num_rows();
+        assert!(
+            rows <= map.num_qubits(),
+            "matrix has {rows} rows, but the coupling map only has {} qubits",
+            map.num_qubits()
+        );
+
+        let mut rank = 0;
+        for comp in components(map, rows) {
+            let mut remaining = vec![false; rows];
+            comp.iter().for_each(|&r| remaining[r] = true);
+
+            for col in 0..self.num_cols() {
+                let ones: Vec<_> = comp
+                    .iter()
+                    .copied()
+                    .filter(|&r| remaining[r] && self[r][col] == 1)
+                    .collect();
+                if ones.is_empty() {
+                    continue;
+                }
+
+                // prefer the pivot on the diagonal, then a row that already has a 1
+                let diagonal = (col < rows && remaining[col]).then_some(col);
+                let candidates: Vec<_> = diagonal
+                    .into_iter()
+                    .chain(ones)
+                    .chain(comp.iter().copied().filter(|&r| remaining[r]))
+                    .collect();
+                let root = non_cut_vertex(map, &mut remaining, candidates);
+
+                self.eliminate_col(map, &remaining, root, col, x);
+                if full_reduce {
+                    self.eliminate_row(map, &remaining, root, x);
+                }
+                remaining[root] = false;
+                rank += 1;
+            }
+        }
+        rank
+    }
+
+    /// Reduce an invertible matrix to the identity, using only row operations between
+    /// neighbours in `map`
+    ///
+    /// The matrix must be square, and row `i` is qubit `i` of `map`. At each step, this
+    /// picks a qubit whose removal leaves the remaining ones connected, makes its
+    /// column a unit vector, then makes its row a unit vector by adding rows that are
+    /// left. The row operations are also applied to `x`, as in [`Mat2::gauss_x`].
+    ///
+    /// Returns the number of qubits eliminated. This is the number of rows unless the
+    /// matrix is singular or `map` (restricted to the rows) is disconnected, in which
+    /// case the matrix is only partly reduced.
+    pub fn rowcol(&mut self, map: &CouplingMap, x: &mut impl RowOps) -> usize {
+        let rows = self.num_rows();
+        assert_eq!(rows, self.num_cols(), "matrix must be square");
+        assert!(
+            rows <= map.num_qubits(),
+            "matrix has {rows} rows, but the coupling map only has {} qubits",
+            map.num_qubits()
+        );
+
+        let mut eliminated = 0;
+        for comp in components(map, rows) {
+            let mut remaining = vec![false; rows];
+            comp.iter().for_each(|&r| remaining[r] = true);
+
+            for _ in 0..comp.len() {
+                let candidates: Vec<_> = comp.iter().copied().filter(|&r| remaining[r]).collect();
+                let v = non_cut_vertex(map, &mut remaining, candidates);
+                if !comp.iter().any(|&r| remaining[r] && self[r][v] == 1) {
+                    break;
+                }
+
+                self.eliminate_col(map, &remaining, v, v, x);
+                self.eliminate_row(map, &remaining, v, x);
+                remaining[v] = false;
+                eliminated += 1;
+            }
+        }
+        eliminated
+    }
+
+    /// Synthesizes a CNOT circuit for an invertible parity matrix, with every CNOT
+    /// acting on neighbouring qubits in `map`
+    ///
+    /// The circuit takes each basis state |b> to |M b>. This uses
+    /// [`Mat2::steiner_gauss`], followed by [`Mat2::rowcol`] to undo the permutation of
+    /// rows that this leaves. Returns `None` if the matrix isn't square and invertible,
+    /// or `map` isn't connected on the first qubits.
+    pub fn steiner_cnots(&self, map: &CouplingMap) -> Option<Circuit> {
+        self.synth_cnots(map, |m, c| {
+            m.steiner_gauss(map, true, c);
+            m.rowcol(map, c);
+        })
+    }
+
+    /// Synthesizes a CNOT circuit for an invertible parity matrix using
+    /// [`Mat2::rowcol`], with every CNOT acting on neighbouring qubits in `map`
+    ///
+    /// The circuit takes each basis state |b> to |M b>. Returns `None` if the matrix
+    /// isn't square and invertible, or `map` isn't connected on the first qubits.
+    pub fn rowcol_cnots(&self, map: &CouplingMap) -> Option<Circuit> {
+        self.synth_cnots(map, |m, c| {
+            m.rowcol(map, c);
+        })
+    }
+
+    fn synth_cnots(
+        &self,
+        map: &CouplingMap,
+        reduce: impl FnOnce(&mut Mat2, &mut Circuit),
+    ) -> Option<Circuit> {
+        let n = self.num_rows();
+        if n != self.num_cols() || n > map.num_qubits() {
+            return None;
+        }
+
+        // The CNOT for a row operation E acts on basis states as E^T. So if E_1, ..., E_k
+        // reduce M^T to the identity, the circuit acts as E_k^T ... E_1^T, which is M
+        // since each E_i is its own inverse.
+        let mut m = self.transpose();
+        let mut c = Circuit::new(n);
+        reduce(&mut m, &mut c);
+        (m == Mat2::id(n)).then_some(c)
+    }
+
+    /// Make column `col` zero on every remaining row except `root`, which must be
+    /// remaining, where it becomes 1
+    ///
+    /// The rows with a 1 are joined to `root` by a Steiner tree. Steiner points are
+    /// filled with 1s from their children, then every row is cleared by adding its
+    /// parent, starting from the leaves.
+    fn eliminate_col(
+        &mut self,
+        map: &CouplingMap,
+        remaining: &[bool],
+        root: usize,
+        col: usize,
+        x: &mut impl RowOps,
+    ) {
+        let terminals: Vec<_> = (0..self.num_rows())
+            .filter(|&r| remaining[r] && r != root && self[r][col] == 1)
+            .collect();
+        let tree = steiner_tree(map, remaining, root, &terminals);
+
+        for &(p, c) in tree.iter().rev() {
+            if self[p][col] == 0 {
+                self.add_rows(c, p, x);
+            }
+        }
+        for &(p, c) in tree.iter().rev() {
+            self.add_rows(p, c, x);
+        }
+    }
+
+    /// Add remaining rows to `root`, which must be remaining, so that it has no 1s in the
+    /// pivot columns of the other remaining rows
+    ///
+    /// The other remaining rows must be 0 in the pivot column of `root`. The rows to
+    /// add are joined to `root` by a Steiner tree. Going down the tree, every Steiner
+    /// point is added to its parent, then going up, every row is added to its parent.
+    /// This adds every row to be added to `root` once, and every Steiner point twice.
+    fn eliminate_row(
+        &mut self,
+        map: &CouplingMap,
+        remaining: &[bool],
+        root: usize,
+        x: &mut impl RowOps,
+    ) {
+        let others: Vec<_> = (0..self.num_rows())
+            .filter(|&r| remaining[r] && r != root)
+            .collect();
+        if others.is_empty() {
+            return;
+        }
+
+        // reduce the root row against the others, keeping track of which rows we used
+        let mut m = Mat2::build(others.len(), self.num_cols(), |i, j| {
+            self[others[i]][j] == 1
+        });
+        let mut used = Mat2::id(others.len());
+        m.gauss_x(true, 1, &mut used);
+        let mut row = self[root].clone();
+        let mut sum = vec![0; others.len()];
+        for i in 0..m.num_rows() {
+            if let Some(p) = m[i].iter().position(|&a| a == 1) {
+                if row[p] == 1 {
+                    row.iter_mut().zip(&m[i]).for_each(|(a, b)| *a ^= b);
+                    sum.iter_mut().zip(&used[i]).for_each(|(a, b)| *a ^= b);
+                }
+            }
+        }
+        let terminals: Vec<_> = (0..others.len())
+            .filter(|&i| sum[i] == 1)
+            .map(|i| others[i])
+            .collect();
+
+        let tree = steiner_tree(map, remaining, root, &terminals);
+        for &(p, c) in &tree {
+            if !terminals.contains(&c) {
+                self.add_rows(c, p, x);
+            }
+        }
+        for &(p, c) in tree.iter().rev() {
+            self.add_rows(c, p, x);
+        }
+    }
+
+    fn add_rows(&mut self, r0: usize, r1: usize, x: &mut impl RowOps) {
+        self.row_add(r0, r1);
+        x.row_add(r0, r1);
+    }
+}
+
+/// The connected components of `map`, restricted to the qubits `0..n`
+fn components(map: &CouplingMap, n: usize) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; n];
+    let mut comps = vec![];
+    for s in 0..n {
+        if !seen[s] {
+            let comp = bfs(map, &vec![true; n], s);
+            comp.iter().for_each(|&v| seen[v] = true);
+            comps.push(comp);
+        }
+    }
+    comps
+}
+
+/// The qubits in `mask` that can be reached from `s` through qubits in `mask`, in BFS
+/// order
+fn bfs(map: &CouplingMap, mask: &[bool], s: usize) -> Vec<usize> {
+    let mut seen = vec![false; mask.len()];
+    seen[s] = true;
+    let mut order = vec![s];
+    let mut i = 0;
+    while i < order.len() {
+        let v = order[i];
+        for &w in map.neighbors(v) {
+            if mask.get(w) == Some(&true) && !seen[w] {
+                seen[w] = true;
+                order.push(w);
+            }
+        }
+        i += 1;
+    }
+    order
+}
+
+/// Return the first of `candidates` that can be removed from `remaining` without
+/// disconnecting it
+///
+/// `remaining` must be connected and contain all of the candidates. There is always
+/// such a vertex (e.g. a leaf of a spanning tree), so this panics if none of the
+/// candidates work and they include every remaining vertex.
+fn non_cut_vertex(map: &CouplingMap, remaining: &mut [bool], candidates: Vec<usize>) -> usize {
+    let size = remaining.iter().filter(|&&b| b).count();
+    for v in candidates {
+        if size == 1 {
+            return v;
+        }
+        remaining[v] = false;
+        let start = map
+            .neighbors(v)
+            .iter()
+            .copied()
+            .find(|&w| remaining.get(w) == Some(&true));
+        let connected = start.is_some_and(|s| bfs(map, remaining, s).len() == size - 1);
+        remaining[v] = true;
+        if connected {
+            return v;
+        }
+    }
+    panic!("a connected graph always has a vertex that doesn't disconnect it");
+}
+
+/// An approximate Steiner tree in `map` on the qubits in `mask`, joining `root` to
+/// `terminals`
+///
+/// This greedily joins up the closest terminal to the tree by a shortest path. The
+/// tree is returned as a list of edges `(parent, child)` in pre-order, starting from
+/// `root`.
+fn steiner_tree(
+    map: &CouplingMap,
+    mask: &[bool],
+    root: usize,
+    terminals: &[usize],
+) -> Vec<(usize, usize)> {
+    let n = mask.len();
+    let mut parent = vec![usize::MAX; n];
+    let mut in_tree = vec![false; n];
+    in_tree[root] = true;
+    let mut left: Vec<_> = terminals.iter().copied().filter(|&t| t != root).collect();
+
+    while !left.is_empty() {
+        // BFS out from the whole tree until we hit a terminal
+        let mut pred = vec![usize::MAX; n];
+        let mut seen = in_tree.clone();
+        let mut queue: VecDeque<_> = (0..n).filter(|&v| in_tree[v]).collect();
+        let mut found = None;
+        while let Some(v) = queue.pop_front() {
+            if left.contains(&v) {
+                found = Some(v);
+                break;
+            }
+            for &w in map.neighbors(v) {
+                if mask.get(w) == Some(&true) && !seen[w] {
+                    seen[w] = true;
+                    pred[w] = v;
+                    queue.push_back(w);
+                }
+            }
+        }
+
+        let mut v = found.expect("terminals should be connected to the root");
+        while !in_tree[v] {
+            in_tree[v] = true;
+            parent[v] = pred[v];
+            left.retain(|&t| t != v);
+            v = pred[v];
+        }
+    }
+
+    let mut children = vec![vec![]; n];
+    for v in 0..n {
+        if parent[v] != usize::MAX {
+            children[parent[v]].push(v);
+        }
+    }
+    let mut edges = vec![];
+    let mut stack = vec![root];
+    while let Some(v) = stack.pop() {
+        for &c in children[v].iter().rev() {
+            edges.push((v, c));
+            stack.push(c);
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::*;
+    use crate::tensor::ToTensor;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// the parity matrix of a circuit of CNOTs
+    fn parity_matrix(c: &Circuit) -> Mat2 {
+        let mut m = Mat2::id(c.num_qubits());
+        for g in &c.gates {
+            assert_eq!(g.t, CNOT);
+            m.row_add(g.qs[0], g.qs[1]);
+        }
+        m
+    }
+
+    fn random_invertible(n: usize, rng: &mut StdRng) -> Mat2 {
+        let mut m = Mat2::id(n);
+        for _ in 0..n * n {
+            let a = rng.gen_range(0..n);
+            let b = rng.gen_range(0..n);
+            if a != b {
+                m.row_add(a, b);
+            }
+        }
+        m
+    }
+
+    #[test]
+    fn synth_cnots() {
+        let mut rng = StdRng::seed_from_u64(1337);
+        let maps = [
+            CouplingMap::line(6),
+            CouplingMap::ring(7),
+            CouplingMap::grid(3, 3),
+            CouplingMap::heavy_hex(1, 1),
+            CouplingMap::all_to_all(5),
+        ];
+        for map in &maps {
+            for _ in 0..5 {
+                let m = random_invertible(map.num_qubits(), &mut rng);
+                for c in [m.steiner_cnots(map), m.rowcol_cnots(map)] {
+                    let c = c.expect("matrix should be synthesized");
+                    assert!(map.respects(&c));
+                    assert_eq!(parity_matrix(&c), m);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn synth_tensor() {
+        let map = CouplingMap::line(4);
+        let m = Mat2::new(vec![
+            vec![0, 1, 1, 0],
+            vec![1, 0, 0, 1],
+            vec![0, 0, 1, 1],
+            vec![1, 0, 0, 0],
+        ]);
+        let c = m.steiner_cnots(&map).unwrap();
+        assert!(map.respects(&c));
+        assert_eq!(parity_matrix(&c), m);
+
+        let mut c1 = Circuit::new(4);
+        c1.push(Gate::new(CNOT, vec![0, 3]));
+        c1.push(Gate::new(CNOT, vec![3, 1]));
+        c1.push(Gate::new(CNOT, vec![2, 0]));
+        let m1 = parity_matrix(&c1);
+        let c2 = m1.rowcol_cnots(&map).unwrap();
+        assert!(map.respects(&c2));
+        assert_eq!(c1.to_tensor4(), c2.to_tensor4());
+    }
+
+    #[test]
+    fn steiner_gauss_rect() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let map = CouplingMap::grid(2, 3);
+        for _ in 0..10 {
+            let bits: Vec<Vec<bool>> = (0..6)
+                .map(|_| (0..8).map(|_| rng.gen_bool(0.4)).collect())
+                .collect();
+            let m = Mat2::build(6, 8, |i, j| bits[i][j]);
+            let mut m1 = m.clone();
+            let mut m2 = m.clone();
+            let mut c = Circuit::new(6);
+            let mut ops = Mat2::id(6);
+            let rank = m1.steiner_gauss(&map, true, &mut ops);
+            m2.steiner_gauss(&map, true, &mut c);
+            assert!(map.respects(&c));
+            assert_eq!(rank, m.rank());
+            assert_eq!(&ops * &m, m1);
+
+            // the same as the reduced echelon form, up to the order of the rows
+            let mut rref = m.clone();
+            rref.gauss(true);
+            let mut rows: Vec<_> = (0..6).map(|i| m1[i].clone()).collect();
+            let mut rref_rows: Vec<_> = (0..6).map(|i| rref[i].clone()).collect();
+            rows.sort();
+            rref_rows.sort();
+            assert_eq!(rows, rref_rows);
+        }
+    }
+
+    #[test]
+    fn disconnected() {
+        let map = CouplingMap::new(4, [(0, 1), (2, 3)]);
+        let m = Mat2::new(vec![
+            vec![1, 0, 1, 0],
+            vec![1, 1, 0, 0],
+            vec![0, 0, 1, 1],
+            vec![0, 0, 0, 1],
+        ]);
+        assert!(m.steiner_cnots(&map).is_none());
+        assert!(m.rowcol_cnots(&map).is_none());
+
+        // each component is reduced on its own
+        let mut m1 = m.clone();
+        let mut c = Circuit::new(4);
+        assert_eq!(m1.steiner_gauss(&map, true, &mut c), 4);
+        assert!(map.respects(&c));
+        assert_eq!(m1.row_weight(2) + m1.row_weight(3), 2);
+    }
+}
@@ -14,15 +14,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod distributed;
+
+pub use distributed::release_unfinished_tasks;
+
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // use crate::decompose;
 use crate::scalar::*;
 // use crate::graph;
 use crate::graph::*;
+use crate::json::{decode_graph, encode_graph, JsonError};
+use crate::params::Parity;
+use crate::tensor_network::TensorNetwork;
 use derive_more::derive::Display;
 use itertools::Itertools;
 // use rand::seq::SliceRandom;
@@ -37,6 +50,7 @@ use num::Rational64;
 use rand::{thread_rng, Rng};
 // use rand::rngs::StdRng;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 /// Gives upper bound for number of terms needed for BSS decomposition
 ///
@@ -1036,6 +1050,396 @@ fn calc_max_terms(node: &ComputationNode<impl GraphLike>) -> f64 {
     }
 }
 
+impl<G: GraphLike> ComputationNode<G> {
+    fn to_saved(&self) -> Result<SavedNode, CheckpointError> {
+        Ok(match self {
+            ComputationNode::None => SavedNode::None,
+            ComputationNode::Scalar(s) => SavedNode::Scalar(*s),
+            // the json format only stores scalars approximately, so keep the exact one aside
+            ComputationNode::Graph(g) => SavedNode::Graph {
+                graph: encode_graph(g)?,
+                scalar: *g.scalar(),
+            },
+            ComputationNode::Prod(terms) => SavedNode::Prod(
+                terms
+                    .iter()
+                    .map(|t| t.to_saved())
+                    .collect::<Result<_, _>>()?,
+            ),
+            ComputationNode::Sum(terms) => SavedNode::Sum(
+                terms
+                    .iter()
+                    .map(|t| t.to_saved())
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    fn from_saved(node: SavedNode) -> Result<Self, CheckpointError> {
+        Ok(match node {
+            SavedNode::None => ComputationNode::None,
+            SavedNode::Scalar(s) => ComputationNode::Scalar(s),
+            SavedNode::Graph { graph, scalar } => {
+                let mut g: G = decode_graph(&graph)?;
+                *g.scalar_mut() = scalar;
+                ComputationNode::Graph(g)
+            }
+            SavedNode::Task(i) => return Err(CheckpointError::MissingResult(i)),
+            SavedNode::Prod(terms) => ComputationNode::Prod(
+                terms
+                    .into_iter()
+                    .map(Self::from_saved)
+                    .collect::<Result<_, _>>()?,
+            ),
+            SavedNode::Sum(terms) => ComputationNode::Sum(
+                terms
+                    .into_iter()
+                    .map(Self::from_saved)
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    /// Returns the position of the first graph in the tree, as a list of child indices
+    fn first_graph(&self) -> Option<Vec<usize>> {
+        match self {
+            ComputationNode::Graph(_) => Some(vec![]),
+            ComputationNode::Prod(terms) | ComputationNode::Sum(terms) => {
+                terms.iter().enumerate().find_map(|(i, t)| {
+                    t.first_graph().map(|mut pos| {
+                        pos.insert(0, i);
+                        pos
+                    })
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn node_at_mut(&mut self, pos: &[usize]) -> &mut Self {
+        match (self, pos) {
+            (node, []) => node,
+            (ComputationNode::Prod(terms) | ComputationNode::Sum(terms), [i, rest @ ..]) => {
+                terms[*i].node_at_mut(rest)
+            }
+            _ => panic!("Invalid position in computation tree"),
+        }
+    }
+
+    /// Combines the computed scalars in the tree into a single scalar
+    ///
+    /// Graphs that have not been computed, e.g. because the decomposition was stopped early,
+    /// are counted as zero.
+    fn reduce(&self) -> Scalar4 {
+        match self {
+            ComputationNode::Scalar(s) => *s,
+            ComputationNode::Prod(terms) => terms.iter().map(|t| t.reduce()).product(),
+            ComputationNode::Sum(terms) => terms.iter().map(|t| t.reduce()).sum(),
+            ComputationNode::Graph(_) | ComputationNode::None => Scalar4::zero(),
+        }
+    }
+
+    /// Estimates the number of terms needed to compute the graphs in the tree
+    fn pending_terms(&self) -> f64 {
+        match self {
+            ComputationNode::Graph(g) => terms_for_tcount(g.tcount()),
+            ComputationNode::Prod(terms) | ComputationNode::Sum(terms) => {
+                terms.iter().map(|t| t.pending_terms()).sum()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// A snapshot of the progress of a running decomposition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of terms computed so far.
+    pub terms: usize,
+    /// Estimated number of terms still to be computed, based on [`terms_for_tcount`]. This is
+    /// an upper bound, so it typically drops quickly as simplification removes T spiders.
+    pub remaining: f64,
+    /// Time since the decomposition was started.
+    pub elapsed: Duration,
+}
+
+/// A handle to stop a running decomposition, which can be shared with other threads
+///
+/// Once cancelled, a token stays cancelled, so any later decomposition using it stops
+/// immediately.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the decomposition to stop as soon as possible
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+type ProgressFn = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// State of a decomposition run, shared between the copies of a [`Decomposer`] used for
+/// computing terms in parallel
+struct Monitor {
+    start: Instant,
+    terms: AtomicUsize,
+    remaining: Mutex<f64>,
+    last_report: Mutex<Instant>,
+    truncated: AtomicBool,
+}
+
+impl Monitor {
+    fn new(remaining: f64) -> Self {
+        let now = Instant::now();
+        Monitor {
+            start: now,
+            terms: AtomicUsize::new(0),
+            remaining: Mutex::new(remaining),
+            last_report: Mutex::new(now),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    fn add_remaining(&self, delta: f64) {
+        let mut r = self.remaining.lock().unwrap();
+        *r = (*r + delta).max(0.0);
+    }
+
+    fn progress(&self) -> Progress {
+        Progress {
+            terms: self.terms.load(Ordering::Relaxed),
+            remaining: *self.remaining.lock().unwrap(),
+            elapsed: self.start.elapsed(),
+        }
+    }
+}
+
+/// Serializable version of a [`ComputationNode`]
+#[derive(Serialize, Deserialize)]
+enum SavedNode {
+    None,
+    Scalar(Scalar4),
+    Graph {
+        graph: String,
+        scalar: Scalar4,
+    },
+    Prod(Vec<SavedNode>),
+    Sum(Vec<SavedNode>),
+    /// A graph written to a separate task file, see [`Decomposer::write_tasks`]
+    Task(usize),
+}
+
+/// The contents of a checkpoint file
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    nterms: usize,
+    result: SavedNode,
+}
+
+/// An error that can occur when saving or loading a checkpoint
+#[derive(Debug, derive_more::Display, derive_more::Error, derive_more::From)]
+pub enum CheckpointError {
+    /// Error reading or writing the checkpoint file.
+    #[display("IO error: {_0}")]
+    IO(std::io::Error),
+    /// The checkpoint file is not valid.
+    #[display("Invalid checkpoint: {_0}")]
+    Serde(serde_json::Error),
+    /// A graph in the checkpoint file is not valid.
+    #[display("Invalid graph in checkpoint: {_0}")]
+    Graph(JsonError),
+    /// A distributed task has not been computed.
+    #[display("No result for task {_0}")]
+    #[from(skip)]
+    MissingResult(#[error(not(source))] usize),
+    /// The work directory already contains the tasks of another decomposition.
+    #[display("{} already contains tasks, resume them or remove them first", _0.display())]
+    #[from(skip)]
+    TasksExist(#[error(not(source))] PathBuf),
+}
+
+/// Writes to a temporary file first, so a crash while writing keeps the old contents
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// Depth to which a decomposition is expanded before its graphs are computed one by one
+/// between checkpoints
+const CHECKPOINT_DEPTH: i64 = 4;
+
+/// Largest tensor rank allowed when contracting a component in hybrid mode
+const MAX_CONTRACTION_RANK: usize = 24;
+
+/// Contracts a scalar graph as a tensor network, if this is cheaper than decomposing it
+///
+/// The number of stabiliser terms is weighted by the size of the graph, as each term needs
+/// to be simplified.
+fn try_contract<G: GraphLike>(g: &G) -> Option<Scalar4> {
+    if !g.inputs().is_empty()
+        || !g.outputs().is_empty()
+        || g.vertices()
+            .any(|v| !matches!(g.vertex_type(v), VType::Z | VType::X))
+    {
+        return None;
+    }
+    let tn = TensorNetwork::<Scalar4>::from_graph(g);
+    let path = tn.min_fill_path();
+
+    // the stabiliser decompositions can only remove T phases, so other non-Clifford
+    // phases always need to be contracted
+    let only_ts = g
+        .vertices()
+        .all(|v| g.phase(v).is_clifford() || g.phase(v).is_t());
+    let decomp_cost = terms_for_tcount(g.tcount()) * g.num_vertices() as f64;
+    if path.max_rank <= MAX_CONTRACTION_RANK && (!only_ts || path.flops <= decomp_cost) {
+        Some(tn.contract_with(&path)[[]])
+    } else {
+        None
+    }
+}
+
+/// Largest number of graphs whose scalars are kept for memoization
+const MAX_MEMO_ENTRIES: usize = 1 << 20;
+
+fn hash_of(x: impl Hash) -> u64 {
+    let mut h = DefaultHasher::new();
+    x.hash(&mut h);
+    h.finish()
+}
+
+/// Computes a key identifying a scalar graph up to isomorphism
+///
+/// Vertices are ordered by colour refinement, individualising the first vertex of the
+/// smallest non-singleton class until all colours are distinct. The key is an exact encoding
+/// of the graph in this order, so equal keys always mean isomorphic graphs, while isomorphic
+/// graphs get the same key in all but rare cases. The scalar of the graph is not included.
+///
+/// Returns `None` for graphs with boundaries or boolean variables.
+fn canonical_key<G: GraphLike>(g: &G) -> Option<Vec<i64>> {
+    if !g.inputs().is_empty() || !g.outputs().is_empty() || g.scalar_factors().next().is_some() {
+        return None;
+    }
+    let vs: Vec<V> = g.vertices().collect();
+    if vs.iter().any(|&v| g.vertex_data(v).vars != Parity::zero()) {
+        return None;
+    }
+    let index: HashMap<V, usize> = vs.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let adj: Vec<Vec<(usize, EType)>> = vs
+        .iter()
+        .map(|&v| g.incident_edges(v).map(|(w, et)| (index[&w], et)).collect())
+        .collect();
+    let label = |v: V| {
+        let r = g.phase(v).to_rational();
+        (g.vertex_type(v) as i64, *r.numer(), *r.denom())
+    };
+
+    let num_classes = |c: &[u64]| c.iter().collect::<HashSet<_>>().len();
+    let refine = |mut colours: Vec<u64>| {
+        let mut classes = num_classes(&colours);
+        loop {
+            let next: Vec<u64> = (0..colours.len())
+                .map(|i| {
+                    let mut nhd: Vec<(i64, u64)> = adj[i]
+                        .iter()
+                        .map(|&(j, et)| (et as i64, colours[j]))
+                        .collect();
+                    nhd.sort_unstable();
+                    hash_of((colours[i], nhd))
+                })
+                .collect();
+            let next_classes = num_classes(&next);
+            colours = next;
+            if next_classes == classes {
+                return (colours, classes);
+            }
+            classes = next_classes;
+        }
+    };
+
+    let (mut colours, mut classes) = refine(vs.iter().map(|&v| hash_of(label(v))).collect());
+    while classes < vs.len() {
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for &c in &colours {
+            *counts.entry(c).or_default() += 1;
+        }
+        let (&c, _) = counts
+            .iter()
+            .filter(|(_, &n)| n > 1)
+            .min_by_key(|(&c, &n)| (n, c))
+            .unwrap();
+        let i = colours.iter().position(|&c1| c1 == c).unwrap();
+        colours[i] = hash_of((c, "individualised"));
+        (colours, classes) = refine(colours);
+    }
+
+    let mut order: Vec<usize> = (0..vs.len()).collect();
+    order.sort_by_key(|&i| colours[i]);
+    let mut pos = vec![0; vs.len()];
+    for (p, &i) in order.iter().enumerate() {
+        pos[i] = p;
+    }
+    let mut key = vec![vs.len() as i64];
+    for &i in &order {
+        let (ty, n, d) = label(vs[i]);
+        key.extend([ty, n, d]);
+    }
+    let mut edges: Vec<(usize, usize, i64)> = g
+        .edges()
+        .map(|(v, w, et)| {
+            let (p, q) = (pos[index[&v]], pos[index[&w]]);
+            (p.min(q), p.max(q), et as i64)
+        })
+        .collect();
+    edges.sort_unstable();
+    for (i, j, et) in edges {
+        key.extend([i as i64, j as i64, et]);
+    }
+    Some(key)
+}
+
+/// Statistics on the reuse of scalars of isomorphic graphs, see [`Decomposer::with_memoize`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoStats {
+    /// Number of graphs whose scalar was found in the table.
+    pub hits: usize,
+    /// Number of graphs that had to be decomposed.
+    pub misses: usize,
+    /// Number of scalars stored in the table.
+    pub entries: usize,
+}
+
+impl MemoStats {
+    /// Fraction of lookups that found a stored scalar
+    pub fn hit_rate(&self) -> f64 {
+        if self.hits + self.misses == 0 {
+            0.0
+        } else {
+            self.hits as f64 / (self.hits + self.misses) as f64
+        }
+    }
+}
+
+/// Table of scalars of graphs computed so far, shared between parallel copies of a
+/// [`Decomposer`]
+#[derive(Default)]
+struct Memo {
+    table: Mutex<HashMap<Vec<i64>, Scalar4>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
 /// Store the (partial) decomposition of a graph into stabilisers
 #[derive(Clone)]
 pub struct Decomposer<G: GraphLike> {
@@ -1044,6 +1448,14 @@ pub struct Decomposer<G: GraphLike> {
     result: ComputationNode<G>,
     simp_func: SimpFunc,
     split_graph_components: bool,
+    hybrid: bool,
+    checkpoint: Option<(PathBuf, Duration)>,
+    term_budget: Option<usize>,
+    time_budget: Option<Duration>,
+    cancel: CancelToken,
+    progress: Option<(Duration, ProgressFn)>,
+    monitor: Arc<Monitor>,
+    memo: Option<Arc<Memo>>,
     save: bool, // save graphs on 'done' stack
 }
 
@@ -1055,6 +1467,14 @@ impl<G: GraphLike> Decomposer<G> {
             nterms: 0,
             simp_func: NoSimp,
             split_graph_components: false,
+            hybrid: false,
+            checkpoint: None,
+            term_budget: None,
+            time_budget: None,
+            cancel: CancelToken::new(),
+            progress: None,
+            monitor: Arc::new(Monitor::new(0.0)),
+            memo: None,
             save: false,
         }
     }
@@ -1066,10 +1486,22 @@ impl<G: GraphLike> Decomposer<G> {
             nterms: 0,
             simp_func: NoSimp,
             split_graph_components: false,
+            hybrid: false,
+            checkpoint: None,
+            term_budget: None,
+            time_budget: None,
+            cancel: CancelToken::new(),
+            progress: None,
+            monitor: Arc::new(Monitor::new(0.0)),
+            memo: None,
             save: false,
         }
     }
 
+    /// Returns the computed scalar
+    ///
+    /// If the decomposition was stopped early (see [`Decomposer::is_truncated`]), this is the
+    /// sum of the terms computed so far.
     pub fn scalar(&self) -> Scalar4 {
         match self.result {
             ComputationNode::Scalar(scalar) => scalar,
@@ -1096,6 +1528,149 @@ impl<G: GraphLike> Decomposer<G> {
         self
     }
 
+    /// Evaluate connected components by tensor contraction whenever that is cheaper
+    ///
+    /// For each connected component with non-Clifford spiders, the cost of contracting it
+    /// along a treewidth-guided path is compared to the number of stabiliser terms needed,
+    /// and the cheaper method is used. Components are always split when this is enabled.
+    pub fn with_hybrid(&mut self, b: bool) -> &mut Self {
+        self.hybrid = b;
+        self
+    }
+
+    /// Reuse the scalars of graphs that are isomorphic to ones already computed
+    ///
+    /// After simplification, each scalar graph is put in a canonical form and looked up in a
+    /// table of previously computed scalars, which is kept across calls to `decompose`. This
+    /// costs some time per graph, but can avoid most of the work on highly symmetric
+    /// instances. Disabling memoization clears the table.
+    pub fn with_memoize(&mut self, b: bool) -> &mut Self {
+        self.memo = b.then(|| self.memo.take().unwrap_or_default());
+        self
+    }
+
+    /// Returns the memoization statistics, if memoization is enabled
+    pub fn memo_stats(&self) -> Option<MemoStats> {
+        self.memo.as_ref().map(|m| MemoStats {
+            hits: m.hits.load(Ordering::Relaxed),
+            misses: m.misses.load(Ordering::Relaxed),
+            entries: m.table.lock().unwrap().len(),
+        })
+    }
+
+    /// Periodically save the pending decomposition to a file while decomposing
+    ///
+    /// The decomposition is first expanded a few levels, then the resulting graphs are
+    /// computed one at a time. Whenever `interval` has passed since the last save, the tree of
+    /// remaining graphs and computed scalars is written to `path`, from where it can be
+    /// resumed with [`Decomposer::load_checkpoint`].
+    pub fn with_checkpoint(&mut self, path: impl Into<PathBuf>, interval: Duration) -> &mut Self {
+        self.checkpoint = Some((path.into(), interval));
+        self
+    }
+
+    /// Saves the pending decomposition to a file
+    pub fn save_checkpoint(&self, path: &Path) -> Result<(), CheckpointError> {
+        let checkpoint = Checkpoint {
+            nterms: self.nterms,
+            result: self.result.to_saved()?,
+        };
+        write_atomic(path, &serde_json::to_string(&checkpoint)?)?;
+        Ok(())
+    }
+
+    /// Loads a pending decomposition from a file written by [`Decomposer::save_checkpoint`]
+    ///
+    /// This replaces the current target. Calling [`Decomposer::decompose`] afterwards
+    /// continues the decomposition.
+    pub fn load_checkpoint(&mut self, path: &Path) -> Result<&mut Self, CheckpointError> {
+        let checkpoint: Checkpoint = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.nterms = checkpoint.nterms;
+        self.result = ComputationNode::from_saved(checkpoint.result)?;
+        Ok(self)
+    }
+
+    /// Stop the decomposition after (approximately) the given number of terms
+    pub fn with_term_budget(&mut self, terms: usize) -> &mut Self {
+        self.term_budget = Some(terms);
+        self
+    }
+
+    /// Stop the decomposition once the given time has passed
+    pub fn with_time_budget(&mut self, time: Duration) -> &mut Self {
+        self.time_budget = Some(time);
+        self
+    }
+
+    /// Use the given token to cancel the decomposition
+    pub fn with_cancel_token(&mut self, token: CancelToken) -> &mut Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Returns a token that can be used to cancel the decomposition from another thread
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Call `f` with the current [`Progress`] after a term is computed, at most once per
+    /// `interval`
+    pub fn with_progress(
+        &mut self,
+        interval: Duration,
+        f: impl Fn(&Progress) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.progress = Some((interval, Arc::new(f)));
+        self
+    }
+
+    /// Returns the progress of the current (or last) decomposition
+    pub fn progress(&self) -> Progress {
+        self.monitor.progress()
+    }
+
+    /// Whether the last decomposition was stopped early by a budget or cancellation
+    ///
+    /// Terms that were not computed are counted as zero in [`Decomposer::scalar`].
+    pub fn is_truncated(&self) -> bool {
+        self.monitor.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Resets the shared state before starting a decomposition
+    fn start_run(&mut self) {
+        self.monitor = Arc::new(Monitor::new(self.result.pending_terms()));
+    }
+
+    fn should_stop(&self) -> bool {
+        if self.cancel.is_cancelled()
+            || self
+                .term_budget
+                .is_some_and(|b| self.monitor.terms.load(Ordering::Relaxed) >= b)
+            || self
+                .time_budget
+                .is_some_and(|t| self.monitor.start.elapsed() >= t)
+        {
+            self.monitor.truncated.store(true, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Counts a computed term and reports progress if it is due
+    fn record_term(&mut self) {
+        self.nterms += 1;
+        self.monitor.terms.fetch_add(1, Ordering::Relaxed);
+        if let Some((interval, f)) = &self.progress {
+            let mut last = self.monitor.last_report.lock().unwrap();
+            if last.elapsed() >= *interval {
+                *last = Instant::now();
+                drop(last);
+                f(&self.monitor.progress());
+            }
+        }
+    }
+
     pub fn with_save(&mut self, b: bool) -> &mut Self {
         self.save = b;
         self
@@ -1112,12 +1687,14 @@ impl<G: GraphLike> Decomposer<G> {
     }
 
     pub fn decompose_until_depth(&mut self, depth: i64, driver: &impl Driver) -> &mut Self {
+        self.start_run();
         self.result = self.decompose_node(self.result.clone(), driver, false, 0, depth, false);
         self
     }
 
     /// Decompose until there are no T gates left
     pub fn decompose_standard(&mut self) -> &mut Self {
+        self.start_run();
         self.result = self.decompose_node(
             self.result.clone(),
             &BssWithCatsDriver { random_t: false },
@@ -1131,15 +1708,68 @@ impl<G: GraphLike> Decomposer<G> {
 
     /// Decompose until there are no T gates left
     pub fn decompose(&mut self, driver: &impl Driver) -> &mut Self {
+        self.start_run();
+        if self.checkpoint.is_some() {
+            return self.decompose_with_checkpoints(driver, false);
+        }
         self.result = self.decompose_node(self.result.clone(), driver, false, 0, -1, true);
         self
     }
 
     pub fn decompose_parallel(&mut self, driver: &impl Driver) -> &mut Self {
+        self.start_run();
+        if self.checkpoint.is_some() {
+            return self.decompose_with_checkpoints(driver, true);
+        }
         self.result = self.decompose_node(self.result.clone(), driver, true, 0, -1, true);
         self
     }
 
+    fn decompose_with_checkpoints(&mut self, driver: &impl Driver, parallel: bool) -> &mut Self {
+        let (path, interval) = self.checkpoint.clone().unwrap();
+        let save = |d: &Self| {
+            if let Err(e) = d.save_checkpoint(&path) {
+                log::warn!("Failed to save checkpoint to {}: {e}", path.display());
+            }
+        };
+
+        // a resumed decomposition has already been expanded
+        if let ComputationNode::Graph(_) = self.result {
+            self.result = self.decompose_node(
+                self.result.clone(),
+                driver,
+                parallel,
+                0,
+                CHECKPOINT_DEPTH,
+                false,
+            );
+            save(self);
+        }
+
+        let mut last_save = Instant::now();
+        while let Some(pos) = self.result.first_graph() {
+            let node = std::mem::replace(self.result.node_at_mut(&pos), ComputationNode::None);
+            let scalar = self.decompose_node(node.clone(), driver, parallel, 0, -1, true);
+            if self.is_truncated() {
+                // keep the graph, so a resumed run computes all of its terms
+                *self.result.node_at_mut(&pos) = node;
+                break;
+            }
+            *self.result.node_at_mut(&pos) = scalar;
+            if last_save.elapsed() >= interval {
+                save(self);
+                last_save = Instant::now();
+            }
+        }
+
+        save(self);
+        self.result = ComputationNode::Scalar(self.result.reduce());
+        if !self.is_truncated() {
+            save(self);
+        }
+        self
+    }
+
     fn node_to_scalar(&mut self, node: ComputationNode<G>) -> Scalar4 {
         if let ComputationNode::Scalar(scalar) = node {
             scalar
@@ -1165,6 +1795,8 @@ impl<G: GraphLike> Decomposer<G> {
                 .map(|component| g.subgraph_from_vertices(component.into_iter().collect()))
                 .collect();
             *subgraphs[0].scalar_mut() = *g.scalar();
+            self.monitor
+                .add_remaining(subgraphs.iter().map(|h| terms_for_tcount(h.tcount())).sum());
             let terms_vec: Vec<ComputationNode<G>> = if parallel {
                 subgraphs
                     .into_par_iter()
@@ -1221,6 +1853,14 @@ impl<G: GraphLike> Decomposer<G> {
         if current_depth == target_depth {
             ComputationNode::Graph(g)
         } else {
+            if self.should_stop() {
+                return if reduce_computation {
+                    ComputationNode::Scalar(Scalar4::zero())
+                } else {
+                    ComputationNode::Graph(g)
+                };
+            }
+            self.monitor.add_remaining(-terms_for_tcount(g.tcount()));
             match self.simp_func {
                 FullSimp => {
                     crate::simplify::full_simp(&mut g);
@@ -1233,7 +1873,7 @@ impl<G: GraphLike> Decomposer<G> {
             //check if clifford
             if g.tcount() == 0 {
                 crate::simplify::full_simp(&mut g);
-                self.nterms += 1;
+                self.record_term();
                 if g.inputs().is_empty() && g.outputs().is_empty() && g.num_vertices() != 0 {
                     println!("{}", g.to_dot());
                     panic!("WARNING: graph was not fully reduced");
@@ -1243,60 +1883,115 @@ impl<G: GraphLike> Decomposer<G> {
                 }
                 return ComputationNode::Scalar(*g.scalar());
             }
-            if self.split_graph_components {
-                if let Some(node) = self.try_decompose_by_components(
-                    &mut g,
-                    driver,
-                    parallel,
-                    current_depth,
-                    target_depth,
-                    reduce_computation,
-                ) {
-                    return node;
+            if let Some(memo) = self.memo.clone().filter(|_| reduce_computation) {
+                if let Some(key) = canonical_key(&g) {
+                    let scalar = *g.scalar();
+                    let found = memo.table.lock().unwrap().get(&key).copied();
+                    if let Some(s) = found {
+                        memo.hits.fetch_add(1, Ordering::Relaxed);
+                        return ComputationNode::Scalar(s * scalar);
+                    }
+                    memo.misses.fetch_add(1, Ordering::Relaxed);
+                    *g.scalar_mut() = Scalar4::one();
+                    let node = self.expand_graph(
+                        g,
+                        driver,
+                        current_depth,
+                        parallel,
+                        target_depth,
+                        reduce_computation,
+                    );
+                    let s = self.node_to_scalar(node);
+                    let mut table = memo.table.lock().unwrap();
+                    if !self.is_truncated() && table.len() < MAX_MEMO_ENTRIES {
+                        table.insert(key, s);
+                    }
+                    return ComputationNode::Scalar(s * scalar);
                 }
-            };
-            let decomp = driver.choose_decomp(&g);
-            let terms = apply_decomp(&g, &decomp);
-            let terms_vec: Vec<ComputationNode<G>> = if parallel {
-                terms
-                    .into_par_iter()
-                    .map(|term| {
-                        let mut d = self.clone();
-                        d.decompose_node(
-                            ComputationNode::Graph(term),
-                            driver,
-                            parallel,
-                            current_depth + 1,
-                            target_depth,
-                            reduce_computation,
-                        )
-                    })
-                    .collect()
-            } else {
-                terms
-                    .into_iter()
-                    .map(|term| {
-                        self.decompose_node(
-                            ComputationNode::Graph(term),
-                            driver,
-                            parallel,
-                            current_depth + 1,
-                            target_depth,
-                            reduce_computation,
-                        )
-                    })
-                    .collect()
-            };
-            if reduce_computation {
-                ComputationNode::Scalar(
-                    terms_vec
-                        .into_iter()
-                        .map(|node| self.node_to_scalar(node))
-                        .sum(),
-                )
-            } else {
-                ComputationNode::Sum(terms_vec)
             }
+            self.expand_graph(
+                g,
+                driver,
+                current_depth,
+                parallel,
+                target_depth,
+                reduce_computation,
+            )
+        }
+    }
+
+    /// Splits a simplified, non-Clifford graph into components or stabiliser terms and
+    /// decomposes those
+    fn expand_graph(
+        &mut self,
+        mut g: G,
+        driver: &impl Driver,
+        current_depth: i64,
+        parallel: bool,
+        target_depth: i64,
+        reduce_computation: bool,
+    ) -> ComputationNode<G> {
+        if self.split_graph_components || self.hybrid {
+            if let Some(node) = self.try_decompose_by_components(
+                &mut g,
+                driver,
+                parallel,
+                current_depth,
+                target_depth,
+                reduce_computation,
+            ) {
+                return node;
+            }
+        };
+        if self.hybrid {
+            if let Some(scalar) = try_contract(&g) {
+                self.record_term();
+                return ComputationNode::Scalar(scalar);
+            }
+        }
+        let decomp = driver.choose_decomp(&g);
+        let terms = apply_decomp(&g, &decomp);
+        self.monitor
+            .add_remaining(terms.iter().map(|h| terms_for_tcount(h.tcount())).sum());
+        let terms_vec: Vec<ComputationNode<G>> = if parallel {
+            terms
+                .into_par_iter()
+                .map(|term| {
+                    let mut d = self.clone();
+                    d.decompose_node(
+                        ComputationNode::Graph(term),
+                        driver,
+                        parallel,
+                        current_depth + 1,
+                        target_depth,
+                        reduce_computation,
+                    )
+                })
+                .collect()
+        } else {
+            terms
+                .into_iter()
+                .map(|term| {
+                    self.decompose_node(
+                        ComputationNode::Graph(term),
+                        driver,
+                        parallel,
+                        current_depth + 1,
+                        target_depth,
+                        reduce_computation,
+                    )
+                })
+                .collect()
+        };
+        if reduce_computation {
+            ComputationNode::Scalar(
+                terms_vec
+                    .into_iter()
+                    .map(|node| self.node_to_scalar(node))
+                    .sum(),
+            )
+        } else {
+            ComputationNode::Sum(terms_vec)
         }
     }
 
@@ -1403,6 +2098,7 @@ mod tests {
     use rand::SeedableRng;
 
     use super::*;
+    use crate::circuit::Circuit;
     use crate::tensor::*;
     use crate::vec_graph::Graph;
     // use itertools::Itertools;
@@ -1416,7 +2112,7 @@ mod tests {
         g
     }
 
-    fn create_graph(n: usize) -> Graph {
+    pub(super) fn create_graph(n: usize) -> Graph {
         let mut g = Graph::new();
         let mut rng = StdRng::seed_from_u64(42);
 
@@ -1972,6 +2668,289 @@ mod tests {
         assert_eq!(expected_scalar, d.scalar());
     }
 
+    // A path of T spiders has too many T gates for stabiliser decompositions, but treewidth 1
+    fn create_t_path(n: usize, phase: Rational64) -> Graph {
+        let mut g = Graph::new();
+        let mut prev = g.add_vertex_with_phase(VType::Z, phase);
+        for _ in 1..n {
+            let v = g.add_vertex_with_phase(VType::Z, phase);
+            g.add_edge_with_type(prev, v, EType::H);
+            prev = v;
+        }
+        g
+    }
+
+    #[test]
+    fn test_hybrid() {
+        let g = create_t_path(8, Rational64::new(1, 4));
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_hybrid(true);
+        d.decompose_standard();
+        assert_eq!(d.nterms, 1);
+        assert_eq!(g.to_tensor4()[[]], d.scalar());
+
+        // components with few T gates are still decomposed
+        let mut g = create_cat_graph(4, Rational64::zero());
+        g.append_graph(&create_t_path(6, Rational64::new(1, 4)));
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_hybrid(true);
+        d.decompose_standard();
+        assert_eq!(g.to_tensor4()[[]], d.scalar());
+    }
+
+    #[test]
+    fn test_hybrid_many_ts() {
+        let n = 200;
+        let g = create_t_path(n, Rational64::new(1, 4));
+        assert!(terms_for_tcount(g.tcount()) > 1e20);
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_hybrid(true);
+        d.decompose_standard();
+        assert_eq!(d.nterms, 1);
+
+        // compute the scalar of the path with a transfer matrix
+        let om = Scalar4::from_phase(Rational64::new(1, 4));
+        let h = Scalar4::sqrt2_pow(-1);
+        let mut v = [Scalar4::one(), om];
+        for _ in 1..n {
+            v = [(v[0] + v[1]) * h, (v[0] - v[1]) * h * om];
+        }
+        assert_eq!(d.scalar(), v[0] + v[1]);
+    }
+
+    #[test]
+    fn test_hybrid_arbitrary_phases() {
+        let g = create_t_path(6, Rational64::new(1, 8));
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_hybrid(true);
+        d.decompose_standard();
+        let expected = g.to_tensorf()[[]];
+        assert!((d.scalar().complex_value() - expected).norm() < 1e-10);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let g = create_graph(16);
+        let expected = g.to_tensor4()[[]];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decomp.json");
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_checkpoint(&path, Duration::ZERO);
+        d.decompose(&BssWithCatsDriver { random_t: false });
+        assert_eq!(d.scalar(), expected);
+
+        // the final checkpoint contains the result
+        let mut d1: Decomposer<Graph> = Decomposer::empty();
+        d1.load_checkpoint(&path).unwrap();
+        assert_eq!(d1.scalar(), expected);
+        assert_eq!(d1.nterms, d.nterms);
+    }
+
+    #[test]
+    fn test_resume() {
+        let g = create_graph(16);
+        let expected = g.to_tensor4()[[]];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decomp.json");
+        let driver = BssWithCatsDriver { random_t: false };
+
+        // save a partially expanded decomposition
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().decompose_until_depth(2, &driver);
+        d.save_checkpoint(&path).unwrap();
+
+        let mut d1: Decomposer<Graph> = Decomposer::empty();
+        d1.with_full_simp().load_checkpoint(&path).unwrap();
+        d1.decompose(&driver);
+        assert_eq!(d1.scalar(), expected);
+
+        let mut d2: Decomposer<Graph> = Decomposer::empty();
+        d2.with_full_simp()
+            .with_checkpoint(&path, Duration::from_secs(3600))
+            .load_checkpoint(&path)
+            .unwrap();
+        d2.decompose_parallel(&driver);
+        assert_eq!(d2.scalar(), expected);
+    }
+
+    #[test]
+    fn test_bad_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decomp.json");
+        let mut d: Decomposer<Graph> = Decomposer::empty();
+        assert!(matches!(
+            d.load_checkpoint(&path),
+            Err(CheckpointError::IO(_))
+        ));
+        fs::write(&path, "{}").unwrap();
+        assert!(matches!(
+            d.load_checkpoint(&path),
+            Err(CheckpointError::Serde(_))
+        ));
+    }
+
+    #[test]
+    fn test_term_budget() {
+        let g = create_graph(16);
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp();
+        d.decompose(&driver);
+        assert!(!d.is_truncated());
+        let nterms = d.nterms;
+        assert!(nterms > 3);
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_term_budget(3);
+        d.decompose(&driver);
+        assert!(d.is_truncated());
+        assert_eq!(d.nterms, 3);
+        assert_eq!(d.progress().terms, 3);
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_time_budget(Duration::ZERO);
+        d.decompose_parallel(&driver);
+        assert!(d.is_truncated());
+        assert_eq!(d.scalar(), Scalar4::zero());
+    }
+
+    #[test]
+    fn test_progress_and_cancel() {
+        let g = create_graph(16);
+        let driver = BssWithCatsDriver { random_t: false };
+        let reports = Arc::new(Mutex::new(vec![]));
+        let r = reports.clone();
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_progress(Duration::ZERO, move |p| r.lock().unwrap().push(*p));
+        d.decompose(&driver);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), d.nterms);
+        assert!(reports.windows(2).all(|w| w[0].terms < w[1].terms));
+        assert_eq!(reports.last().unwrap().remaining, 0.0);
+        assert_eq!(d.scalar(), g.to_tensor4()[[]]);
+
+        // cancel from another thread as soon as the first term is reported
+        let mut d = Decomposer::new(&g);
+        let token = d.cancel_token();
+        d.with_full_simp().with_progress(Duration::ZERO, move |_| {
+            let token = token.clone();
+            std::thread::spawn(move || token.cancel()).join().unwrap();
+        });
+        d.decompose(&driver);
+        assert!(d.is_truncated());
+        assert_eq!(d.nterms, 1);
+
+        // a cancelled token stops later runs immediately
+        d.set_target(g.clone()).decompose(&driver);
+        assert!(d.is_truncated());
+        assert_eq!(d.progress().terms, 0);
+    }
+
+    #[test]
+    fn test_truncated_checkpoint() {
+        let g = create_graph(16);
+        let expected = g.to_tensor4()[[]];
+        let driver = BssWithCatsDriver { random_t: false };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decomp.json");
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_checkpoint(&path, Duration::from_secs(3600))
+            .with_term_budget(5);
+        d.decompose(&driver);
+        assert!(d.is_truncated());
+
+        // resuming computes the remaining terms
+        let mut d1: Decomposer<Graph> = Decomposer::empty();
+        d1.with_full_simp().load_checkpoint(&path).unwrap();
+        d1.decompose(&driver);
+        assert!(!d1.is_truncated());
+        assert_eq!(d1.scalar(), expected);
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let g = create_graph(10);
+        // the same graph, with vertices added in the reverse order
+        let mut h = Graph::new();
+        let vs: Vec<V> = g.vertices().collect();
+        let mut map = HashMap::new();
+        for &v in vs.iter().rev() {
+            map.insert(v, h.add_vertex_with_data(g.vertex_data(v).clone()));
+        }
+        for (v, w, et) in g.edges() {
+            h.add_edge_with_type(map[&v], map[&w], et);
+        }
+        assert!(canonical_key(&g).is_some());
+        assert_eq!(canonical_key(&g), canonical_key(&h));
+
+        let (v, w, _) = h.edges().next().unwrap();
+        h.remove_edge(v, w);
+        assert_ne!(canonical_key(&g), canonical_key(&h));
+        h.set_phase(v, Rational64::new(1, 8));
+        assert_ne!(canonical_key(&g), canonical_key(&h));
+
+        let mut h = g.clone();
+        let b = h.add_vertex(VType::B);
+        h.add_edge(b, 0);
+        h.set_outputs(vec![b]);
+        assert_eq!(canonical_key(&h), None);
+    }
+
+    #[test]
+    fn test_memoize() {
+        // identical components, which should only be decomposed once
+        let mut g = Graph::new();
+        for _ in 0..4 {
+            g.append_graph(&create_graph(10));
+        }
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_split_graphs_components(true);
+        d.decompose(&driver);
+        let nterms = d.nterms;
+        assert_eq!(d.memo_stats(), None);
+
+        let mut d1 = Decomposer::new(&g);
+        d1.with_full_simp()
+            .with_split_graphs_components(true)
+            .with_memoize(true);
+        d1.decompose(&driver);
+        assert_eq!(d1.scalar(), d.scalar());
+        let stats = d1.memo_stats().unwrap();
+        assert!(stats.hits >= 3);
+        assert!(stats.entries > 0 && stats.hit_rate() > 0.0);
+        assert!(4 * d1.nterms <= nterms + 3);
+
+        // the table is kept between runs
+        d1.set_target(g.clone()).decompose_parallel(&driver);
+        assert_eq!(d1.scalar(), d.scalar());
+        assert_eq!(d1.memo_stats().unwrap().misses, stats.misses);
+    }
+
+    #[test]
+    fn test_memoize_pauli_gadgets() {
+        let c = Circuit::random_pauli_gadget()
+            .seed(1337)
+            .qubits(6)
+            .depth(10)
+            .weight(3)
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 6]);
+        g.plug_outputs(&[BasisElem::Z0; 6]);
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().decompose(&driver);
+        let mut d1 = Decomposer::new(&g);
+        d1.with_full_simp().with_memoize(true).decompose(&driver);
+        assert_eq!(d1.scalar(), d.scalar());
+        assert!(d1.nterms <= d.nterms);
+    }
+
     // Existing tests from the original code (kept for compatibility)
     #[test]
     fn bss_scalars() {
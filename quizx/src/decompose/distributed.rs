@@ -0,0 +1,288 @@
+//! Splitting a decomposition into work files, to be computed by separate processes
+//!
+//! [`Decomposer::write_tasks`] expands the decomposition to a given depth and writes each of
+//! the remaining graphs to `task-<i>.json` in a work directory, along with `plan.json`, which
+//! records how their scalars combine. The work directory must not already contain the files
+//! of another decomposition. Any number of processes can then call
+//! [`Decomposer::run_tasks`] on the same directory. Each claims pending tasks by creating
+//! `task-<i>.claim`, and writes the scalar of each task to `result-<i>.json`. Finally,
+//! [`Decomposer::reduce_tasks`] combines the results.
+//!
+//! If a worker is killed, its claimed task is not picked up by other workers. Deleting the
+//! `.claim` files of tasks without a result makes them available again.
+
+use super::*;
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+
+const PLAN_FILE: &str = "plan.json";
+
+/// The contents of a result file
+#[derive(Serialize, Deserialize)]
+struct TaskResult {
+    scalar: Scalar4,
+    nterms: usize,
+}
+
+fn task_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("task-{i}.json"))
+}
+
+fn claim_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("task-{i}.claim"))
+}
+
+fn result_path(dir: &Path, i: usize) -> PathBuf {
+    dir.join(format!("result-{i}.json"))
+}
+
+/// Returns `true` if `name` is a file written by [`Decomposer::write_tasks`] or a worker
+fn is_work_file(name: &str) -> bool {
+    let name = name.strip_suffix(".tmp").unwrap_or(name);
+    name == PLAN_FILE
+        || (["task-", "result-"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+            && (name.ends_with(".json") || name.ends_with(".claim")))
+}
+
+/// Makes tasks that were claimed, but not finished, available to workers again
+///
+/// This should only be called when no workers are running on `dir`, e.g. to recover from
+/// workers that were killed. Returns the number of tasks released.
+pub fn release_unfinished_tasks(dir: &Path) -> Result<usize, CheckpointError> {
+    fs::metadata(dir.join(PLAN_FILE))?;
+    let mut count = 0;
+    for i in (0..).take_while(|&i| task_path(dir, i).exists()) {
+        let claim = claim_path(dir, i);
+        if claim.exists() && !result_path(dir, i).exists() {
+            fs::remove_file(claim)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+impl<G: GraphLike> ComputationNode<G> {
+    /// Converts the tree to a plan, moving the graphs to a separate list of tasks
+    fn to_plan(&self, tasks: &mut Vec<SavedNode>) -> Result<SavedNode, CheckpointError> {
+        Ok(match self {
+            ComputationNode::Graph(_) => {
+                tasks.push(self.to_saved()?);
+                SavedNode::Task(tasks.len() - 1)
+            }
+            ComputationNode::Prod(terms) => SavedNode::Prod(
+                terms
+                    .iter()
+                    .map(|t| t.to_plan(tasks))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ComputationNode::Sum(terms) => SavedNode::Sum(
+                terms
+                    .iter()
+                    .map(|t| t.to_plan(tasks))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => self.to_saved()?,
+        })
+    }
+}
+
+impl SavedNode {
+    /// Replaces the tasks in a plan by their results, where these have been computed
+    fn fill_results(self, dir: &Path, nterms: &mut usize) -> Result<SavedNode, CheckpointError> {
+        Ok(match self {
+            SavedNode::Task(i) => match fs::read_to_string(result_path(dir, i)) {
+                Ok(s) => {
+                    let result: TaskResult = serde_json::from_str(&s)?;
+                    *nterms += result.nterms;
+                    SavedNode::Scalar(result.scalar)
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => SavedNode::Task(i),
+                Err(e) => return Err(e.into()),
+            },
+            SavedNode::Prod(terms) => SavedNode::Prod(
+                terms
+                    .into_iter()
+                    .map(|t| t.fill_results(dir, nterms))
+                    .collect::<Result<_, _>>()?,
+            ),
+            SavedNode::Sum(terms) => SavedNode::Sum(
+                terms
+                    .into_iter()
+                    .map(|t| t.fill_results(dir, nterms))
+                    .collect::<Result<_, _>>()?,
+            ),
+            node => node,
+        })
+    }
+}
+
+impl<G: GraphLike> Decomposer<G> {
+    /// Expands the decomposition to `depth` and writes the remaining graphs to `dir`
+    ///
+    /// Returns the number of tasks written. Graphs that were already reduced to a scalar
+    /// while expanding are kept in the plan.
+    ///
+    /// Fails with [`CheckpointError::TasksExist`] if `dir` already contains a plan, tasks or
+    /// results, as workers would mix them up with the new tasks.
+    pub fn write_tasks(
+        &mut self,
+        dir: &Path,
+        depth: i64,
+        driver: &impl Driver,
+    ) -> Result<usize, CheckpointError> {
+        fs::create_dir_all(dir)?;
+        for entry in fs::read_dir(dir)? {
+            if entry?.file_name().to_str().is_some_and(is_work_file) {
+                return Err(CheckpointError::TasksExist(dir.to_path_buf()));
+            }
+        }
+        self.decompose_until_depth(depth, driver);
+        let mut tasks = vec![];
+        let plan = Checkpoint {
+            nterms: self.nterms,
+            result: self.result.to_plan(&mut tasks)?,
+        };
+        for (i, task) in tasks.iter().enumerate() {
+            write_atomic(&task_path(dir, i), &serde_json::to_string(task)?)?;
+        }
+        // the plan is written last, so workers only start once all tasks are there
+        write_atomic(&dir.join(PLAN_FILE), &serde_json::to_string(&plan)?)?;
+        Ok(tasks.len())
+    }
+
+    /// Computes tasks in `dir` that have not been claimed by another worker
+    ///
+    /// This replaces the current target. Returns the number of tasks computed. If the
+    /// decomposition is stopped early, e.g. by [`Decomposer::with_time_budget`], the task
+    /// being computed is released again.
+    pub fn run_tasks(
+        &mut self,
+        dir: &Path,
+        driver: &impl Driver,
+    ) -> Result<usize, CheckpointError> {
+        fs::metadata(dir.join(PLAN_FILE))?;
+        let mut count = 0;
+        for i in 0.. {
+            let task = task_path(dir, i);
+            if !task.exists() {
+                break;
+            }
+            if result_path(dir, i).exists() {
+                continue;
+            }
+            let claim = claim_path(dir, i);
+            match OpenOptions::new().write(true).create_new(true).open(&claim) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+
+            let saved: SavedNode = serde_json::from_str(&fs::read_to_string(&task)?)?;
+            self.nterms = 0;
+            self.result = ComputationNode::from_saved(saved)?;
+            self.decompose(driver);
+            if self.is_truncated() {
+                fs::remove_file(&claim)?;
+                break;
+            }
+            let result = TaskResult {
+                scalar: self.scalar(),
+                nterms: self.nterms,
+            };
+            write_atomic(&result_path(dir, i), &serde_json::to_string(&result)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Combines the results of the tasks in `dir` into the scalar of the decomposition
+    ///
+    /// Fails with [`CheckpointError::MissingResult`] if some task has not been computed yet.
+    pub fn reduce_tasks(&mut self, dir: &Path) -> Result<&mut Self, CheckpointError> {
+        let plan: Checkpoint = serde_json::from_str(&fs::read_to_string(dir.join(PLAN_FILE))?)?;
+        let mut nterms = plan.nterms;
+        let saved = plan.result.fill_results(dir, &mut nterms)?;
+        let result = ComputationNode::<G>::from_saved(saved)?;
+        self.nterms = nterms;
+        self.result = ComputationNode::Scalar(result.reduce());
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompose::tests::create_graph;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+
+    #[test]
+    fn distributed() {
+        let g = create_graph(16);
+        let expected = g.to_tensor4()[[]];
+        let driver = BssWithCatsDriver { random_t: false };
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp();
+        let ntasks = d.write_tasks(dir.path(), 2, &driver).unwrap();
+        assert!(ntasks > 1);
+        assert!(matches!(
+            Decomposer::<Graph>::empty().reduce_tasks(dir.path()),
+            Err(CheckpointError::MissingResult(_))
+        ));
+
+        // two workers sharing the tasks
+        let mut w1: Decomposer<Graph> = Decomposer::empty();
+        w1.with_full_simp().with_term_budget(1);
+        let n1 = w1.run_tasks(dir.path(), &driver).unwrap();
+        let mut w2: Decomposer<Graph> = Decomposer::empty();
+        w2.with_full_simp();
+        let n2 = w2.run_tasks(dir.path(), &driver).unwrap();
+        assert_eq!(n1 + n2, ntasks);
+        assert_eq!(w2.run_tasks(dir.path(), &driver).unwrap(), 0);
+
+        let mut r: Decomposer<Graph> = Decomposer::empty();
+        r.reduce_tasks(dir.path()).unwrap();
+        assert_eq!(r.scalar(), expected);
+
+        assert!(r.nterms >= ntasks);
+        assert_eq!(release_unfinished_tasks(dir.path()).unwrap(), 0);
+    }
+
+    #[test]
+    fn tasks_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d = Decomposer::new(&create_graph(8));
+        d.with_full_simp();
+        d.write_tasks(dir.path(), 1, &driver).unwrap();
+
+        let mut d = Decomposer::new(&create_graph(10));
+        d.with_full_simp();
+        assert!(matches!(
+            d.write_tasks(dir.path(), 1, &driver),
+            Err(CheckpointError::TasksExist(_))
+        ));
+
+        // other files are left alone
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.json"), "{}").unwrap();
+        d.write_tasks(dir.path(), 1, &driver).unwrap();
+        assert!(is_work_file("task-3.claim") && is_work_file("result-0.json.tmp"));
+        assert!(!is_work_file("task-3.txt"));
+    }
+
+    #[test]
+    fn missing_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d: Decomposer<Graph> = Decomposer::empty();
+        assert!(matches!(
+            d.run_tasks(dir.path(), &driver),
+            Err(CheckpointError::IO(_))
+        ));
+    }
+}
@@ -1,12 +1,19 @@
 use approx::abs_diff_eq;
 use num::Complex;
+use rand::Rng;
 
-use crate::circuit::Circuit;
-use crate::graph::GraphLike;
+use crate::circuit::{Circuit, QasmImport};
+use crate::decompose::{BssWithCatsDriver, Decomposer};
+use crate::gate::{GType, Gate};
+use crate::graph::{BasisElem, EType, GraphLike};
 use crate::simplify::full_simp;
+use crate::tableau::Tableau;
 use crate::tensor::ToTensor;
 use crate::vec_graph::Graph;
 
+/// Tolerance used when comparing amplitudes in the randomized checks.
+const AMPLITUDE_TOLERANCE: f64 = 1e-6;
+
 /// Checks if two graphs have the same number of input qubits and output qubits respectively.
 /// This check is computationally inexpensive and suitable for any number of qubits.
 pub fn equal_graph_dim(g1: &Graph, g2: &Graph) -> bool {
@@ -63,9 +70,7 @@ pub fn equal_graph_with_options(g1: &Graph, g2: &Graph, up_to_global_phase: bool
         // both graphs are verifiably unequal due to an unequal number of input qubits or output qubits
         return Some(false);
     }
-    let mut g = g1.to_adjoint();
-    g.plug(g2);
-    full_simp(&mut g);
+    let g = simplified_miter(g1, g2);
     if g.is_identity() {
         if !up_to_global_phase {
             // both graphs are verifiably equal if the resulting global phase is zero
@@ -80,6 +85,109 @@ pub fn equal_graph_with_options(g1: &Graph, g2: &Graph, up_to_global_phase: bool
     None
 }
 
+/// Composes `g2` after the adjoint of `g1` and simplifies the result.
+fn simplified_miter(g1: &Graph, g2: &Graph) -> Graph {
+    let mut g = g1.to_adjoint();
+    g.plug(g2);
+    full_simp(&mut g);
+    g
+}
+
+/// Reads off the permutation of a graph consisting only of plain wires from inputs to outputs.
+///
+/// The i-th input is connected to the `perm[i]`-th output.
+fn wire_permutation(g: &Graph) -> Option<Vec<usize>> {
+    let n = g.inputs().len();
+    if g.outputs().len() != n || g.num_vertices() != 2 * n {
+        return None;
+    }
+    g.inputs()
+        .iter()
+        .map(|&i| {
+            let [(o, et)] = g.incident_edge_vec(i).try_into().ok()?;
+            if et != EType::N {
+                return None;
+            }
+            g.outputs().iter().position(|&o1| o1 == o)
+        })
+        .collect()
+}
+
+/// Verifies the equality of two graphs up to a permutation of the outputs.
+///
+/// On success, returns the permutation `perm` such that `g2` is `g1` followed by moving its
+/// i-th output to position `perm[i]`. This is the form of circuits extracted with
+/// [`Extractor::up_to_perm`](crate::extract::Extractor::up_to_perm).
+///
+/// Like [`equal_graph_with_options`], this relies on simplification, so `None` means that no
+/// permutation could be verified, not that the graphs are verifiably unequal.
+pub fn equal_graph_up_to_perm(
+    g1: &Graph,
+    g2: &Graph,
+    up_to_global_phase: bool,
+) -> Option<Vec<usize>> {
+    if !equal_graph_dim(g1, g2) {
+        return None;
+    }
+    let g = simplified_miter(g1, g2);
+    let perm = wire_permutation(&g)?;
+    if !up_to_global_phase && !abs_diff_eq!(g.scalar().complex_value().arg(), 0.0) {
+        return None;
+    }
+    Some(perm)
+}
+
+/// Verifies the equality of two circuits up to a permutation of the output qubits.
+/// See [`equal_graph_up_to_perm`].
+pub fn equal_circuit_up_to_perm(
+    c1: &Circuit,
+    c2: &Circuit,
+    up_to_global_phase: bool,
+) -> Option<Vec<usize>> {
+    let g1: Graph = c1.to_graph();
+    let g2: Graph = c2.to_graph();
+    equal_graph_up_to_perm(&g1, &g2, up_to_global_phase)
+}
+
+/// Wraps a circuit with `InitAncilla` and `PostSelect` gates on the given qubits.
+fn with_ancillas(c: &Circuit, ancillas: &[usize]) -> Circuit {
+    let mut c1 = c.clone();
+    for &q in ancillas {
+        c1.push_front(Gate::new(GType::InitAncilla, vec![q]));
+        c1.push_back(Gate::new(GType::PostSelect, vec![q]));
+    }
+    c1
+}
+
+/// Verifies the equality of two circuits with ancilla qubits.
+///
+/// The qubits `ancillas1` of `c1` and `ancillas2` of `c2` start in |0> and are post-selected
+/// onto |0> at the end, so only the remaining qubits are compared. Unlike
+/// [`equal_circuit_with_options`], this also checks the norm of the composed map: an ancilla
+/// that is not returned to |0> with certainty makes the circuits verifiably unequal.
+pub fn equal_circuit_with_ancillas(
+    c1: &Circuit,
+    ancillas1: &[usize],
+    c2: &Circuit,
+    ancillas2: &[usize],
+    up_to_global_phase: bool,
+) -> Option<bool> {
+    let g1: Graph = with_ancillas(c1, ancillas1).to_graph();
+    let g2: Graph = with_ancillas(c2, ancillas2).to_graph();
+    if !equal_graph_dim(&g1, &g2) {
+        return Some(false);
+    }
+    let g = simplified_miter(&g1, &g2);
+    if g.is_identity() {
+        let c: Complex<f64> = g.scalar().complex_value();
+        if !abs_diff_eq!(c.norm(), 1.0, epsilon = AMPLITUDE_TOLERANCE) {
+            return Some(false);
+        }
+        return Some(up_to_global_phase || abs_diff_eq!(c.arg(), 0.0));
+    }
+    None
+}
+
 /// Verifies the equality of two graphs up to global phase.
 pub fn equal_graph(g1: &Graph, g2: &Graph) -> Option<bool> {
     equal_graph_with_options(g1, g2, true)
@@ -101,13 +209,252 @@ pub fn equal_circuit(c1: &Circuit, c2: &Circuit) -> Option<bool> {
     equal_circuit_with_options(c1, c2, true)
 }
 
+/// Verifies the equality of two circuits read from QASM, up to global phase.
+///
+/// Angles that had to be rounded on import can make circuits that differ compare equal, so
+/// if either import has rounded angles, a positive answer is replaced by `None`.
+pub fn equal_qasm_import(i1: &QasmImport, i2: &QasmImport) -> Option<bool> {
+    match equal_circuit(&i1.circuit, &i2.circuit) {
+        Some(true) if !i1.is_exact() || !i2.is_exact() => None,
+        r => r,
+    }
+}
+
+/// A stabilizer state on which two graphs were found to differ.
+///
+/// `state` is a Clifford circuit preparing the state |psi> from |0...0>. `amplitude` is the
+/// value of <psi| g1^dagger . g2 |psi>, whereas `expected` is the value it would have if the
+/// miter `g1^dagger . g2` were the identity, or a phase times the identity if the global phase
+/// is ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub state: Circuit,
+    pub amplitude: Complex<f64>,
+    pub expected: Complex<f64>,
+}
+
+/// How the verdict of an equivalence check was reached.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evidence {
+    /// The verdict was proven, by simplification or by comparing the numbers of inputs and
+    /// outputs.
+    Proof,
+    /// The graphs differ on the given stabilizer state.
+    Counterexample(Counterexample),
+    /// The graphs agreed on the given number of random stabilizer states on `qubits` qubits.
+    ///
+    /// This is not a proof of equality. See [`Verdict::confidence`] for how likely the
+    /// samples were to find a difference.
+    Samples { trials: usize, qubits: usize },
+}
+
+/// The outcome of an equivalence check that may fall back to sampling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub equal: bool,
+    pub evidence: Evidence,
+}
+
+impl Verdict {
+    fn proof(equal: bool) -> Self {
+        Verdict {
+            equal,
+            evidence: Evidence::Proof,
+        }
+    }
+
+    /// Returns `true` if the verdict is certain, rather than based on agreeing samples.
+    pub fn is_certain(&self) -> bool {
+        !matches!(self.evidence, Evidence::Samples { .. })
+    }
+
+    /// The stabilizer state on which the graphs differ, if it was found by sampling.
+    pub fn counterexample(&self) -> Option<&Counterexample> {
+        match &self.evidence {
+            Evidence::Counterexample(cex) => Some(cex),
+            _ => None,
+        }
+    }
+
+    /// A lower bound on the probability that the samples would have found a difference, had
+    /// the graphs differed by a process infidelity of at least `infidelity`.
+    ///
+    /// The process infidelity of unitaries `U1`, `U2` on `n` qubits is
+    /// `1 - |tr(U1^dagger U2)|^2 / 4^n`, which is zero exactly when they are equal up to global
+    /// phase. This is 1 for certain verdicts.
+    ///
+    /// The bound holds if both graphs are unitaries, including their scalars, as the graphs of
+    /// circuits without measurements or post-selection are. Random stabilizer states form a
+    /// 2-design, so for the miter `U = U1^dagger U2` and a random stabilizer state |psi> on
+    /// `d = 2^n` dimensions, `E[1 - |<psi|U|psi>|^2] = d * infidelity / (d + 1)`. A sample only
+    /// passes if `1 - |<psi|U|psi>|^2` is at most `t = 1 - (1 - AMPLITUDE_TOLERANCE)^2`, so
+    /// by Markov's inequality applied to `|<psi|U|psi>|^2`, each sample fails with probability
+    /// at least `p = (d * infidelity / (d + 1) - t) / (1 - t)`.
+    pub fn confidence(&self, infidelity: f64) -> f64 {
+        match self.evidence {
+            Evidence::Samples { trials, qubits } => {
+                // d / (d + 1), computed without overflowing d for many qubits
+                let ratio = 1.0 / (1.0 + 0.5f64.powf(qubits as f64));
+                let t = 1.0 - (1.0 - AMPLITUDE_TOLERANCE).powi(2);
+                let p = ((ratio * infidelity - t) / (1.0 - t)).clamp(0.0, 1.0);
+                1.0 - (1.0 - p).powf(trials as f64)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Computes `<psi| g |psi>` for the state |psi> prepared from |0...0> by the graph `state`.
+///
+/// T phases are removed by the stabilizer decomposer, whereas components with other
+/// non-Clifford phases are contracted as tensor networks, which is only feasible if they have
+/// low treewidth.
+fn miter_amplitude(g: &Graph, state: &Graph) -> Complex<f64> {
+    let zeros = vec![BasisElem::Z0; g.inputs().len()];
+    let mut h = state.clone();
+    h.plug(g);
+    h.plug(&state.to_adjoint());
+    h.plug_inputs(&zeros);
+    h.plug_outputs(&zeros);
+    full_simp(&mut h);
+    let mut d = Decomposer::empty();
+    d.with_full_simp().with_hybrid(true).set_target(h);
+    d.decompose(&BssWithCatsDriver { random_t: false })
+        .scalar()
+        .complex_value()
+}
+
+/// Checks the equality of two graphs by sampling amplitudes of the miter `g1^dagger . g2`.
+///
+/// Each trial prepares a uniformly random stabilizer state |psi> with a random Clifford
+/// circuit (see [`Tableau::random`]) and computes `<psi| g1^dagger . g2 |psi>` with the
+/// [`Decomposer`]. If the graphs are equal, each of these amplitudes is 1. If the global phase
+/// is ignored, they must instead all be the same phase, which is fixed by the first sample.
+///
+/// If a sampled amplitude deviates from this, the graphs are verifiably unequal and the
+/// Clifford circuit preparing the state is returned as a [`Counterexample`]. Otherwise, the
+/// graphs are reported as equal based on [`Evidence::Samples`], which is not a proof, but
+/// comes with a bound on the chance of missing a difference, see [`Verdict::confidence`].
+///
+/// The graphs are assumed to be unitaries, including their scalars, like the graphs of
+/// circuits without measurements or post-selection. A counterexample is only meaningful
+/// under this assumption, as other maps can have amplitudes with a norm other than 1.
+///
+/// Returns `None` if `trials` is zero.
+pub fn equal_graph_randomized(
+    g1: &Graph,
+    g2: &Graph,
+    up_to_global_phase: bool,
+    trials: usize,
+    rng: &mut impl Rng,
+) -> Option<Verdict> {
+    if !equal_graph_dim(g1, g2) {
+        return Some(Verdict::proof(false));
+    }
+    if trials == 0 {
+        return None;
+    }
+    let mut g = g1.to_adjoint();
+    g.plug(g2);
+    let n = g.inputs().len();
+
+    let mut reference = None;
+    for _ in 0..trials {
+        let state = Tableau::random(n, rng).to_circuit();
+        let amplitude = miter_amplitude(&g, &state.to_graph());
+
+        let expected = match reference {
+            Some(r) => r,
+            None if up_to_global_phase && amplitude.norm() > AMPLITUDE_TOLERANCE => {
+                // the phase of a multiple of the identity is the phase of any diagonal element
+                let r = amplitude / amplitude.norm();
+                reference = Some(r);
+                r
+            }
+            None => Complex::new(1.0, 0.0),
+        };
+
+        if (amplitude - expected).norm() > AMPLITUDE_TOLERANCE {
+            return Some(Verdict {
+                equal: false,
+                evidence: Evidence::Counterexample(Counterexample {
+                    state,
+                    amplitude,
+                    expected,
+                }),
+            });
+        }
+    }
+
+    Some(Verdict {
+        equal: true,
+        evidence: Evidence::Samples { trials, qubits: n },
+    })
+}
+
+/// Verifies the equality of two graphs, falling back to [`equal_graph_randomized`] if
+/// [`equal_graph_with_options`] is inconclusive.
+pub fn equal_graph_with_fallback(
+    g1: &Graph,
+    g2: &Graph,
+    up_to_global_phase: bool,
+    trials: usize,
+    rng: &mut impl Rng,
+) -> Option<Verdict> {
+    match equal_graph_with_options(g1, g2, up_to_global_phase) {
+        Some(equal) => Some(Verdict::proof(equal)),
+        None => equal_graph_randomized(g1, g2, up_to_global_phase, trials, rng),
+    }
+}
+
+/// Checks the equality of two circuits by sampling amplitudes. See [`equal_graph_randomized`].
+pub fn equal_circuit_randomized(
+    c1: &Circuit,
+    c2: &Circuit,
+    up_to_global_phase: bool,
+    trials: usize,
+    rng: &mut impl Rng,
+) -> Option<Verdict> {
+    let g1: Graph = c1.to_graph();
+    let g2: Graph = c2.to_graph();
+    equal_graph_randomized(&g1, &g2, up_to_global_phase, trials, rng)
+}
+
+/// Verifies the equality of two circuits, falling back to random sampling if simplification
+/// is inconclusive. See [`equal_graph_with_fallback`].
+pub fn equal_circuit_with_fallback(
+    c1: &Circuit,
+    c2: &Circuit,
+    up_to_global_phase: bool,
+    trials: usize,
+    rng: &mut impl Rng,
+) -> Option<Verdict> {
+    let g1: Graph = c1.to_graph();
+    let g2: Graph = c2.to_graph();
+    equal_graph_with_fallback(&g1, &g2, up_to_global_phase, trials, rng)
+}
+
 #[cfg(test)]
 mod tests {
     use num::Rational64;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
+    use super::equal_circuit;
+    use super::equal_circuit_randomized;
     use super::equal_circuit_tensor;
+    use super::equal_circuit_up_to_perm;
+    use super::equal_circuit_with_ancillas;
+    use super::equal_circuit_with_fallback;
     use super::equal_circuit_with_options;
-    use crate::circuit::Circuit;
+    use super::equal_qasm_import;
+    use super::{Evidence, Verdict};
+    use crate::circuit::{Circuit, QasmOptions};
+    use crate::extract::Extractor;
+    use crate::gate::{GType, Gate};
+    use crate::simplify::full_simp;
+    use crate::tableau::Tableau;
+    use crate::vec_graph::Graph;
 
     /// Inspired by `BothCircuitsEmptyZXChecker` found in `test_equality.cpp` from mqt-qcec
     #[test]
@@ -138,6 +485,21 @@ mod tests {
         assert!(equal_circuit_with_options(&c1, &c2, true).is_none());
     }
 
+    #[test]
+    fn rounded_qasm_angles() {
+        let read =
+            |qasm: &str| Circuit::from_qasm_with_options(qasm, &QasmOptions::default()).unwrap();
+        let i1 = read("qreg q[1]; rz(0.1) q[0];");
+        let i2 = read("qreg q[1]; rz(0.1000000000001) q[0];");
+        // the angles round to the same phase
+        assert_eq!(equal_circuit(&i1.circuit, &i2.circuit), Some(true));
+        assert_eq!(equal_qasm_import(&i1, &i2), None);
+
+        let i1 = read("qreg q[1]; rz(pi/1024) q[0]; rz(pi/1024) q[0];");
+        let i2 = read("qreg q[1]; rz(pi/512) q[0];");
+        assert_eq!(equal_qasm_import(&i1, &i2), Some(true));
+    }
+
     #[test]
     fn cx_with_ancilla_as_x() {
         let mut c1 = Circuit::new(1);
@@ -191,4 +553,196 @@ mod tests {
         // c1 and c2 are verifiably equal
         assert!(equal_circuit_with_options(&c1, &c2, false).unwrap());
     }
+
+    #[test]
+    fn randomized_counterexample() {
+        let mut c1 = Circuit::new(3);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate("ccz", vec![0, 1, 2]);
+        c1.add_gate("t", vec![1]);
+        c1.add_gate("cx", vec![2, 0]);
+
+        // drop one T gate
+        let mut c2 = c1.clone();
+        c2.gates.remove(2);
+
+        let mut rng = StdRng::seed_from_u64(1337);
+        let v = equal_circuit_randomized(&c1, &c2, true, 50, &mut rng).unwrap();
+        assert!(!v.equal);
+        assert!(v.is_certain());
+        let cex = v.counterexample().unwrap();
+        assert_eq!(cex.state.num_qubits(), 3);
+        assert!(Tableau::from_circuit(&cex.state).is_some());
+        assert!((cex.amplitude - cex.expected).norm() > 1e-6);
+    }
+
+    #[test]
+    fn randomized_equal() {
+        let c1 = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(30)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let c2 = c1.to_basic_gates();
+
+        let mut rng = StdRng::seed_from_u64(1337);
+        let v = equal_circuit_randomized(&c1, &c2, false, 20, &mut rng).unwrap();
+        assert_eq!(
+            v.evidence,
+            Evidence::Samples {
+                trials: 20,
+                qubits: 5
+            }
+        );
+        assert!(!v.is_certain());
+        assert_eq!(v.confidence(0.0), 0.0);
+        assert!(v.confidence(0.5) > 0.99);
+        assert!(v.confidence(0.1) < v.confidence(0.5));
+        assert!(equal_circuit_randomized(&c1, &c2, false, 0, &mut rng).is_none());
+
+        // a global phase is only noticed if it matters
+        let mut c3 = c1.clone();
+        c3.add_gate("z", vec![0]);
+        c3.add_gate("x", vec![0]);
+        c3.add_gate("z", vec![0]);
+        c3.add_gate("x", vec![0]);
+        let v = equal_circuit_randomized(&c1, &c3, true, 20, &mut rng).unwrap();
+        assert!(v.equal);
+        let v = equal_circuit_randomized(&c1, &c3, false, 20, &mut rng).unwrap();
+        assert!(!v.equal);
+    }
+
+    #[test]
+    fn randomized_arbitrary_phases() {
+        let mut c1 = Circuit::new(3);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate_with_phase("rz", vec![0], Rational64::new(1, 7));
+        c1.add_gate("cx", vec![0, 1]);
+        c1.add_gate_with_phase("rz", vec![1], Rational64::new(1, 5));
+        c1.add_gate("h", vec![2]);
+        c1.add_gate("cx", vec![1, 2]);
+        c1.add_gate_with_phase("rz", vec![2], Rational64::new(1, 3));
+
+        let mut c2 = c1.clone();
+        c2.add_gate_with_phase("rz", vec![1], Rational64::new(1, 11));
+        c2.add_gate_with_phase("rz", vec![1], Rational64::new(-1, 11));
+        let mut rng = StdRng::seed_from_u64(1337);
+        let v = equal_circuit_randomized(&c1, &c2, false, 20, &mut rng).unwrap();
+        assert!(v.equal);
+
+        let mut c3 = c1.clone();
+        c3.gates[1] = Gate::new_with_phase(GType::ZPhase, vec![0], Rational64::new(1, 6));
+        let v = equal_circuit_randomized(&c1, &c3, true, 20, &mut rng).unwrap();
+        assert!(!v.equal);
+        assert_eq!(v.confidence(0.0), 1.0);
+    }
+
+    #[test]
+    fn fallback() {
+        let mut c1 = Circuit::new(1);
+        let mut c2 = Circuit::new(1);
+        c1.add_gate("x", vec![0]);
+        c2.add_gate("x", vec![0]);
+        c2.add_gate_with_phase("rz", vec![0], Rational64::new(1, 1024));
+
+        let mut rng = StdRng::seed_from_u64(1337);
+        let v = equal_circuit_with_fallback(&c1, &c2, true, 20, &mut rng).unwrap();
+        assert!(!v.equal);
+        assert!(v.counterexample().is_some());
+
+        // verdicts reached by simplification are proofs
+        let v = equal_circuit_with_fallback(&c1, &c1, true, 20, &mut rng).unwrap();
+        assert_eq!(v, Verdict::proof(true));
+    }
+
+    #[test]
+    fn up_to_perm_swap() {
+        let mut c1 = Circuit::new(3);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate("cx", vec![0, 1]);
+        c1.add_gate("t", vec![2]);
+
+        let mut c2 = c1.clone();
+        c2.add_gate("swap", vec![0, 2]);
+
+        assert_eq!(
+            equal_circuit_up_to_perm(&c1, &c1, false),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(
+            equal_circuit_up_to_perm(&c1, &c2, false),
+            Some(vec![2, 1, 0])
+        );
+
+        // not a permutation
+        c2.add_gate("h", vec![1]);
+        assert_eq!(equal_circuit_up_to_perm(&c1, &c2, true), None);
+    }
+
+    #[test]
+    fn up_to_perm_extracted() {
+        let c1 = Circuit::random()
+            .seed(1337)
+            .qubits(6)
+            .depth(40)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let mut g: Graph = c1.to_graph();
+        full_simp(&mut g);
+        let c2 = Extractor::new(&mut g).up_to_perm().extract().unwrap();
+
+        let perm = equal_circuit_up_to_perm(&c1, &c2, true).unwrap();
+        let mut sorted = perm.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..6).collect::<Vec<_>>());
+
+        // undoing the permutation gives back an equal circuit
+        let mut c3 = c2.clone();
+        let mut pos: Vec<_> = (0..6).collect();
+        for (i, &p) in perm.iter().enumerate() {
+            let j = pos.iter().position(|&q| q == i).unwrap();
+            if j != p {
+                c3.add_gate("swap", vec![j, p]);
+                pos.swap(j, p);
+            }
+        }
+        assert_eq!(equal_circuit(&c1, &c3), Some(true));
+    }
+
+    #[test]
+    fn ancillas() {
+        let mut c1 = Circuit::new(1);
+        c1.add_gate("x", vec![0]);
+
+        let mut c2 = Circuit::new(2);
+        c2.add_gate("x", vec![1]);
+        c2.add_gate("cx", vec![1, 0]);
+        c2.add_gate("x", vec![1]);
+
+        assert_eq!(
+            equal_circuit_with_ancillas(&c1, &[], &c2, &[1], false),
+            Some(true)
+        );
+
+        // ancillas on both sides
+        let mut c3 = Circuit::new(2);
+        c3.add_gate("cx", vec![0, 1]);
+        c3.add_gate("x", vec![1]);
+        assert_eq!(
+            equal_circuit_with_ancillas(&c2, &[1], &c3, &[0], false),
+            Some(true)
+        );
+
+        // ancilla is left in |+>, so the post-selection only succeeds with probability 1/2
+        let mut c4 = Circuit::new(2);
+        c4.add_gate("x", vec![0]);
+        c4.add_gate("h", vec![1]);
+        assert_eq!(
+            equal_circuit_with_ancillas(&c1, &[], &c4, &[1], true),
+            Some(false)
+        );
+    }
 }
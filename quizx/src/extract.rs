@@ -19,6 +19,7 @@ use crate::circuit::*;
 use crate::gate::*;
 use crate::graph::*;
 use crate::linalg::*;
+use crate::routing::CouplingMap;
 use num::Rational64;
 use num::Zero;
 use rustc_hash::FxHashSet;
@@ -49,7 +50,7 @@ pub trait ToCircuit: GraphLike {
         self.clone().to_circuit_mut()
     }
 
-    fn extractor(&mut self) -> Extractor<Self> {
+    fn extractor(&mut self) -> Extractor<'_, Self> {
         Extractor::new(self)
     }
 }
@@ -59,6 +60,7 @@ pub struct Extractor<'a, G: GraphLike> {
     frontier: Vec<(usize, V)>,
     up_to_perm: bool,
     gaussf: fn(&mut Extractor<'a, G>, &mut Circuit),
+    coupling_map: Option<CouplingMap>,
 }
 
 impl<'a, G: GraphLike> Extractor<'a, G> {
@@ -68,6 +70,7 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
             frontier: Vec::new(),
             up_to_perm: false,
             gaussf: Extractor::single_sln_set,
+            coupling_map: None,
         }
     }
 
@@ -93,6 +96,28 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
         self.with_gaussf(Extractor::simple_gauss)
     }
 
+    pub fn gflow_pmh_gauss(&mut self) -> &mut Self {
+        self.with_gaussf(Extractor::pmh_gauss)
+    }
+
+    /// Extract a circuit for a device with the given coupling map
+    ///
+    /// Qubit `i` of the circuit is qubit `i` of the map, and only the qubits of the
+    /// circuit are used. CNOT and CZ gates between qubits that aren't neighbours are
+    /// replaced by CNOTs along a path between them (see
+    /// [`CouplingMap::long_range_cnot`]), and the final permutation is synthesized with
+    /// [`Mat2::rowcol`]. To keep the CNOTs from gaussian elimination local as well, use
+    /// [`Extractor::steiner_gauss`].
+    pub fn with_coupling_map(&mut self, map: CouplingMap) -> &mut Self {
+        self.coupling_map = Some(map);
+        self
+    }
+
+    pub fn gflow_steiner_gauss(&mut self, map: CouplingMap) -> &mut Self {
+        self.with_coupling_map(map)
+            .with_gaussf(Extractor::steiner_gauss)
+    }
+
     /// Build a biadjacency matrix of frontier with its neighbors
     ///
     /// Frontier elements are rows and neighbors are columns. The computed
@@ -143,7 +168,50 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
             let mut gate = gate.clone();
             gate.qs[0] = self.frontier[gate.qs[0]].0;
             gate.qs[1] = self.frontier[gate.qs[1]].0;
+            self.push_front_gate(c, gate);
+        }
+    }
+
+    /// Push a gate on to the front of `c`
+    ///
+    /// If there is a coupling map, CNOT, CZ and SWAP gates between qubits that aren't
+    /// neighbours are replaced by CNOTs between neighbours. Gates between disconnected
+    /// qubits are left as they are.
+    fn push_front_gate(&self, c: &mut Circuit, gate: Gate) {
+        let (Some(map), CNOT | CZ | SWAP) = (&self.coupling_map, gate.t) else {
+            c.push_front(gate);
+            return;
+        };
+        let (q0, q1) = (gate.qs[0], gate.qs[1]);
+        if map.connected(q0, q1) {
             c.push_front(gate);
+            return;
+        }
+        let (Some(cnot), Some(cnot_rev)) =
+            (map.long_range_cnot(q0, q1), map.long_range_cnot(q1, q0))
+        else {
+            c.push_front(gate);
+            return;
+        };
+
+        let gates = match gate.t {
+            CNOT => cnot,
+            CZ => {
+                let h = Gate::new(HAD, vec![q1]);
+                let mut gates = vec![h.clone()];
+                gates.extend(cnot);
+                gates.push(h);
+                gates
+            }
+            _ => {
+                let mut gates = cnot.clone();
+                gates.extend(cnot_rev);
+                gates.extend(cnot);
+                gates
+            }
+        };
+        for g in gates.into_iter().rev() {
+            c.push_front(g);
         }
     }
 
@@ -170,6 +238,48 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
         e.update_frontier_biadj(&neighbors, m);
     }
 
+    /// Perform gaussian elimination on the frontier as CNOT gates, using the
+    /// Patel/Markov/Hayes algorithm
+    ///
+    /// This reduces the biadjacency matrix to the same form as
+    /// [`Extractor::simple_gauss`], but usually with fewer CNOTs when the frontier is
+    /// wide. See [`Mat2::pmh_gauss_x`].
+    pub fn pmh_gauss(e: &mut Extractor<G>, c: &mut Circuit) {
+        let (neighbors, mut m) = e.frontier_biadj();
+        let mut c1 = Circuit::new(c.num_qubits());
+        m.pmh_gauss_x(&mut c1);
+
+        e.update_frontier_circuit(&c1, c);
+        e.update_frontier_biadj(&neighbors, m);
+    }
+
+    /// Perform gaussian elimination on the frontier as CNOT gates between neighbours in
+    /// the coupling map
+    ///
+    /// This reduces the biadjacency matrix in the same way as [`Extractor::simple_gauss`],
+    /// but uses [`Mat2::steiner_gauss`] on the coupling map restricted to the frontier
+    /// qubits. If those aren't connected, and the elimination doesn't free any vertex,
+    /// this falls back to [`Extractor::simple_gauss`]. Without a coupling map, this is
+    /// the same as [`Extractor::simple_gauss`].
+    pub fn steiner_gauss(e: &mut Extractor<G>, c: &mut Circuit) {
+        let Some(map) = &e.coupling_map else {
+            return Extractor::simple_gauss(e, c);
+        };
+        let qs: Vec<_> = e.frontier.iter().map(|&(q, _)| q).collect();
+        let map = map.subgraph(&qs);
+
+        let (neighbors, mut m) = e.frontier_biadj();
+        let mut c1 = Circuit::new(c.num_qubits());
+        m.steiner_gauss(&map, true, &mut c1);
+        let freed = !m.unit_rows().is_empty();
+
+        e.update_frontier_circuit(&c1, c);
+        e.update_frontier_biadj(&neighbors, m);
+        if !freed {
+            Extractor::simple_gauss(e, c);
+        }
+    }
+
     /// Perform row operations to free a single vertex with the smallest solution set
     pub fn single_sln_set(e: &mut Extractor<G>, c: &mut Circuit) {
         let (neighbors, mut m) = e.frontier_biadj();
@@ -254,11 +364,17 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
             self.g.connected(self.g.outputs()[i], self.g.inputs()[j])
         });
 
-        // Extract CNOTs until adj. matrix is in reduced echelon form
+        // Extract CNOTs until adj. matrix is in reduced echelon form. With a coupling map,
+        // rowcol does this with local CNOTs, unless the map is disconnected, in which case
+        // gauss_x finishes the job.
         let mut c1 = Circuit::new(c.num_qubits());
+        if let (Some(map), true) = (&self.coupling_map, m.num_rows() == m.num_cols()) {
+            let qs: Vec<_> = (0..m.num_rows()).collect();
+            m.rowcol(&map.subgraph(&qs), &mut c1);
+        }
         m.gauss_x(true, blocksize, &mut c1);
         for g in c1.gates {
-            c.push_front(g);
+            self.push_front_gate(c, g);
         }
     }
 
@@ -332,7 +448,7 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
                     } else if let Some(&(r, _)) = self.frontier.iter().find(|&&(_, n1)| n == n1) {
                         // TODO: CZ optimisation (maybe)
                         self.g.remove_edge(v, n);
-                        c.push_front(Gate::new(CZ, vec![q, r]));
+                        self.push_front_gate(c, Gate::new(CZ, vec![q, r]));
 
                         // we should not encounter any non-Z vertices at this point
                     } else if self.g.vertex_type(n) != VType::Z {
@@ -406,6 +522,21 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
     pub fn extract(&mut self) -> Result<Circuit, ExtractError<G>> {
         // let t = self.to_tensorf(); // DEBUG
         let mut c = Circuit::new(self.g.outputs().len());
+        if let Some(map) = &self.coupling_map {
+            if map.num_qubits() < c.num_qubits() {
+                return Err(ExtractError(
+                    format!(
+                        "Circuit has {} qubits, but the coupling map only has {}.",
+                        c.num_qubits(),
+                        map.num_qubits()
+                    ),
+                    c,
+                    self.g.clone(),
+                ));
+            }
+            let qs: Vec<_> = (0..c.num_qubits()).collect();
+            self.coupling_map = Some(map.subgraph(&qs));
+        }
 
         // Pre-generate a set of all the phase gadgets. The extraction should
         // only ever eliminate phase gadgets, never create new ones.
@@ -721,4 +852,81 @@ mod tests {
             assert!(Tensor4::scalar_compare(&c, &c1));
         }
     }
+
+    #[test]
+    fn pmh_extract() {
+        for seed in [1337, 143, 105] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(6)
+                .depth(40)
+                .p_t(0.2)
+                .with_cliffords()
+                .build();
+            let mut g: Graph = c.to_graph();
+            full_simp(&mut g);
+            let c1 = g
+                .extractor()
+                .gflow_pmh_gauss()
+                .extract()
+                .expect("Circuit should extract.");
+            assert!(Tensor4::scalar_compare(&c, &c1));
+        }
+    }
+
+    #[test]
+    fn steiner_extract() {
+        for (seed, map) in [
+            (1337, CouplingMap::line(5)),
+            (143, CouplingMap::grid(2, 3)),
+            (105, CouplingMap::ring(7)),
+        ] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(40)
+                .p_t(0.2)
+                .with_cliffords()
+                .build();
+            let mut g: Graph = c.to_graph();
+            full_simp(&mut g);
+            let c1 = g
+                .extractor()
+                .gflow_steiner_gauss(map.clone())
+                .extract()
+                .expect("Circuit should extract.");
+            assert!(map.respects(&c1));
+            assert!(Tensor4::scalar_compare(&c, &c1));
+        }
+    }
+
+    #[test]
+    fn steiner_extract_perm() {
+        let mut g = Graph::new();
+        let is: Vec<_> = (0..4).map(|_| g.add_vertex(VType::B)).collect();
+        let os: Vec<_> = (0..4).map(|_| g.add_vertex(VType::B)).collect();
+        g.add_edge(is[0], os[3]);
+        g.add_edge(is[1], os[2]);
+        g.add_edge(is[2], os[0]);
+        g.add_edge(is[3], os[1]);
+        g.set_inputs(is);
+        g.set_outputs(os);
+
+        let map = CouplingMap::line(4);
+        let c = g
+            .clone()
+            .extractor()
+            .with_coupling_map(map.clone())
+            .extract()
+            .unwrap();
+        assert!(map.respects(&c));
+        assert_eq!(g.to_tensor4(), c.to_tensor4());
+
+        let err = g
+            .extractor()
+            .with_coupling_map(CouplingMap::line(3))
+            .extract()
+            .unwrap_err();
+        assert!(err.0.contains("coupling map"));
+    }
 }
@@ -43,6 +43,7 @@ pub enum GType {
     PostSelect,
     Measure,
     MeasureReset,
+    Barrier,
     UnknownGate,
 }
 
@@ -67,6 +68,9 @@ impl GType {
             "ccx" => TOFF,
             "ccz" => CCZ,
             "swap" => SWAP,
+            "barrier" => Barrier,
+            "measure" => Measure,
+            "reset" => MeasureReset,
             // n.b. these are pyzx-specific gates
             "pp" => ParityPhase,
             "xcx" => XCX,
@@ -94,6 +98,7 @@ impl GType {
             TOFF => "ccx",
             CCZ => "ccz",
             SWAP => "swap",
+            Barrier => "barrier",
             // n.b. these are pyzx-specific gates
             ParityPhase => "pp",
             XCX => "xcx",
@@ -113,20 +118,83 @@ impl GType {
         match self {
             CNOT | CZ | XCX | SWAP => Some(2),
             TOFF | CCZ => Some(3),
-            ParityPhase | UnknownGate => None,
+            ParityPhase | Barrier | UnknownGate => None,
             _ => Some(1),
         }
     }
 }
 
+/// A condition on the value of a classical register, as in `if (c == 2)`
+///
+/// This holds if the bits `start..start + len`, read as a little-endian integer, are
+/// equal to `value`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct RegisterCondition {
+    pub start: Var,
+    pub len: usize,
+    pub value: u64,
+}
+
+impl RegisterCondition {
+    /// The bits of the register, starting with the least significant one
+    pub fn bits(&self) -> impl Iterator<Item = Var> {
+        self.start..self.start + self.len as Var
+    }
+}
+
+/// A gate in a [`Circuit`]
+///
+/// For `Measure` and `MeasureReset` gates, `vars` gives the classical bit that
+/// the outcome is stored in. For all other gates, a non-zero `vars` makes the
+/// gate classically controlled: it is only applied if the parity of the given
+/// bits is 1. Gates other than Paulis (see [`Gate::is_pauli`]) should only be
+/// controlled by a single bit.
+///
+/// A gate can also be controlled by the value of a whole register, given by
+/// `condition`. Only Pauli gates controlled by `vars` can be added to a ZX-diagram.
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Gate {
     pub t: GType,
     pub qs: Vec<usize>,
     pub phase: Phase,
     pub vars: Parity,
+    pub condition: Option<RegisterCondition>,
 }
 
+/// Standard OpenQASM gates with no [`GType`] of their own, given as (name, number
+/// of parameters, number of qubits)
+///
+/// These are the gates from `qelib1.inc` and `stdgates.inc` which are read by
+/// decomposing them with [`Gate::push_std_gate_decomp`].
+pub const STD_GATES: [(&str, usize, usize); 26] = [
+    ("id", 0, 1),
+    ("y", 0, 1),
+    ("sx", 0, 1),
+    ("sxdg", 0, 1),
+    ("p", 1, 1),
+    ("phase", 1, 1),
+    ("u1", 1, 1),
+    ("ry", 1, 1),
+    ("u2", 2, 1),
+    ("u3", 3, 1),
+    ("u", 3, 1),
+    ("U", 3, 1),
+    ("cy", 0, 2),
+    ("ch", 0, 2),
+    ("csx", 0, 2),
+    ("cp", 1, 2),
+    ("cphase", 1, 2),
+    ("cu1", 1, 2),
+    ("crx", 1, 2),
+    ("cry", 1, 2),
+    ("crz", 1, 2),
+    ("cu3", 3, 2),
+    ("cu", 4, 2),
+    ("rzz", 1, 2),
+    ("rxx", 1, 2),
+    ("cswap", 0, 3),
+];
+
 impl Default for Gate {
     fn default() -> Self {
         Gate {
@@ -134,6 +202,7 @@ impl Default for Gate {
             qs: vec![],
             phase: Phase::zero(),
             vars: Parity::zero(),
+            condition: None,
         }
     }
 }
@@ -145,6 +214,7 @@ impl Gate {
             qs: vec![],
             phase: Phase::zero(),
             vars: Parity::zero(),
+            condition: None,
         }
     }
 
@@ -166,6 +236,50 @@ impl Gate {
         s
     }
 
+    /// Returns `true` if the gate is a Pauli X or Z gate, possibly given as a phase gate
+    pub fn is_pauli(&self) -> bool {
+        match self.t {
+            NOT | Z => true,
+            ZPhase | XPhase => self.phase.is_pauli(),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the gate is classically controlled
+    pub fn is_conditional(&self) -> bool {
+        self.condition.is_some()
+            || (!matches!(self.t, Measure | MeasureReset) && !self.vars.is_zero())
+    }
+
+    /// The classical bits the gate writes to, or is controlled by
+    pub fn bits(&self) -> impl Iterator<Item = Var> + '_ {
+        self.vars
+            .iter()
+            .chain(self.condition.iter().flat_map(|c| c.bits()))
+    }
+
+    /// Returns an error if the gate is classically controlled in a way that can't be
+    /// added to a ZX-diagram
+    ///
+    /// ZX-diagrams can only control Pauli gates, on the parity of some bits.
+    pub fn check_graph_condition(&self) -> Result<(), String> {
+        if self.condition.is_some() {
+            Err(format!(
+                "'{}' is controlled by a register of more than one bit, which a ZX-diagram \
+                 can't represent",
+                self.to_qasm()
+            ))
+        } else if self.is_conditional() && !self.is_pauli() {
+            Err(format!(
+                "'{}' is classically controlled, but a ZX-diagram can only represent \
+                 classically controlled Pauli gates",
+                self.to_qasm()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn adjoint(&mut self) {
         match self.t {
             ZPhase | XPhase | ParityPhase => {
@@ -209,6 +323,7 @@ impl Gate {
             qs,
             phase: phase.into(),
             vars: vars.into(),
+            condition: None,
         }
     }
 
@@ -228,6 +343,135 @@ impl Gate {
         circ.push(Gate::new(CNOT, vec![qs[0], qs[1]]));
     }
 
+    /// decompose a standard OpenQASM gate with no [`GType`] of its own, and push
+    /// the result on to the given circuit
+    ///
+    /// Parameters are given in units of pi, and are not reduced modulo 2, since
+    /// controlled rotations are sensitive to this. The decompositions are exact up
+    /// to global phase. Returns `false` if the gate is not in [`STD_GATES`], or is
+    /// given the wrong number of parameters or qubits.
+    pub fn push_std_gate_decomp(
+        circ: &mut Circuit,
+        name: &str,
+        params: &[Rational64],
+        qs: &[usize],
+    ) -> bool {
+        let Some(&(_, nparams, nqubits)) = STD_GATES.iter().find(|(n, _, _)| *n == name) else {
+            return false;
+        };
+        if params.len() != nparams || qs.len() != nqubits {
+            return false;
+        }
+
+        let half = Rational64::new(1, 2);
+        let z = |circ: &mut Circuit, q: usize, p: Rational64| {
+            circ.push(Gate::new_with_phase(ZPhase, vec![q], p))
+        };
+        let x = |circ: &mut Circuit, q: usize, p: Rational64| {
+            circ.push(Gate::new_with_phase(XPhase, vec![q], p))
+        };
+        let g = |circ: &mut Circuit, t: GType, qs: &[usize]| circ.push(Gate::new(t, qs.to_vec()));
+        let decomp = |circ: &mut Circuit, name: &str, params: &[Rational64], qs: &[usize]| {
+            Gate::push_std_gate_decomp(circ, name, params, qs);
+        };
+
+        match name {
+            "id" => {}
+            "y" => {
+                g(circ, Z, qs);
+                g(circ, NOT, qs);
+            }
+            "sx" => x(circ, qs[0], half),
+            "sxdg" => x(circ, qs[0], -half),
+            "p" | "phase" | "u1" => z(circ, qs[0], params[0]),
+            "ry" => {
+                x(circ, qs[0], half);
+                z(circ, qs[0], params[0]);
+                x(circ, qs[0], -half);
+            }
+            "u2" => decomp(circ, "U", &[half, params[0], params[1]], qs),
+            "u3" | "u" | "U" => {
+                z(circ, qs[0], params[2]);
+                decomp(circ, "ry", &params[..1], qs);
+                z(circ, qs[0], params[1]);
+            }
+            "cy" => {
+                g(circ, Sdg, &qs[1..]);
+                g(circ, CNOT, qs);
+                g(circ, S, &qs[1..]);
+            }
+            "ch" => {
+                g(circ, S, &qs[1..]);
+                g(circ, HAD, &qs[1..]);
+                g(circ, T, &qs[1..]);
+                g(circ, CNOT, qs);
+                g(circ, Tdg, &qs[1..]);
+                g(circ, HAD, &qs[1..]);
+                g(circ, Sdg, &qs[1..]);
+            }
+            "csx" => {
+                z(circ, qs[0], Rational64::new(1, 4));
+                decomp(circ, "crx", &[half], qs);
+            }
+            "cp" | "cphase" | "cu1" => {
+                z(circ, qs[0], params[0] * half);
+                decomp(circ, "crz", params, qs);
+            }
+            "crx" => {
+                g(circ, HAD, &qs[1..]);
+                decomp(circ, "crz", params, qs);
+                g(circ, HAD, &qs[1..]);
+            }
+            "cry" => {
+                decomp(circ, "ry", &[params[0] * half], &qs[1..]);
+                g(circ, CNOT, qs);
+                decomp(circ, "ry", &[-params[0] * half], &qs[1..]);
+                g(circ, CNOT, qs);
+            }
+            "crz" => {
+                z(circ, qs[1], params[0] * half);
+                g(circ, CNOT, qs);
+                z(circ, qs[1], -params[0] * half);
+                g(circ, CNOT, qs);
+            }
+            "cu3" => decomp(circ, "cu", &[params[0], params[1], params[2], 0.into()], qs),
+            "cu" => {
+                let (theta, phi, lambda) = (params[0], params[1], params[2]);
+                z(circ, qs[0], params[3] + (lambda + phi) * half);
+                z(circ, qs[1], (lambda - phi) * half);
+                g(circ, CNOT, qs);
+                decomp(
+                    circ,
+                    "U",
+                    &[-theta * half, 0.into(), -(phi + lambda) * half],
+                    &qs[1..],
+                );
+                g(circ, CNOT, qs);
+                decomp(circ, "U", &[theta * half, phi, 0.into()], &qs[1..]);
+            }
+            "rzz" => {
+                g(circ, CNOT, qs);
+                z(circ, qs[1], params[0]);
+                g(circ, CNOT, qs);
+            }
+            "rxx" => {
+                g(circ, HAD, &qs[..1]);
+                g(circ, HAD, &qs[1..]);
+                decomp(circ, "rzz", params, qs);
+                g(circ, HAD, &qs[..1]);
+                g(circ, HAD, &qs[1..]);
+            }
+            "cswap" => {
+                g(circ, CNOT, &[qs[2], qs[1]]);
+                g(circ, TOFF, qs);
+                g(circ, CNOT, &[qs[2], qs[1]]);
+            }
+            _ => unreachable!(),
+        }
+
+        true
+    }
+
     /// number of 1- and 2-qubit Clifford + phase gates needed to realise this gate
     pub fn num_basic_gates(&self) -> usize {
         match self.t {
@@ -248,6 +492,7 @@ impl Gate {
     ///
     /// If a gate is already basic, push a copy of itself.
     pub fn push_basic_gates(&self, circ: &mut Circuit) {
+        let start = circ.num_gates();
         match self.t {
             CCZ => {
                 Gate::push_ccz_decomp(circ, &self.qs);
@@ -271,6 +516,14 @@ impl Gate {
             }
             _ => circ.push(self.clone()),
         }
+
+        // every gate of a decomposition is controlled by the same condition
+        if self.is_conditional() {
+            for g in circ.gates.range_mut(start..) {
+                g.vars = self.vars.clone();
+                g.condition = self.condition;
+            }
+        }
     }
 
     fn add_spider<G: GraphLike>(
@@ -390,6 +643,10 @@ impl Gate {
         qs: &mut FxHashMap<usize, usize>,
         postselect: bool,
     ) -> Vec<V> {
+        if self.is_conditional() {
+            return self.add_conditional_to_graph(graph, qs);
+        }
+
         match self.t {
             ZPhase => Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, self.phase)
                 .into_iter()
@@ -682,9 +939,43 @@ impl Gate {
                 }
                 vec![]
             }
-            UnknownGate => {
+            Barrier | UnknownGate => {
                 vec![]
             }
         }
     }
+
+    /// Add a classically controlled Pauli gate as a spider whose phase depends on
+    /// the classical bits
+    ///
+    /// # Panics
+    ///
+    /// Panics if the condition can't be represented (see [`Gate::check_graph_condition`]).
+    fn add_conditional_to_graph(
+        &self,
+        graph: &mut impl GraphLike,
+        qs: &mut FxHashMap<usize, usize>,
+    ) -> Vec<V> {
+        if let Err(e) = self.check_graph_condition() {
+            panic!("{e}");
+        }
+        let ty = match self.t {
+            NOT | XPhase => VType::X,
+            Z | ZPhase => VType::Z,
+            _ => unreachable!("Pauli gates are X or Z gates"),
+        };
+
+        // a phase of 0 just gives the identity, regardless of the condition
+        if matches!(self.t, ZPhase | XPhase) && self.phase.is_zero() {
+            return vec![];
+        }
+
+        match Gate::add_spider(graph, qs, self.qs[0], ty, EType::N, Phase::zero()) {
+            Some(v) => {
+                graph.set_vars(v, self.vars.clone());
+                vec![v]
+            }
+            None => vec![],
+        }
+    }
 }
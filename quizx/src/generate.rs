@@ -18,6 +18,7 @@ use std::mem;
 
 use crate::circuit::*;
 use crate::gate::*;
+use crate::tableau::Tableau;
 use num::Rational64;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
@@ -49,6 +50,12 @@ pub struct RandomPauliGadgetCircuitBuilder {
     pub phase_denom: usize,
 }
 
+/// Samples Clifford circuits uniformly from the Clifford group, see [`Tableau::random`]
+pub struct RandomCliffordCircuitBuilder {
+    pub rng: StdRng,
+    pub qubits: usize,
+}
+
 pub struct SurfaceCodeCircuitBuilder {
     pub distance: usize,
     pub rounds: usize,
@@ -67,6 +74,10 @@ impl Circuit {
         Default::default()
     }
 
+    pub fn random_clifford() -> RandomCliffordCircuitBuilder {
+        Default::default()
+    }
+
     pub fn surface_code() -> SurfaceCodeCircuitBuilder {
         Default::default()
     }
@@ -111,6 +122,15 @@ impl Default for RandomPauliGadgetCircuitBuilder {
     }
 }
 
+impl Default for RandomCliffordCircuitBuilder {
+    fn default() -> Self {
+        RandomCliffordCircuitBuilder {
+            rng: StdRng::from_entropy(),
+            qubits: 0,
+        }
+    }
+}
+
 impl Default for SurfaceCodeCircuitBuilder {
     fn default() -> Self {
         SurfaceCodeCircuitBuilder {
@@ -330,6 +350,28 @@ impl RandomHiddenShiftCircuitBuilder {
     }
 }
 
+impl RandomCliffordCircuitBuilder {
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+    pub fn qubits(&mut self, qubits: usize) -> &mut Self {
+        self.qubits = qubits;
+        self
+    }
+
+    /// Samples a uniformly random Clifford tableau
+    pub fn build_tableau(&mut self) -> Tableau {
+        Tableau::random(self.qubits, &mut self.rng)
+    }
+
+    /// Samples a uniformly random Clifford, and synthesizes a circuit for it with
+    /// [`Tableau::to_circuit`]
+    pub fn build(&mut self) -> Circuit {
+        self.build_tableau().to_circuit()
+    }
+}
+
 impl RandomPauliGadgetCircuitBuilder {
     pub fn seed(&mut self, seed: u64) -> &mut Self {
         self.rng = StdRng::seed_from_u64(seed);
@@ -589,6 +631,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn random_clifford() {
+        let mut builder = Circuit::random_clifford();
+        builder.qubits(6).seed(1337);
+        let c1 = builder.build();
+        builder.seed(1337);
+        let t = builder.build_tableau();
+        assert_eq!(Tableau::from_circuit(&c1), Some(t));
+        builder.seed(1338);
+        assert_ne!(builder.build(), c1);
+    }
+
+    #[test]
+    fn random_clifford_uniform() {
+        // there are 24 single-qubit Cliffords, up to global phase, which should each
+        // come up about 100 times
+        let mut builder = Circuit::random_clifford();
+        builder.qubits(1).seed(42);
+        let mut counts = rustc_hash::FxHashMap::default();
+        for _ in 0..2400 {
+            *counts.entry(builder.build_tableau()).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 24);
+        assert!(counts.values().all(|&k| (50..150).contains(&k)));
+
+        // and 11520 two-qubit Cliffords
+        builder.qubits(2);
+        let mut seen = rustc_hash::FxHashSet::default();
+        for _ in 0..20000 {
+            seen.insert(builder.build_tableau());
+        }
+        assert!(seen.len() > 9000);
+    }
+
     #[test]
     fn random_hidden_shift() {
         // this could fail with some (small) probablity, so try some fixed seeds
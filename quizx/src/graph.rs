@@ -15,7 +15,7 @@
 // limitations under the License.
 
 use crate::linalg::Mat2;
-use crate::params::Expr;
+use crate::params::{Expr, Var};
 use crate::phase::Phase;
 use crate::util::*;
 use crate::{params::Parity, scalar::*};
@@ -162,6 +162,110 @@ impl Coord {
     }
 }
 
+/// Keeps track of which phases have been added together by the simplifier
+///
+/// This is used for phase teleportation, where the phases of a circuit are
+/// simplified, then written back into the original circuit. Phases are tracked by
+/// an index (e.g. the index of a gate in a circuit), and the tracker records which
+/// vertex each tracked phase currently lives on, and which tracked phases have been
+/// added together, up to a sign.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhaseTracker {
+    /// the index of the tracked phase on each vertex, and whether it is negated
+    vertices: FxHashMap<V, (usize, bool)>,
+    /// a union-find structure on indices, giving the parent of each index and
+    /// whether its phase is negated relative to the parent
+    parent: FxHashMap<usize, (usize, bool)>,
+}
+
+impl PhaseTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking the phase of `v`, with the given index
+    pub fn track(&mut self, v: V, index: usize) {
+        self.vertices.insert(v, (index, false));
+        self.parent.insert(index, (index, false));
+    }
+
+    /// The index of the tracked phase on `v`, if any, and whether it is negated
+    pub fn index(&self, v: V) -> Option<(usize, bool)> {
+        self.vertices.get(&v).copied()
+    }
+
+    /// The root of the set containing `i`, and whether `i` is negated relative to it
+    fn find(&self, mut i: usize) -> (usize, bool) {
+        let mut neg = false;
+        loop {
+            let (p, n) = self.parent[&i];
+            if p == i {
+                return (i, neg);
+            }
+            neg ^= n;
+            i = p;
+        }
+    }
+
+    /// Record that the phase of `v1` has been added to the phase of `v0`
+    pub fn fuse(&mut self, v0: V, v1: V) {
+        let Some((i1, n1)) = self.vertices.remove(&v1) else {
+            return;
+        };
+        let Some(&(i0, n0)) = self.vertices.get(&v0) else {
+            self.vertices.insert(v0, (i1, n1));
+            return;
+        };
+        let (r0, s0) = self.find(i0);
+        let (r1, s1) = self.find(i1);
+        if r0 != r1 {
+            self.parent.insert(r1, (r0, n0 ^ n1 ^ s0 ^ s1));
+        }
+    }
+
+    /// Record that the phase of `v` has been negated
+    pub fn negate(&mut self, v: V) {
+        if let Some((_, n)) = self.vertices.get_mut(&v) {
+            *n = !*n;
+        }
+    }
+
+    /// Record that the phase of `v0` has been moved to `v1`
+    pub fn move_phase(&mut self, v0: V, v1: V) {
+        if let Some(p) = self.vertices.remove(&v0) {
+            self.vertices.insert(v1, p);
+        }
+    }
+
+    /// Stop tracking the phase of `v`, e.g. because it has been removed
+    ///
+    /// Any phases it was added to stay in the same set.
+    pub fn remove(&mut self, v: V) {
+        self.vertices.remove(&v);
+    }
+
+    /// Rename vertices with the given function
+    pub fn rename(&mut self, f: impl Fn(V) -> V) {
+        self.vertices = self.vertices.drain().map(|(v, p)| (f(v), p)).collect();
+    }
+
+    /// The sets of indices whose phases have been added together, with whether each
+    /// one is negated relative to the others
+    ///
+    /// Each set is sorted by index, and the sets are sorted by their smallest index.
+    pub fn groups(&self) -> Vec<Vec<(usize, bool)>> {
+        let mut groups: FxHashMap<usize, Vec<(usize, bool)>> = FxHashMap::default();
+        for &i in self.parent.keys() {
+            let (r, n) = self.find(i);
+            groups.entry(r).or_default().push((i, n));
+        }
+        let mut groups: Vec<_> = groups.into_values().collect();
+        groups.iter_mut().for_each(|g| g.sort());
+        groups.sort();
+        groups
+    }
+}
+
 pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     /// Initialise a new empty graph
     fn new() -> Self;
@@ -283,6 +387,57 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     /// Insert (i.e. multiply) a new scalar factor `s^e` into the overall scalar
     fn mul_scalar_factor(&mut self, e: Expr, s: Scalar4);
 
+    /// Remove all of the parametrised scalar factors
+    ///
+    /// Graph types that store scalar factors should override this. The default
+    /// implementation panics if there are any.
+    fn clear_scalar_factors(&mut self) {
+        assert!(
+            self.scalar_factors().next().is_none(),
+            "clear_scalar_factors is not implemented for this graph type"
+        );
+    }
+
+    /// The phase tracker, if phases are being tracked
+    fn phase_tracker(&self) -> Option<&PhaseTracker> {
+        None
+    }
+
+    /// Mutable access to the phase tracker, if phases are being tracked
+    fn phase_tracker_mut(&mut self) -> Option<&mut PhaseTracker> {
+        None
+    }
+
+    /// Start or stop tracking phases, see [`PhaseTracker`]
+    ///
+    /// Graph types that support phase tracking should override this, along with
+    /// [`GraphLike::phase_tracker`] and [`GraphLike::phase_tracker_mut`]. By default,
+    /// phases are never tracked.
+    fn set_phase_tracker(&mut self, _tracker: Option<PhaseTracker>) {}
+
+    /// Record that the phase of `v1` has been added to the phase of `v0`, if phases
+    /// are being tracked
+    fn fuse_phases(&mut self, v0: V, v1: V) {
+        if let Some(t) = self.phase_tracker_mut() {
+            t.fuse(v0, v1);
+        }
+    }
+
+    /// Record that the phase of `v0` has been moved to `v1`, if phases are being
+    /// tracked
+    fn move_phase(&mut self, v0: V, v1: V) {
+        if let Some(t) = self.phase_tracker_mut() {
+            t.move_phase(v0, v1);
+        }
+    }
+
+    /// Record that the phase of `v` has been negated, if phases are being tracked
+    fn phase_negate(&mut self, v: V) {
+        if let Some(t) = self.phase_tracker_mut() {
+            t.negate(v);
+        }
+    }
+
     /// Returns the phase and any boolean variables at a vertex
     fn phase_and_vars(&self, v: V) -> (Phase, Parity) {
         let vd = self.vertex_data(v);
@@ -384,6 +539,33 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         self.vertex_data_mut(v).vars = vars1;
     }
 
+    /// Substitutes a concrete value for every boolean variable in the graph
+    ///
+    /// Vertices whose parity evaluates to 1 gain a pi phase, and scalar factors whose
+    /// expression evaluates to 1 are multiplied into the constant scalar. Afterwards, the
+    /// graph no longer depends on any variables.
+    fn plug_vars(&mut self, value: impl Fn(Var) -> bool) {
+        let vs: Vec<V> = self.vertices().collect();
+        for v in vs {
+            let vars = self.vars(v);
+            if vars.is_zero() {
+                continue;
+            }
+            if vars.eval(&value) {
+                self.add_to_phase(v, Rational64::one());
+            }
+            self.set_vars(v, Parity::zero());
+        }
+
+        let s: Scalar4 = self
+            .scalar_factors()
+            .filter(|(e, _)| e.eval(&value))
+            .map(|(_, s)| *s)
+            .fold(Scalar4::one(), |acc, s| acc * s);
+        *self.scalar_mut() *= s;
+        self.clear_scalar_factors();
+    }
+
     /// Add an edge to the graph
     fn add_edge(&mut self, s: V, t: V) {
         self.add_edge_with_type(s, t, EType::N);
@@ -0,0 +1,495 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hamiltonians given as weighted sums of Pauli strings, and their expectation values
+//!
+//! A [`Hamiltonian`] can be read from a text file with one term per line, consisting of a
+//! real coefficient followed by a Pauli string. The Pauli string is either dense, e.g.
+//! `XZIY`, or lists the non-identity Paulis with their qubits, e.g. `X0 Z3`. A line with
+//! only a coefficient is a multiple of the identity, and `#` starts a comment:
+//!
+//! ```text
+//! # H = -1.05 + 0.39 Z0 + 0.18 X0 X1
+//! -1.05
+//! 0.39 Z0
+//! 0.18 XX
+//! ```
+//!
+//! The same terms can be given in JSON, as a list of objects with a `coeff` and a `pauli`
+//! field, e.g. `[{"coeff": 0.39, "pauli": "Z0"}, {"coeff": 0.18, "pauli": "XX"}]`.
+
+use crate::circuit::Circuit;
+use crate::decompose::{Decomposer, Driver};
+use crate::detection_webs::Pauli;
+use crate::graph::{BasisElem, EType, GraphLike, VType};
+use crate::pauli::PauliString;
+use crate::simplify::full_simp;
+use num::Rational64;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An error that can occur when reading a Hamiltonian
+#[derive(Debug, derive_more::Display, derive_more::Error, derive_more::From)]
+pub enum HamiltonianError {
+    /// Error reading the Hamiltonian file.
+    #[display("IO error: {_0}")]
+    IO(std::io::Error),
+    /// The JSON is not a list of terms.
+    #[display("Invalid JSON: {_0}")]
+    Json(serde_json::Error),
+    /// A term could not be parsed.
+    #[display("Invalid term: {_0}")]
+    #[from(skip)]
+    Term(#[error(not(source))] String),
+}
+
+/// A term given by its coefficient, the number of qubits it needs and its non-identity Paulis
+type SparseTerm = (f64, usize, Vec<(usize, Pauli)>);
+
+/// A term of a Hamiltonian in JSON
+#[derive(Deserialize)]
+struct JsonTerm {
+    coeff: f64,
+    pauli: String,
+}
+
+/// A Hermitian operator, as a real linear combination of Pauli strings
+///
+/// All of the Paulis act on the same number of qubits and have a + sign. Terms are kept in
+/// the order they were added, and equal Paulis are not merged.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Hamiltonian {
+    nqubits: usize,
+    terms: Vec<(f64, PauliString)>,
+}
+
+impl Hamiltonian {
+    /// The zero operator on `nqubits` qubits
+    pub fn new(nqubits: usize) -> Self {
+        Hamiltonian {
+            nqubits,
+            terms: vec![],
+        }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.nqubits
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The coefficients and Paulis of the terms
+    pub fn terms(&self) -> impl Iterator<Item = (f64, &PauliString)> {
+        self.terms.iter().map(|(c, p)| (*c, p))
+    }
+
+    /// Adds `coeff * p`, moving the sign of `p` into the coefficient
+    ///
+    /// Panics if `p` is not Hermitian, or acts on the wrong number of qubits.
+    pub fn add_term(&mut self, coeff: f64, mut p: PauliString) {
+        assert_eq!(p.num_qubits(), self.nqubits, "Pauli has the wrong size");
+        assert!(p.is_hermitian(), "Pauli {p} is not Hermitian");
+        let coeff = if p.sign() == 2 { -coeff } else { coeff };
+        p.take_sign();
+        self.terms.push((coeff, p));
+    }
+
+    /// Builds a Hamiltonian from terms given as Paulis on a subset of the qubits
+    ///
+    /// The number of qubits is the smallest that fits all of the terms.
+    fn from_sparse_terms(terms: Vec<SparseTerm>) -> Self {
+        let nqubits = terms.iter().map(|(_, n, _)| *n).max().unwrap_or(0);
+        let mut h = Hamiltonian::new(nqubits);
+        for (coeff, _, ps) in terms {
+            let mut p = PauliString::identity(nqubits);
+            for (q, p1) in ps {
+                p.set(q, Some(p1));
+            }
+            h.add_term(coeff, p);
+        }
+        h
+    }
+
+    /// Reads a Hamiltonian from a list of terms in JSON
+    pub fn from_json(s: &str) -> Result<Self, HamiltonianError> {
+        let terms: Vec<JsonTerm> = serde_json::from_str(s)?;
+        let terms = terms
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| {
+                let (n, ps) = parse_paulis(&t.pauli)
+                    .map_err(|e| HamiltonianError::Term(format!("term {i}: {e}")))?;
+                Ok((t.coeff, n, ps))
+            })
+            .collect::<Result<_, HamiltonianError>>()?;
+        Ok(Self::from_sparse_terms(terms))
+    }
+
+    /// Reads a Hamiltonian from a file, in JSON if it has a `.json` extension and in the
+    /// text format otherwise
+    pub fn from_file(path: &Path) -> Result<Self, HamiltonianError> {
+        let s = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|e| e == "json") {
+            Self::from_json(&s)
+        } else {
+            s.parse()
+        }
+    }
+
+    /// Computes the expectation value of each Pauli of the Hamiltonian, without its
+    /// coefficient, in the output state of `c` applied to the all-zero state
+    ///
+    /// For Clifford circuits, the Paulis are pushed through the circuit instead of
+    /// decomposing a diagram. Otherwise, the diagram of the state is simplified once and
+    /// shared between the terms, which are computed in parallel, each with a clone of
+    /// `decomposer`. The clones share the memo table of the decomposer, if it has one.
+    ///
+    /// Panics if the Hamiltonian acts on more qubits than the circuit. If it acts on fewer,
+    /// the Paulis are extended with the identity.
+    pub fn term_expvals<G: GraphLike>(
+        &self,
+        c: &Circuit,
+        decomposer: &Decomposer<G>,
+        driver: &impl Driver,
+    ) -> Vec<f64> {
+        let n = c.num_qubits();
+        assert!(
+            self.nqubits <= n,
+            "Hamiltonian acts on {} qubits, but the circuit has {n}",
+            self.nqubits
+        );
+        let paulis: Vec<PauliString> = self
+            .terms
+            .iter()
+            .map(|(_, p)| {
+                let mut p1 = PauliString::identity(n);
+                for q in 0..self.nqubits {
+                    p1.set(q, p.get(q));
+                }
+                p1
+            })
+            .collect();
+
+        let adj = c.to_adjoint();
+        if let Some(ps) = paulis
+            .iter()
+            .map(|p| adj.conjugate_pauli(p))
+            .collect::<Option<Vec<_>>>()
+        {
+            return ps
+                .iter()
+                .map(|p| p.zero_state_expval().complex_value().re)
+                .collect();
+        }
+
+        let mut state: G = c.to_graph();
+        state.plug_inputs(&vec![BasisElem::Z0; n]);
+        full_simp(&mut state);
+        let state_adj = state.to_adjoint();
+        paulis
+            .par_iter()
+            .map(|p| {
+                // the state is normalised
+                if p.is_identity() {
+                    return 1.0;
+                }
+                let mut g = sandwich(&state, &state_adj, p);
+                full_simp(&mut g);
+                let mut d = decomposer.clone();
+                d.set_target(g)
+                    .decompose(driver)
+                    .scalar()
+                    .complex_value()
+                    .re
+            })
+            .collect()
+    }
+
+    /// Computes the expectation value of the Hamiltonian in the output state of `c` applied
+    /// to the all-zero state
+    ///
+    /// See [`Hamiltonian::term_expvals`] for how the terms are computed.
+    pub fn expval<G: GraphLike>(
+        &self,
+        c: &Circuit,
+        decomposer: &Decomposer<G>,
+        driver: &impl Driver,
+    ) -> f64 {
+        self.term_expvals(c, decomposer, driver)
+            .iter()
+            .zip(&self.terms)
+            .map(|(e, (coeff, _))| coeff * e)
+            .sum()
+    }
+}
+
+/// Parses a Pauli string, either dense or as a list of Paulis with their qubits
+///
+/// Returns the number of qubits the string needs, and its non-identity Paulis.
+fn parse_paulis(s: &str) -> Result<(usize, Vec<(usize, Pauli)>), String> {
+    let pauli = |c: char| match c.to_ascii_uppercase() {
+        'X' => Some(Pauli::X),
+        'Y' => Some(Pauli::Y),
+        'Z' => Some(Pauli::Z),
+        _ => None,
+    };
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    if let [t] = tokens[..] {
+        if t.chars()
+            .all(|c| c.eq_ignore_ascii_case(&'I') || pauli(c).is_some())
+        {
+            let ps = t
+                .chars()
+                .enumerate()
+                .filter_map(|(q, c)| Some((q, pauli(c)?)));
+            return Ok((t.len(), ps.collect()));
+        }
+    }
+
+    let mut ps: Vec<(usize, Pauli)> = vec![];
+    for t in tokens {
+        let mut chars = t.chars();
+        let p = chars.next().and_then(pauli);
+        let q = chars.as_str().parse::<usize>().ok();
+        let (Some(p), Some(q)) = (p, q) else {
+            return Err(format!("'{t}' is not a Pauli on a qubit, e.g. 'X3'"));
+        };
+        if ps.iter().any(|&(q1, _)| q1 == q) {
+            return Err(format!("qubit {q} appears twice in '{s}'"));
+        }
+        ps.push((q, p));
+    }
+    let n = ps.iter().map(|&(q, _)| q + 1).max().unwrap_or(0);
+    Ok((n, ps))
+}
+
+impl FromStr for Hamiltonian {
+    type Err = HamiltonianError;
+
+    /// Parses the text format, with one term per line
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut terms = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |e: String| HamiltonianError::Term(format!("line {}: {e}", i + 1));
+            let (coeff, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let coeff: f64 = coeff
+                .parse()
+                .map_err(|_| err(format!("'{coeff}' is not a number")))?;
+            let (n, ps) = parse_paulis(rest).map_err(err)?;
+            terms.push((coeff, n, ps));
+        }
+        Ok(Self::from_sparse_terms(terms))
+    }
+}
+
+impl fmt::Display for Hamiltonian {
+    /// Writes the text format, with dense Pauli strings
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (coeff, p) in &self.terms {
+            // the sign was moved into the coefficient by `add_term`
+            writeln!(f, "{coeff} {}", p.to_string().trim_start_matches('+'))?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the scalar diagram for `⟨ψ|P|ψ⟩`, where `state` is a diagram for `|ψ⟩` with an
+/// output for each qubit
+pub fn expval_graph<G: GraphLike>(state: &G, p: &PauliString) -> G {
+    sandwich(state, &state.to_adjoint(), p)
+}
+
+/// Applies `p` to the outputs of `state`, then plugs them into `state_adj`
+fn sandwich<G: GraphLike>(state: &G, state_adj: &G, p: &PauliString) -> G {
+    assert_eq!(
+        p.num_qubits(),
+        state.outputs().len(),
+        "Pauli has the wrong size"
+    );
+    let mut g = state.clone();
+    for (q, b) in state.outputs().iter().copied().enumerate() {
+        // Y = iXZ, so a Y is a Z spider followed by an X spider
+        let spiders: &[VType] = match p.get(q) {
+            None => continue,
+            Some(Pauli::X) => &[VType::X],
+            Some(Pauli::Y) => &[VType::Z, VType::X],
+            Some(Pauli::Z) => &[VType::Z],
+        };
+        let (mut v, mut et) = g.incident_edges(b).next().unwrap();
+        g.remove_edge(v, b);
+        for &t in spiders {
+            let w = g.add_vertex_with_phase(t, 1);
+            g.add_edge_with_type(v, w, et);
+            (v, et) = (w, EType::N);
+        }
+        g.add_edge(v, b);
+        if p.get(q) == Some(Pauli::Y) {
+            g.scalar_mut().mul_phase(Rational64::new(1, 2));
+        }
+    }
+    g.scalar_mut()
+        .mul_phase(Rational64::new(p.sign() as i64, 2));
+    g.plug(state_adj);
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompose::BssWithCatsDriver;
+    use crate::tensor::ToTensor;
+    use crate::vec_graph::Graph;
+    use approx::assert_abs_diff_eq;
+
+    #[test]
+    fn parse() {
+        let h: Hamiltonian = "# a comment\n\
+                              -1.5\n\
+                              0.5 XZIY\n\
+                              \n\
+                              2 X0 z4 # another comment\n"
+            .parse()
+            .unwrap();
+        assert_eq!(h.num_qubits(), 5);
+        assert_eq!(h.len(), 3);
+        assert_eq!(h.to_string(), "-1.5 IIIII\n0.5 XZIYI\n2 XIIIZ\n");
+        assert_eq!(h.to_string().parse::<Hamiltonian>().unwrap(), h);
+
+        let h1 = Hamiltonian::from_json(
+            r#"[{"coeff": -1.5, "pauli": ""}, {"coeff": 0.5, "pauli": "Y3 Z1 X0"},
+                {"coeff": 2, "pauli": "XIIIZ"}]"#,
+        )
+        .unwrap();
+        assert_eq!(h1, h);
+
+        for s in ["1.0 XQ", "abc X0", "1.0 X0 Z0", "1.0 X0Z1"] {
+            assert!(matches!(
+                s.parse::<Hamiltonian>(),
+                Err(HamiltonianError::Term(_))
+            ));
+        }
+        assert!(matches!(
+            Hamiltonian::from_json(r#"[{"coeff": 1.0}]"#),
+            Err(HamiltonianError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn add_term_sign() {
+        let mut h = Hamiltonian::new(2);
+        h.add_term(0.5, "-YX".parse().unwrap());
+        h.add_term(0.25, "ZZ".parse().unwrap());
+        let terms: Vec<_> = h.terms().map(|(c, p)| (c, p.to_string())).collect();
+        assert_eq!(
+            terms,
+            vec![(-0.5, "+YX".to_string()), (0.25, "+ZZ".to_string())]
+        );
+    }
+
+    #[test]
+    fn expval_graph_matches_tensor() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(3)
+            .depth(20)
+            .p_t(0.3)
+            .with_cliffords()
+            .build();
+        let mut state: Graph = c.to_graph();
+        state.plug_inputs(&[BasisElem::Z0; 3]);
+        let psi = state.to_tensorf();
+        for s in ["XYZ", "-IYI", "ZIX"] {
+            let p: PauliString = s.parse().unwrap();
+            let mut ppsi = psi.clone();
+            // apply the Pauli to the state vector, with qubit 0 as the first axis
+            for (i, x) in ppsi.indexed_iter_mut() {
+                let mut j = i.clone();
+                let mut phase = num::Complex::new(1.0, 0.0);
+                for q in 0..3 {
+                    let b = i[q];
+                    match p.get(q) {
+                        Some(Pauli::X) => j[q] = 1 - b,
+                        Some(Pauli::Y) => {
+                            j[q] = 1 - b;
+                            phase *= num::Complex::new(0.0, if b == 0 { -1.0 } else { 1.0 });
+                        }
+                        Some(Pauli::Z) if b == 1 => phase = -phase,
+                        _ => {}
+                    }
+                }
+                *x = phase * psi[j];
+            }
+            let sign = if p.sign() == 2 { -1.0 } else { 1.0 };
+            let expected: f64 = psi
+                .iter()
+                .zip(ppsi.iter())
+                .map(|(a, b)| (a.conj() * b).re)
+                .sum::<f64>()
+                * sign;
+            let g = expval_graph(&state, &p);
+            assert_abs_diff_eq!(g.to_tensorf()[[]].re, expected, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn expval() {
+        let h: Hamiltonian = "0.5\n1.5 Z0 Z1\n-0.75 XXI\n0.25 Y2".parse().unwrap();
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d: Decomposer<Graph> = Decomposer::empty();
+        d.with_full_simp();
+
+        // a Clifford circuit preparing a Bell pair
+        let mut c = Circuit::new(3);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        assert_eq!(h.term_expvals(&c, &d, &driver), vec![1.0, 1.0, 1.0, 0.0]);
+        assert_abs_diff_eq!(h.expval(&c, &d, &driver), 1.25);
+
+        // a non-Clifford circuit, checked against the doubled diagram of each term
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(4)
+            .depth(30)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let mut state: Graph = c.to_graph();
+        state.plug_inputs(&[BasisElem::Z0; 4]);
+        let mut expected = 0.0;
+        for (coeff, p) in h.terms() {
+            let mut p1 = PauliString::identity(4);
+            for q in 0..3 {
+                p1.set(q, p.get(q));
+            }
+            expected += coeff * expval_graph(&state, &p1).to_tensorf()[[]].re;
+        }
+        assert_abs_diff_eq!(h.expval(&c, &d, &driver), expected, epsilon = 1e-9);
+    }
+}
@@ -36,6 +36,7 @@ pub struct Graph {
     freshv: V,
     scalar: Scalar4,
     scalar_factors: FxHashMap<Expr, Scalar4>,
+    phase_tracker: Option<PhaseTracker>,
 }
 
 impl Graph {
@@ -59,6 +60,7 @@ impl GraphLike for Graph {
             freshv: 0,
             scalar: 1.into(),
             scalar_factors: FxHashMap::default(),
+            phase_tracker: None,
         }
     }
 
@@ -145,6 +147,9 @@ impl GraphLike for Graph {
 
         self.vdata.remove(&v);
         self.edata.remove(&v);
+        if let Some(t) = self.phase_tracker.as_mut() {
+            t.remove(v);
+        }
     }
 
     fn add_edge_with_type(&mut self, s: V, t: V, ety: EType) {
@@ -266,6 +271,22 @@ impl GraphLike for Graph {
         }
     }
 
+    fn clear_scalar_factors(&mut self) {
+        self.scalar_factors.clear();
+    }
+
+    fn phase_tracker(&self) -> Option<&PhaseTracker> {
+        self.phase_tracker.as_ref()
+    }
+
+    fn phase_tracker_mut(&mut self) -> Option<&mut PhaseTracker> {
+        self.phase_tracker.as_mut()
+    }
+
+    fn set_phase_tracker(&mut self, tracker: Option<PhaseTracker>) {
+        self.phase_tracker = tracker;
+    }
+
     #[inline]
     fn pack(&mut self, _force: bool) {}
 }
@@ -60,6 +60,7 @@ pub mod annealer;
 pub mod basic_rules;
 pub mod circuit;
 pub mod cli;
+pub mod cnot_synth;
 pub mod decompose;
 pub mod detection_webs;
 pub mod equality;
@@ -70,16 +71,25 @@ pub mod generate;
 pub mod graph;
 pub mod graph_loader;
 pub mod graph_to_svg;
+pub mod hamiltonian;
 pub mod hash_graph;
 pub mod json;
 pub mod linalg;
+pub mod marginals;
+pub mod noise;
 pub mod optimize_circuit;
 pub mod params;
+pub mod pauli;
 pub mod phase;
+pub mod phase_poly;
+pub mod qasm3;
 pub mod random_graph;
+pub mod routing;
 pub mod scalar;
 pub mod scalar_traits;
 pub mod simplify;
+pub mod tableau;
 pub mod tensor;
+pub mod tensor_network;
 pub mod util;
 pub mod vec_graph;
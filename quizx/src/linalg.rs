@@ -16,6 +16,7 @@
 
 //! Matrices and linear algebra over F2
 
+use crate::circuit::Circuit;
 use rustc_hash::FxHashMap;
 use std::cmp::min;
 use std::fmt;
@@ -368,6 +369,145 @@ impl Mat2 {
         }
         result
     }
+
+    /// Reduce an invertible matrix to the identity using the Patel/Markov/Hayes
+    /// algorithm, see:
+    ///
+    /// K. Patel, I. Markov, J. Hayes. Optimal Synthesis of Linear Reversible
+    /// Circuits. QIC 2008
+    ///
+    /// The matrix is made upper triangular by block-wise elimination, where duplicate
+    /// rows within each block of `blocksize` columns are cleared first. The same is
+    /// then done to the transpose, and the row operations from that are applied to the
+    /// matrix as column operations, in reverse. The row operations are also applied to
+    /// `x`, as in [`Mat2::gauss_x`].
+    ///
+    /// Returns `false`, leaving the matrix and `x` unchanged, if the matrix isn't square
+    /// and invertible.
+    pub fn pmh_x(&mut self, blocksize: usize, x: &mut impl RowOps) -> bool {
+        let Some(ops) = self.pmh_ops(blocksize) else {
+            return false;
+        };
+        for (r0, r1) in ops {
+            self.row_add(r0, r1);
+            x.row_add(r0, r1);
+        }
+        true
+    }
+
+    /// Compute the reduced echelon form with the Patel/Markov/Hayes algorithm
+    ///
+    /// This works for any matrix, and gives the same result as [`Mat2::gauss_x`] with
+    /// `full_reduce` set. The row operations are those that reduce the pivot columns,
+    /// padded out to an invertible matrix with unit vectors, to the identity using
+    /// [`Mat2::pmh_x`]. Every block size up to log2 of the number of rows is tried,
+    /// and the one that needs the fewest row operations is used. Returns the rank.
+    pub fn pmh_gauss_x(&mut self, x: &mut impl RowOps) -> usize {
+        let rows = self.num_rows();
+        let mut rref = self.clone();
+        let mut pivot_cols = vec![];
+        let rank = rref.gauss_helper(true, 3, &mut (), &mut pivot_cols);
+
+        // pad the pivot columns out with unit vectors on rows that are independent of
+        // the others, i.e. rows that aren't pivots of the transpose
+        let p = Mat2::build(rows, rank, |i, j| self[i][pivot_cols[j]] == 1);
+        let mut pt = p.transpose();
+        let mut row_pivots = vec![];
+        pt.gauss_helper(true, 3, &mut (), &mut row_pivots);
+        let free: Vec<_> = (0..rows).filter(|r| !row_pivots.contains(r)).collect();
+        let square = p.hstack(&Mat2::build(rows, free.len(), |i, j| free[j] == i));
+
+        let ops = square
+            .best_pmh_ops()
+            .expect("padded pivot columns should be invertible");
+        for (r0, r1) in ops {
+            self.row_add(r0, r1);
+            x.row_add(r0, r1);
+        }
+        rank
+    }
+
+    /// Synthesizes a CNOT circuit for an invertible parity matrix using the
+    /// Patel/Markov/Hayes algorithm
+    ///
+    /// The circuit takes each basis state |b> to |M b>. The block size is chosen as in
+    /// [`Mat2::pmh_gauss_x`]. Returns `None` if the matrix isn't square and invertible.
+    pub fn pmh_cnots(&self) -> Option<Circuit> {
+        // The CNOT for a row operation E acts on basis states as E^T, so row operations
+        // that reduce M^T to the identity give a circuit for M.
+        let mut c = Circuit::new(self.num_rows());
+        for (r0, r1) in self.transpose().best_pmh_ops()? {
+            c.row_add(r0, r1);
+        }
+        Some(c)
+    }
+
+    /// The row operations from [`Mat2::pmh_x`] for the block size that gives the fewest
+    fn best_pmh_ops(&self) -> Option<Vec<(usize, usize)>> {
+        let max_blocksize = self.num_rows().max(2).ilog2() as usize;
+        (1..=max_blocksize)
+            .map(|b| self.pmh_ops(b))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min_by_key(Vec::len)
+    }
+
+    /// The row operations, as pairs `(r0, r1)` adding r0 to r1, that reduce an
+    /// invertible matrix to the identity
+    fn pmh_ops(&self, blocksize: usize) -> Option<Vec<(usize, usize)>> {
+        assert!(blocksize > 0, "blocksize must be positive");
+        if self.num_rows() != self.num_cols() {
+            return None;
+        }
+
+        // L * M = U, and L' * U^T = id. So, (L'^T)^-1 = U and (L'^T * L) * M = id.
+        let mut m = self.clone();
+        let mut ops = vec![];
+        m.pmh_lower(blocksize, &mut ops)?;
+        let mut ops_t = vec![];
+        m.transpose().pmh_lower(blocksize, &mut ops_t)?;
+        ops.extend(ops_t.into_iter().rev().map(|(r0, r1)| (r1, r0)));
+        Some(ops)
+    }
+
+    /// Make a square matrix upper triangular with 1s on the diagonal, pushing the row
+    /// operations on to `ops`, or return `None` if it is singular
+    fn pmh_lower(&mut self, blocksize: usize, ops: &mut Vec<(usize, usize)>) -> Option<()> {
+        let n = self.num_rows();
+        for i0 in (0..n).step_by(blocksize) {
+            let i1 = min(n, i0 + blocksize);
+
+            // clear out duplicate sub-rows in this block
+            let mut chunks: FxHashMap<Vec<u8>, usize> = FxHashMap::default();
+            for r in i0..n {
+                let ch = self.d[r][i0..i1].to_vec();
+                if ch.iter().all(|&x| x == 0) {
+                    continue;
+                }
+                if let Some(&r1) = chunks.get(&ch) {
+                    self.row_add(r1, r);
+                    ops.push((r1, r));
+                } else {
+                    chunks.insert(ch, r);
+                }
+            }
+
+            for p in i0..i1 {
+                if self.d[p][p] == 0 {
+                    let r = (p + 1..n).find(|&r| self.d[r][p] != 0)?;
+                    self.row_add(r, p);
+                    ops.push((r, p));
+                }
+                for r in p + 1..n {
+                    if self.d[r][p] != 0 {
+                        self.row_add(p, r);
+                        ops.push((p, r));
+                    }
+                }
+            }
+        }
+        Some(())
+    }
 }
 
 impl RowOps for Mat2 {
@@ -477,6 +617,8 @@ impl std::ops::Mul<Mat2> for Mat2 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn mat_mul() {
@@ -588,6 +730,59 @@ mod tests {
         assert_eq!(vi_exp, vi);
     }
 
+    #[test]
+    fn pmh() {
+        let mut rng = StdRng::seed_from_u64(1337);
+        for n in [1, 4, 9, 16] {
+            let mut m = Mat2::id(n);
+            for _ in 0..n * n {
+                let (a, b) = (rng.gen_range(0..n), rng.gen_range(0..n));
+                if a != b {
+                    m.row_add(a, b);
+                }
+            }
+
+            for blocksize in 1..4 {
+                let mut m1 = m.clone();
+                let mut ops = Mat2::id(n);
+                assert!(m1.pmh_x(blocksize, &mut ops));
+                assert_eq!(m1, Mat2::id(n));
+                assert_eq!(ops, m.inverse().unwrap());
+            }
+
+            let c = m.pmh_cnots().expect("m should be invertible");
+            let mut m1 = Mat2::id(n);
+            for g in &c.gates {
+                m1.row_add(g.qs[0], g.qs[1]);
+            }
+            assert_eq!(m1, m);
+        }
+
+        let mut singular = Mat2::new(vec![vec![1, 1, 0], vec![0, 1, 1], vec![1, 0, 1]]);
+        assert!(singular.pmh_cnots().is_none());
+        assert!(!singular.pmh_x(1, &mut ()));
+        assert_eq!(singular.row_weight(0), 2);
+    }
+
+    #[test]
+    fn pmh_gauss() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for (rows, cols) in [(5, 8), (8, 5), (6, 6), (0, 3), (3, 0)] {
+            let m = Mat2::new(
+                (0..rows)
+                    .map(|_| (0..cols).map(|_| rng.gen_bool(0.4) as u8).collect())
+                    .collect(),
+            );
+            let mut m1 = m.clone();
+            let mut ops = Mat2::id(rows);
+            let rank = m1.pmh_gauss_x(&mut ops);
+            let mut rref = m.clone();
+            assert_eq!(rank, rref.gauss(true));
+            assert_eq!(m1, rref);
+            assert_eq!(&ops * &m, m1);
+        }
+    }
+
     #[test]
     fn test_nullspace() {
         // Test with a simple matrix that has a non-trivial nullspace
@@ -0,0 +1,227 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reduced density matrices and marginal distributions of the outputs of a circuit
+//!
+//! These are computed from the doubled diagram of the circuit applied to the all-zero state,
+//! where the outputs that are not of interest are traced out by connecting them to the outputs
+//! of the adjoint. Small diagrams can be evaluated with [`ToTensor`], while larger ones are
+//! computed entry by entry with a [`Decomposer`].
+//!
+//! Bit strings over the chosen qubits are numbered with the first qubit as the most
+//! significant bit.
+
+use crate::circuit::Circuit;
+use crate::decompose::{Decomposer, Driver};
+use crate::graph::{BasisElem, EType, GraphLike};
+use crate::simplify::full_simp;
+use crate::tensor::ToTensor;
+use ndarray::{Array2, Ix2};
+use num::Complex;
+
+/// Builds the reduced density matrix of `qubits` in the output of `c`, as a diagram
+///
+/// The resulting graph has an input and an output for each of the given qubits. The other
+/// qubits are traced out.
+pub fn reduced_density_graph<G: GraphLike>(c: &Circuit, qubits: &[usize]) -> G {
+    let n = c.num_qubits();
+    for (i, &q) in qubits.iter().enumerate() {
+        assert!(q < n, "Qubit {q} out of range");
+        assert!(!qubits[..i].contains(&q), "Qubit {q} given twice");
+    }
+
+    let mut g: G = c.to_graph();
+    g.plug_inputs(&vec![BasisElem::Z0; n]);
+    let adj = g.to_adjoint();
+    let vmap = g.append_graph(&adj);
+    let outputs = g.outputs().clone();
+    let adj_inputs: Vec<_> = adj.inputs().iter().map(|v| vmap[v]).collect();
+
+    for q in (0..n).filter(|q| !qubits.contains(q)) {
+        let (o, i) = (outputs[q], adj_inputs[q]);
+        let (no, et0) = g.incident_edges(o).next().unwrap();
+        let (ni, et1) = g.incident_edges(i).next().unwrap();
+        g.add_edge_smart(no, ni, EType::merge(et0, et1));
+        g.remove_vertex(o);
+        g.remove_vertex(i);
+    }
+    g.set_inputs(qubits.iter().map(|&q| adj_inputs[q]).collect());
+    g.set_outputs(qubits.iter().map(|&q| outputs[q]).collect());
+    g
+}
+
+/// Builds the scalar diagram for the entry `⟨row|ρ|col⟩` of a reduced density matrix
+///
+/// Here, `rho` is a graph returned by [`reduced_density_graph`]. For `row == col`, this is
+/// the probability of measuring the qubits as `row`.
+pub fn density_entry_graph<G: GraphLike>(rho: &G, row: &[bool], col: &[bool]) -> G {
+    let basis = |bits: &[bool]| -> Vec<BasisElem> {
+        bits.iter()
+            .map(|&b| if b { BasisElem::Z1 } else { BasisElem::Z0 })
+            .collect()
+    };
+    let mut g = rho.clone();
+    g.plug_inputs(&basis(col));
+    g.plug_outputs(&basis(row));
+    g
+}
+
+/// The bits of `i`, as a bit string of length `k` with the most significant bit first
+fn bits(i: usize, k: usize) -> Vec<bool> {
+    (0..k).rev().map(|j| i >> j & 1 == 1).collect()
+}
+
+/// Computes the reduced density matrix of `qubits` in the output of `c`, using [`ToTensor`]
+pub fn reduced_density_matrix<G: GraphLike>(c: &Circuit, qubits: &[usize]) -> Array2<Complex<f64>> {
+    let k = qubits.len();
+    let g: G = reduced_density_graph(c, qubits);
+    // the axes of the tensor are the inputs, followed by the outputs
+    let t = g
+        .to_tensor4()
+        .map(|x| x.complex_value())
+        .into_shape_with_order((1 << k, 1 << k))
+        .unwrap();
+    t.into_dimensionality::<Ix2>().unwrap().reversed_axes()
+}
+
+/// Computes the probabilities of all outcomes of measuring `qubits` in the output of `c`,
+/// using [`ToTensor`]
+pub fn marginal_probabilities<G: GraphLike>(c: &Circuit, qubits: &[usize]) -> Vec<f64> {
+    reduced_density_matrix::<G>(c, qubits)
+        .diag()
+        .iter()
+        .map(|p| p.re)
+        .collect()
+}
+
+/// Computes the reduced density matrix of `qubits` in the output of `c` with a decomposer
+///
+/// Each entry is computed as a separate scalar. Only the upper triangle is computed, as the
+/// matrix is Hermitian.
+pub fn reduced_density_matrix_decomposed<G: GraphLike>(
+    c: &Circuit,
+    qubits: &[usize],
+    decomposer: &mut Decomposer<G>,
+    driver: &impl Driver,
+) -> Array2<Complex<f64>> {
+    let k = qubits.len();
+    let rho: G = reduced_density_graph(c, qubits);
+    let mut m = Array2::zeros((1 << k, 1 << k));
+    for i in 0..1 << k {
+        for j in i..1 << k {
+            let mut g = density_entry_graph(&rho, &bits(i, k), &bits(j, k));
+            full_simp(&mut g);
+            let x = decomposer.set_target(g).decompose(driver).scalar();
+            m[[i, j]] = x.complex_value();
+            m[[j, i]] = x.complex_value().conj();
+        }
+    }
+    m
+}
+
+/// Computes the probabilities of all outcomes of measuring `qubits` in the output of `c`
+/// with a decomposer
+pub fn marginal_probabilities_decomposed<G: GraphLike>(
+    c: &Circuit,
+    qubits: &[usize],
+    decomposer: &mut Decomposer<G>,
+    driver: &impl Driver,
+) -> Vec<f64> {
+    let k = qubits.len();
+    let rho: G = reduced_density_graph(c, qubits);
+    (0..1 << k)
+        .map(|i| {
+            let mut g = density_entry_graph(&rho, &bits(i, k), &bits(i, k));
+            full_simp(&mut g);
+            let p = decomposer.set_target(g).decompose(driver).scalar();
+            p.complex_value().re
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompose::BssWithCatsDriver;
+    use crate::vec_graph::Graph;
+    use approx::assert_abs_diff_eq;
+
+    fn assert_close(a: &Array2<Complex<f64>>, b: &Array2<Complex<f64>>) {
+        assert_eq!(a.shape(), b.shape());
+        for (x, y) in a.iter().zip(b) {
+            assert_abs_diff_eq!(x.re, y.re, epsilon = 1e-9);
+            assert_abs_diff_eq!(x.im, y.im, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn bell_state() {
+        let mut c = Circuit::new(3);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 2]);
+        c.add_gate("x", vec![1]);
+
+        let rho = reduced_density_matrix::<Graph>(&c, &[0, 2]);
+        let half = Complex::new(0.5, 0.0);
+        let zero = Complex::new(0.0, 0.0);
+        let expected = ndarray::array![
+            [half, zero, zero, half],
+            [zero, zero, zero, zero],
+            [zero, zero, zero, zero],
+            [half, zero, zero, half],
+        ];
+        assert_close(&rho, &expected);
+
+        // tracing out half of a Bell pair gives the maximally mixed state
+        let rho = reduced_density_matrix::<Graph>(&c, &[2]);
+        assert_close(&rho, &ndarray::array![[half, zero], [zero, half]]);
+
+        // the order of the qubits determines the order of the bits
+        assert_eq!(
+            marginal_probabilities::<Graph>(&c, &[1, 0]),
+            vec![0.0, 0.0, 0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn decomposed() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(30)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let qubits = [3, 0];
+        let driver = BssWithCatsDriver { random_t: false };
+        let mut d = Decomposer::empty();
+        d.with_full_simp();
+
+        let rho = reduced_density_matrix::<Graph>(&c, &qubits);
+        let rho1 = reduced_density_matrix_decomposed::<Graph>(&c, &qubits, &mut d, &driver);
+        assert_close(&rho, &rho1);
+        assert_abs_diff_eq!(
+            rho.diag().iter().map(|x| x.re).sum::<f64>(),
+            1.0,
+            epsilon = 1e-9
+        );
+
+        let ps = marginal_probabilities_decomposed::<Graph>(&c, &qubits, &mut d, &driver);
+        for (p, x) in ps.iter().zip(rho.diag()) {
+            assert_abs_diff_eq!(*p, x.re, epsilon = 1e-9);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pauli noise for simulating noisy circuits
+//!
+//! A [`NoiseModel`] attaches single-qubit Pauli channels to the gates of a [`Circuit`]. Noisy
+//! circuits are simulated by Monte Carlo sampling: [`NoiseModel::sample_errors`] draws the
+//! Pauli errors of a single run of the circuit, and [`NoiseModel::apply_errors`] inserts them
+//! into the circuit as gates. Averaging the results of many such runs converges to the
+//! behaviour of the noisy circuit.
+
+use crate::circuit::Circuit;
+use crate::detection_webs::Pauli;
+use crate::gate::{GType, Gate};
+use rand::Rng;
+
+/// A single-qubit channel applying X, Y or Z with the given probabilities
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PauliChannel {
+    pub px: f64,
+    pub py: f64,
+    pub pz: f64,
+}
+
+impl PauliChannel {
+    pub fn new(px: f64, py: f64, pz: f64) -> Self {
+        assert!(
+            px >= 0.0 && py >= 0.0 && pz >= 0.0 && px + py + pz <= 1.0,
+            "Invalid Pauli channel probabilities"
+        );
+        PauliChannel { px, py, pz }
+    }
+
+    /// Applies each of X, Y and Z with probability `p/3`
+    pub fn depolarizing(p: f64) -> Self {
+        Self::new(p / 3.0, p / 3.0, p / 3.0)
+    }
+
+    /// Applies X with probability `p`
+    pub fn bit_flip(p: f64) -> Self {
+        Self::new(p, 0.0, 0.0)
+    }
+
+    /// Applies Z with probability `p`
+    pub fn phase_flip(p: f64) -> Self {
+        Self::new(0.0, 0.0, p)
+    }
+
+    pub fn is_noiseless(&self) -> bool {
+        self.px + self.py + self.pz == 0.0
+    }
+
+    /// Probabilities of I, X, Z and Y, indexed by the X and Z parts of the Pauli as bits
+    fn probs(&self) -> [f64; 4] {
+        [1.0 - self.px - self.py - self.pz, self.px, self.pz, self.py]
+    }
+
+    /// The channel applying `self`, followed by `other`
+    pub fn compose(&self, other: &PauliChannel) -> PauliChannel {
+        let (p, q) = (self.probs(), other.probs());
+        let mut r = [0.0; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                r[i ^ j] += p[i] * q[j];
+            }
+        }
+        PauliChannel {
+            px: r[1],
+            py: r[3],
+            pz: r[2],
+        }
+    }
+
+    /// Draws the error applied by one use of the channel, or `None` for no error
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<Pauli> {
+        let r: f64 = rng.gen();
+        if r < self.px {
+            Some(Pauli::X)
+        } else if r < self.px + self.py {
+            Some(Pauli::Y)
+        } else if r < self.px + self.py + self.pz {
+            Some(Pauli::Z)
+        } else {
+            None
+        }
+    }
+}
+
+/// A Pauli error in a run of a circuit, given as the number of gates before the error, the
+/// qubit and the Pauli
+pub type PauliError = (usize, usize, Pauli);
+
+/// Pauli noise on the gates and measurements of a circuit
+///
+/// After each gate, the channel for its type is applied independently to each of the qubits it
+/// acts on. Barriers are never noisy.
+#[derive(Debug, Clone, Default)]
+pub struct NoiseModel {
+    default: PauliChannel,
+    gates: Vec<(GType, PauliChannel)>,
+    measurement: PauliChannel,
+}
+
+impl NoiseModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a channel after every gate
+    pub fn with_default(&mut self, channel: PauliChannel) -> &mut Self {
+        self.default = self.default.compose(&channel);
+        self
+    }
+
+    /// Adds a channel after every gate of type `t`, on top of the default channel
+    pub fn with_gate(&mut self, t: GType, channel: PauliChannel) -> &mut Self {
+        self.gates.push((t, channel));
+        self
+    }
+
+    /// Adds a channel on every qubit at the end of the circuit, before it is measured
+    ///
+    /// Only X and Y errors change the outcome of a measurement in the Z basis.
+    pub fn with_measurement(&mut self, channel: PauliChannel) -> &mut Self {
+        self.measurement = self.measurement.compose(&channel);
+        self
+    }
+
+    /// The channel applied after gates of type `t`
+    pub fn channel(&self, t: GType) -> PauliChannel {
+        if t == GType::Barrier {
+            return PauliChannel::default();
+        }
+        self.gates
+            .iter()
+            .filter(|(t1, _)| *t1 == t)
+            .fold(self.default, |ch, (_, ch1)| ch.compose(ch1))
+    }
+
+    pub fn is_noiseless(&self) -> bool {
+        self.default.is_noiseless()
+            && self.measurement.is_noiseless()
+            && self.gates.iter().all(|(_, ch)| ch.is_noiseless())
+    }
+
+    /// Draws the Pauli errors of a single run of `c`
+    ///
+    /// Errors on measurements come after all of the gates.
+    pub fn sample_errors(&self, c: &Circuit, rng: &mut impl Rng) -> Vec<PauliError> {
+        let mut errors = vec![];
+        for (i, g) in c.gates.iter().enumerate() {
+            let channel = self.channel(g.t);
+            if channel.is_noiseless() {
+                continue;
+            }
+            for &q in &g.qs {
+                if let Some(p) = channel.sample(rng) {
+                    errors.push((i + 1, q, p));
+                }
+            }
+        }
+        for q in 0..c.num_qubits() {
+            if let Some(p) = self.measurement.sample(rng) {
+                errors.push((c.num_gates(), q, p));
+            }
+        }
+        errors
+    }
+
+    /// Returns a copy of `c` with the given errors inserted as gates
+    ///
+    /// Y errors are inserted as X followed by Z, which only differs from Y by a global phase.
+    pub fn apply_errors(c: &Circuit, errors: &[PauliError]) -> Circuit {
+        let mut errors = errors.to_vec();
+        errors.sort_by_key(|&(pos, _, _)| pos);
+        let mut errors = errors.into_iter().peekable();
+        let mut c1 = Circuit::new(c.num_qubits());
+        for pos in 0..=c.num_gates() {
+            if pos > 0 {
+                c1.push(c.gates[pos - 1].clone());
+            }
+            while let Some((_, q, p)) = errors.next_if(|&(pos1, _, _)| pos1 == pos) {
+                if p != Pauli::Z {
+                    c1.push(Gate::new(GType::NOT, vec![q]));
+                }
+                if p != Pauli::X {
+                    c1.push(Gate::new(GType::Z, vec![q]));
+                }
+            }
+        }
+        c1
+    }
+
+    /// Draws the errors of a single run of `c` and inserts them into the circuit
+    pub fn sample_circuit(&self, c: &Circuit, rng: &mut impl Rng) -> Circuit {
+        Self::apply_errors(c, &self.sample_errors(c, rng))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_abs_diff_eq;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn compose() {
+        let ch = PauliChannel::bit_flip(0.1).compose(&PauliChannel::phase_flip(0.2));
+        assert_abs_diff_eq!(ch.px, 0.08);
+        assert_abs_diff_eq!(ch.py, 0.02);
+        assert_abs_diff_eq!(ch.pz, 0.18);
+
+        // two bit flips cancel out
+        let ch = PauliChannel::bit_flip(0.5).compose(&PauliChannel::bit_flip(0.5));
+        assert_abs_diff_eq!(ch.px, 0.5);
+
+        let ch = PauliChannel::depolarizing(0.3).compose(&PauliChannel::default());
+        assert_eq!(ch, PauliChannel::depolarizing(0.3));
+    }
+
+    #[test]
+    fn sample_errors() {
+        let mut c = Circuit::new(2);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate("barrier", vec![0, 1]);
+        let mut noise = NoiseModel::new();
+        noise
+            .with_gate(GType::CNOT, PauliChannel::depolarizing(0.3))
+            .with_measurement(PauliChannel::bit_flip(0.1));
+        assert!(!noise.is_noiseless());
+        assert!(noise.channel(GType::HAD).is_noiseless());
+
+        let mut rng = StdRng::seed_from_u64(1337);
+        let runs = 10000;
+        let mut gate_errors = 0;
+        let mut measurement_errors = 0;
+        for _ in 0..runs {
+            for (pos, q, p) in noise.sample_errors(&c, &mut rng) {
+                assert!(q < 2);
+                if pos == 2 {
+                    gate_errors += 1;
+                } else {
+                    assert_eq!((pos, p), (3, Pauli::X));
+                    measurement_errors += 1;
+                }
+            }
+        }
+        assert_abs_diff_eq!(gate_errors as f64 / runs as f64, 0.6, epsilon = 0.05);
+        assert_abs_diff_eq!(measurement_errors as f64 / runs as f64, 0.2, epsilon = 0.05);
+    }
+
+    #[test]
+    fn apply_errors() {
+        let mut c = Circuit::new(2);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        assert_eq!(NoiseModel::apply_errors(&c, &[]), c);
+
+        let c1 = NoiseModel::apply_errors(&c, &[(2, 1, Pauli::Y), (0, 0, Pauli::X)]);
+        let mut expected = Circuit::new(2);
+        expected.add_gate("x", vec![0]);
+        expected.add_gate("h", vec![0]);
+        expected.add_gate("cx", vec![0, 1]);
+        expected.add_gate("x", vec![1]);
+        expected.add_gate("z", vec![1]);
+        assert_eq!(c1, expected);
+
+        let noise = NoiseModel::new();
+        let mut rng = StdRng::seed_from_u64(1337);
+        assert_eq!(noise.sample_circuit(&c, &mut rng), c);
+    }
+}
@@ -1,8 +1,546 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Gate-level peephole optimisation of circuits
+
 use crate::circuit::*;
+use crate::gate::*;
+use crate::graph::GraphLike;
+use crate::phase::Phase;
+use crate::simplify::full_simp;
+use crate::vec_graph::Graph;
+use num::{One, Rational64, Zero};
+
+/// Indices of the gates touching each qubit, in circuit order
+///
+/// Gates are never moved during optimisation, only removed (set to `None`) or
+/// replaced by a gate acting on the same set of qubits, so these lists stay valid
+/// for the whole run of the optimiser.
+struct Wires(Vec<Vec<usize>>);
+
+impl Wires {
+    fn new(nqubits: usize, gates: &[Option<Gate>]) -> Wires {
+        let mut w = vec![vec![]; nqubits];
+        for (i, g) in gates.iter().enumerate() {
+            if let Some(g) = g {
+                if g.qs.is_empty() {
+                    // a gate with no qubits is treated as a barrier on everything
+                    w.iter_mut().for_each(|ws| ws.push(i));
+                } else {
+                    for &q in &g.qs {
+                        w[q].push(i);
+                    }
+                }
+            }
+        }
+        Wires(w)
+    }
+
+    /// The index of the first remaining gate after `i` touching qubit `q`
+    fn next_on(&self, gates: &[Option<Gate>], q: usize, i: usize) -> Option<usize> {
+        let ws = &self.0[q];
+        let p = ws.partition_point(|&j| j <= i);
+        ws[p..].iter().copied().find(|&j| gates[j].is_some())
+    }
+
+    /// The index of the first remaining gate after `i` touching any of `qs`
+    fn next_on_any(&self, gates: &[Option<Gate>], qs: &[usize], i: usize) -> Option<usize> {
+        qs.iter().filter_map(|&q| self.next_on(gates, q, i)).min()
+    }
+}
+
+/// Rewrite all single-qubit phase gates as `ZPhase` or `XPhase`
+fn normalize(mut g: Gate) -> Gate {
+    let p = match g.t {
+        Z => Some(Rational64::one()),
+        S => Some(Rational64::new(1, 2)),
+        Sdg => Some(Rational64::new(-1, 2)),
+        T => Some(Rational64::new(1, 4)),
+        Tdg => Some(Rational64::new(-1, 4)),
+        _ => None,
+    };
+
+    if let Some(p) = p {
+        g.t = ZPhase;
+        g.phase = p.into();
+    } else if g.t == NOT {
+        g.t = XPhase;
+        g.phase = Phase::one();
+    }
+
+    g
+}
+
+/// Turn `ZPhase` and `XPhase` gates back into named gates where possible
+pub(crate) fn denormalize(mut g: Gate) -> Gate {
+    match g.t {
+        ZPhase => {
+            let p = g.phase.to_rational();
+            let t = if p == Rational64::one() {
+                Z
+            } else if p == Rational64::new(1, 2) {
+                S
+            } else if p == Rational64::new(-1, 2) {
+                Sdg
+            } else if p == Rational64::new(1, 4) {
+                T
+            } else if p == Rational64::new(-1, 4) {
+                Tdg
+            } else {
+                ZPhase
+            };
+
+            if t != ZPhase {
+                g.t = t;
+                g.phase = Phase::zero();
+            }
+        }
+        XPhase if g.phase.is_one() => {
+            g.t = NOT;
+            g.phase = Phase::zero();
+        }
+        _ => {}
+    }
+    g
+}
+
+/// Returns true if `g` can be moved past `h`
+fn commutes(g: &Gate, h: &Gate) -> bool {
+    if !g.qs.is_empty() && !h.qs.is_empty() && g.qs.iter().all(|q| !h.qs.contains(q)) {
+        return true;
+    }
+
+    match (g.t, h.t) {
+        (ZPhase, ZPhase) | (XPhase, XPhase) => true,
+        (ZPhase, CZ) | (CZ, ZPhase) | (CZ, CZ) => true,
+        // Z phases commute through CNOT controls, X phases through CNOT targets
+        (ZPhase, CNOT) => g.qs[0] == h.qs[0],
+        (CNOT, ZPhase) => g.qs[0] == h.qs[0],
+        (XPhase, CNOT) => g.qs[0] == h.qs[1],
+        (CNOT, XPhase) => g.qs[1] == h.qs[0],
+        (CNOT, CNOT) => g.qs[0] != h.qs[1] && g.qs[1] != h.qs[0],
+        (CNOT, CZ) => !h.qs.contains(&g.qs[1]),
+        (CZ, CNOT) => !g.qs.contains(&h.qs[1]),
+        _ => false,
+    }
+}
+
+/// Try to combine `g` with a later gate `h` on overlapping qubits
+///
+/// Returns `None` if the gates don't combine, `Some(None)` if they cancel, and
+/// `Some(Some(g1))` if they combine into a single gate `g1`.
+fn merge(g: &Gate, h: &Gate) -> Option<Option<Gate>> {
+    if g.is_conditional() || h.is_conditional() {
+        return None;
+    }
+
+    let same_set = || g.qs.len() == h.qs.len() && g.qs.iter().all(|q| h.qs.contains(q));
+    match (g.t, h.t) {
+        (ZPhase, ZPhase) | (XPhase, XPhase) if g.qs == h.qs => {
+            let p = g.phase + h.phase;
+            if p.is_zero() {
+                Some(None)
+            } else {
+                Some(Some(Gate::new_with_phase(g.t, g.qs.clone(), p)))
+            }
+        }
+        (HAD, HAD) | (CNOT, CNOT) if g.qs == h.qs => Some(None),
+        (CZ, CZ) | (SWAP, SWAP) if same_set() => Some(None),
+        _ => None,
+    }
+}
+
+/// Move gates forward as far as they commute, cancelling or merging them with
+/// the first gate they meet that they combine with
+fn cancel_and_merge(gates: &mut [Option<Gate>], wires: &Wires) -> bool {
+    let mut changed = false;
+    for i in 0..gates.len() {
+        let g = match &gates[i] {
+            Some(g) if !g.qs.is_empty() => g.clone(),
+            _ => continue,
+        };
+
+        let mut cur = i;
+        while let Some(j) = wires.next_on_any(gates, &g.qs, cur) {
+            let h = gates[j].as_ref().unwrap();
+            if let Some(m) = merge(&g, h) {
+                gates[i] = None;
+                gates[j] = m;
+                changed = true;
+                break;
+            } else if commutes(&g, h) {
+                cur = j;
+            } else {
+                break;
+            }
+        }
+    }
+    changed
+}
+
+/// Returns `i` if it is the index of a Hadamard gate
+fn had_at(gates: &[Option<Gate>], i: Option<usize>) -> Option<usize> {
+    let i = i?;
+    match &gates[i] {
+        Some(g) if g.t == HAD => Some(i),
+        _ => None,
+    }
+}
+
+/// Remove pairs of Hadamard gates by conjugating the gate in between them
+///
+/// Uses the rules H Z(a) H = X(a), H X(a) H = Z(a), H_t CNOT(c,t) H_t = CZ(c,t), and
+/// H_t CZ(c,t) H_t = CNOT(c,t). Applying the last two in turn also flips CNOTs with
+/// Hadamards on all four legs.
+fn push_hadamards(gates: &mut [Option<Gate>], wires: &Wires) -> bool {
+    let mut changed = false;
+    for i in 0..gates.len() {
+        let q = match &gates[i] {
+            Some(g) if g.t == HAD => g.qs[0],
+            _ => continue,
+        };
+
+        let Some(j) = wires.next_on(gates, q, i) else {
+            continue;
+        };
+        let Some(k) = had_at(gates, wires.next_on(gates, q, j)) else {
+            continue;
+        };
+
+        let h = gates[j].as_ref().unwrap();
+        let new_gate = match h.t {
+            ZPhase => Some(Gate {
+                t: XPhase,
+                ..h.clone()
+            }),
+            XPhase => Some(Gate {
+                t: ZPhase,
+                ..h.clone()
+            }),
+            CNOT if h.qs[1] == q => Some(Gate::new(CZ, h.qs.clone())),
+            CZ => {
+                let c = if h.qs[0] == q { h.qs[1] } else { h.qs[0] };
+                Some(Gate::new(CNOT, vec![c, q]))
+            }
+            _ => None,
+        };
+
+        if let Some(g) = new_gate {
+            gates[i] = None;
+            gates[k] = None;
+            gates[j] = Some(g);
+            changed = true;
+        }
+    }
+    changed
+}
 
 impl Circuit {
     /// Implements (roughly) `basic_optimization` from pyzx
+    ///
+    /// The circuit is decomposed into basic gates, then phase gates are commuted
+    /// through CNOT controls/targets and merged, adjacent self-inverse gates are
+    /// cancelled, and Hadamard gates are pushed through phases and CNOTs to cancel
+    /// in pairs. This is repeated until nothing changes. Gates the optimiser doesn't
+    /// know how to commute (e.g. measurements and barriers) are left in place and act
+    /// as barriers on their qubits. Classically controlled gates commute like the
+    /// Pauli they apply, but are never merged.
     pub fn optimize(&mut self) {
-        panic!("Not implemented.");
+        let mut gates: Vec<Option<Gate>> = self
+            .to_basic_gates()
+            .gates
+            .into_iter()
+            .map(|g| Some(normalize(g)))
+            .collect();
+
+        for g in gates.iter_mut() {
+            if let Some(Gate {
+                t: ZPhase | XPhase,
+                phase,
+                ..
+            }) = g
+            {
+                if phase.is_zero() {
+                    *g = None;
+                }
+            }
+        }
+
+        let wires = Wires::new(self.num_qubits(), &gates);
+        loop {
+            let merged = cancel_and_merge(&mut gates, &wires);
+            let pushed = push_hadamards(&mut gates, &wires);
+            if !merged && !pushed {
+                break;
+            }
+        }
+
+        self.gates = gates.into_iter().flatten().map(denormalize).collect();
+    }
+
+    /// Returns an optimised copy of the circuit, see [`Circuit::optimize`]
+    pub fn to_optimized(&self) -> Circuit {
+        let mut c = self.clone();
+        c.optimize();
+        c
+    }
+
+    /// Implements `teleport_reduce` from pyzx
+    ///
+    /// The circuit is decomposed into basic gates and converted to a graph, keeping
+    /// track of which non-Clifford phases get added together by [`full_simp`]. The
+    /// combined phases are then written back into the circuit: the first gate of each
+    /// set of fused phases gets their sum, and the rest are removed. This reduces the
+    /// T-count by the same amount as `full_simp`, but keeps the structure of the
+    /// original circuit, rather than extracting a new one.
+    pub fn teleport_reduce(&mut self) {
+        let c = self.to_basic_gates();
+        let mut g: Graph = c.to_graph_with_phase_tracking();
+        full_simp(&mut g);
+        let Some(tracker) = g.phase_tracker() else {
+            return;
+        };
+
+        let mut gates: Vec<Option<Gate>> =
+            c.gates.into_iter().map(|g| Some(normalize(g))).collect();
+        for group in tracker.groups() {
+            if group.len() < 2 {
+                continue;
+            }
+            let mut phase = Phase::zero();
+            for &(i, neg) in &group {
+                let p = gates[i].as_ref().map_or(Phase::zero(), |g| g.phase);
+                phase += if neg { -p } else { p };
+            }
+            let (i, neg) = group[0];
+            if let Some(g) = &mut gates[i] {
+                g.phase = if neg { -phase } else { phase };
+                if g.phase.is_zero() {
+                    gates[i] = None;
+                }
+            }
+            for &(i, _) in &group[1..] {
+                gates[i] = None;
+            }
+        }
+
+        self.gates = gates.into_iter().flatten().map(denormalize).collect();
+    }
+
+    /// Returns a copy of the circuit with its phases reduced, see
+    /// [`Circuit::teleport_reduce`]
+    pub fn to_teleport_reduced(&self) -> Circuit {
+        let mut c = self.clone();
+        c.teleport_reduce();
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+
+    #[test]
+    fn cancel_adjacent() {
+        let mut c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            h q[0];
+            h q[0];
+            cx q[0], q[1];
+            cx q[0], q[1];
+            cz q[0], q[1];
+            cz q[1], q[0];
+            x q[1];
+            x q[1];
+        "#,
+        )
+        .unwrap();
+        c.optimize();
+        assert_eq!(c.num_gates(), 0);
+    }
+
+    #[test]
+    fn commute_phases() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            rx(0.5*pi) q[1];
+            cx q[0], q[1];
+            t q[0];
+            rx(0.5*pi) q[1];
+        "#,
+        )
+        .unwrap();
+        let c1 = c.to_optimized();
+        assert_eq!(c1.num_gates(), 3);
+        assert_eq!(c1.num_gates_of_type(S), 1);
+        assert_eq!(c1.num_gates_of_type(NOT), 1);
+        assert!(Tensor4::scalar_compare(&c, &c1));
+    }
+
+    #[test]
+    fn push_hadamards() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[5];
+            h q[1];
+            cx q[0], q[1];
+            h q[1];
+            h q[2];
+            s q[2];
+            h q[2];
+            h q[3];
+            h q[4];
+            cx q[3], q[4];
+            h q[3];
+            h q[4];
+        "#,
+        )
+        .unwrap();
+        let c1 = c.to_optimized();
+        assert_eq!(c1.num_gates_of_type(HAD), 0);
+        assert_eq!(c1.num_gates(), 3);
+        assert!(Tensor4::scalar_compare(&c, &c1));
+    }
+
+    #[test]
+    fn barriers() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            h q[0];
+            t q[0];
+            t q[1];
+            measure_d q[1];
+            t q[1];
+        "#,
+        )
+        .unwrap();
+        let c1 = c.to_optimized();
+        assert_eq!(c1.num_gates_of_type(T), 4);
+    }
+
+    #[test]
+    fn classical_control() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            creg c[1];
+            t q[0];
+            barrier q[0], q[1];
+            t q[0];
+            measure q[1] -> c[0];
+            x q[0];
+            if(c==1) x q[0];
+            if(c==1) x q[0];
+            h q[0];
+            if(c==1) z q[0];
+            h q[0];
+            x q[0];
+        "#,
+        )
+        .unwrap();
+        let c1 = c.to_optimized();
+        assert_eq!(c1.num_gates_of_type(T), 2);
+        assert_eq!(c1.num_gates_of_type(Barrier), 1);
+        assert_eq!(c1.num_gates_of_type(HAD), 0);
+        assert_eq!(c1.num_gates_of_type(NOT), 3);
+        assert!(c1
+            .gates
+            .iter()
+            .filter(|g| g.t == NOT)
+            .all(|g| g.is_conditional()));
+        assert_eq!(c1.num_gates(), 7);
+    }
+
+    #[test]
+    fn random_optimize() {
+        for seed in [1337, 800, 40104, 42] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(100)
+                .p_t(0.2)
+                .p_cz(0.1)
+                .with_cliffords()
+                .build();
+            let c1 = c.to_optimized();
+            assert!(c1.num_gates() < c.num_gates());
+            assert!(Tensor4::scalar_compare(&c, &c1));
+        }
+    }
+
+    #[test]
+    fn optimize_extracted() {
+        use crate::extract::ToCircuit;
+
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(60)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let mut g: Graph = c.to_graph();
+        full_simp(&mut g);
+        let c1 = g.to_circuit().unwrap();
+        let c2 = c1.to_optimized();
+        assert!(c2.num_gates() <= c1.num_gates());
+        assert!(Tensor4::scalar_compare(&c, &c2));
+    }
+
+    #[test]
+    fn teleport_reduce() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            cx q[0], q[1];
+            h q[1];
+            t q[0];
+            h q[1];
+            cx q[0], q[1];
+            tdg q[1];
+            t q[0];
+            "#,
+        )
+        .unwrap();
+        let c1 = c.to_teleport_reduced();
+        assert_eq!(c.stats().tcount, 4);
+        assert_eq!(c1.stats().tcount, 2);
+        assert!(Tensor4::scalar_compare(&c, &c1));
+    }
+
+    #[test]
+    fn random_teleport_reduce() {
+        for seed in [1337, 800, 40104, 42] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(100)
+                .p_t(0.3)
+                .with_cliffords()
+                .build();
+            let c1 = c.to_teleport_reduced();
+            assert!(c1.stats().tcount < c.stats().tcount);
+            assert!(Tensor4::scalar_compare(&c, &c1));
+        }
     }
 }
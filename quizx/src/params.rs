@@ -69,10 +69,20 @@ impl Parity {
         Parity(self.0.clone(), !self.1)
     }
 
-    pub fn iter(&self) -> ParityIter {
+    pub fn iter(&self) -> ParityIter<'_> {
         let it = self.0.iter().copied();
         it
     }
+
+    /// Returns the constant term of the parity
+    pub fn flip(&self) -> bool {
+        self.1
+    }
+
+    /// Evaluates the parity for the given assignment of variables
+    pub fn eval(&self, value: impl Fn(Var) -> bool) -> bool {
+        self.iter().fold(self.1, |b, v| b ^ value(v))
+    }
 }
 
 impl Index<usize> for Parity {
@@ -187,9 +197,14 @@ impl Expr {
         self.len() == 1
     }
 
-    pub fn iter(&self) -> ExprIter {
+    pub fn iter(&self) -> ExprIter<'_> {
         self.0.iter()
     }
+
+    /// Evaluates the expression for the given assignment of variables
+    pub fn eval(&self, value: impl Fn(Var) -> bool) -> bool {
+        self.iter().all(|p| p.eval(&value))
+    }
 }
 
 impl Index<usize> for Expr {
@@ -226,4 +241,17 @@ mod tests {
         assert_eq!(&p2 + &p4, Parity::new([], true));
         assert_eq!(p3, p3.negated().negated());
     }
+
+    #[test]
+    fn eval() {
+        let p1 = Parity::new([0, 1, 3], false);
+        let p2 = Parity::new([2, 4], true);
+        let value = |v: Var| v == 1 || v == 2;
+        assert!(p1.eval(value));
+        assert!(!p1.negated().eval(value));
+        assert!(!p2.eval(value));
+        assert!(Parity::one().eval(value));
+        assert!(Expr::quadratic(p1.clone(), p2.negated()).eval(value));
+        assert!(!Expr::quadratic(p1.negated(), p2.negated()).eval(value));
+    }
 }
@@ -0,0 +1,666 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pauli strings, and their propagation through circuits
+//!
+//! A [`PauliString`] is a tensor product of Pauli operators with a phase. It can be
+//! pushed through a Clifford circuit gate by gate, which computes U P U† without
+//! building a tableau or a ZX-diagram. Pushing a Pauli through a Clifford+T circuit
+//! branches into a [`PauliSum`] at each T gate that doesn't commute with it.
+
+use crate::circuit::Circuit;
+use crate::detection_webs::Pauli;
+use crate::gate::*;
+use crate::scalar::Scalar4;
+use crate::scalar_traits::{FromPhase, Sqrt2};
+use num::{One, Zero};
+use rand::Rng;
+use rustc_hash::FxHashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Returns true if `g` is a unitary Clifford gate, or a barrier
+pub(crate) fn is_unitary_clifford(g: &Gate) -> bool {
+    !g.is_conditional()
+        && match g.t {
+            ZPhase | XPhase => g.phase.is_clifford(),
+            NOT | Z | S | Sdg | HAD | CNOT | CZ | XCX | SWAP | Barrier => true,
+            _ => false,
+        }
+}
+
+/// A Pauli operator on n qubits, with a phase that is a power of i
+///
+/// This is stored as i^phase X^x Z^z, where X^x and Z^z are tensor products of
+/// X and Z on the qubits where the bits of x and z are set. So, for example,
+/// Y = iXZ has `x` and `z` both set, and a phase of 1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PauliString {
+    pub(crate) x: Vec<bool>,
+    pub(crate) z: Vec<bool>,
+    /// the power of i, modulo 4
+    pub(crate) phase: u8,
+}
+
+impl PauliString {
+    /// The identity on `nqubits` qubits
+    pub fn identity(nqubits: usize) -> Self {
+        PauliString {
+            x: vec![false; nqubits],
+            z: vec![false; nqubits],
+            phase: 0,
+        }
+    }
+
+    /// X on qubit `q`, out of `nqubits`
+    pub fn x(nqubits: usize, q: usize) -> Self {
+        let mut p = PauliString::identity(nqubits);
+        p.x[q] = true;
+        p
+    }
+
+    /// Z on qubit `q`, out of `nqubits`
+    pub fn z(nqubits: usize, q: usize) -> Self {
+        let mut p = PauliString::identity(nqubits);
+        p.z[q] = true;
+        p
+    }
+
+    /// Y on qubit `q`, out of `nqubits`
+    pub fn y(nqubits: usize, q: usize) -> Self {
+        let mut p = PauliString::identity(nqubits);
+        p.x[q] = true;
+        p.z[q] = true;
+        p.phase = 1;
+        p
+    }
+
+    /// A uniformly random Pauli with a + sign, acting only on qubits `q` and higher
+    pub(crate) fn random_from(nqubits: usize, q: usize, rng: &mut impl Rng) -> Self {
+        let mut p = PauliString::identity(nqubits);
+        for q1 in q..nqubits {
+            p.x[q1] = rng.gen_bool(0.5);
+            p.z[q1] = rng.gen_bool(0.5);
+        }
+        p
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.x.len()
+    }
+
+    /// Returns true if the X part of the Pauli is set on qubit `q`
+    pub fn x_bit(&self, q: usize) -> bool {
+        self.x[q]
+    }
+
+    /// Returns true if the Z part of the Pauli is set on qubit `q`
+    pub fn z_bit(&self, q: usize) -> bool {
+        self.z[q]
+    }
+
+    /// The Pauli acting on qubit `q`, or `None` for the identity
+    pub fn get(&self, q: usize) -> Option<Pauli> {
+        match (self.x[q], self.z[q]) {
+            (false, false) => None,
+            (true, false) => Some(Pauli::X),
+            (true, true) => Some(Pauli::Y),
+            (false, true) => Some(Pauli::Z),
+        }
+    }
+
+    /// Sets the Pauli acting on qubit `q`, keeping the sign of the whole string
+    pub fn set(&mut self, q: usize, p: Option<Pauli>) {
+        let sign = self.sign();
+        (self.x[q], self.z[q]) = match p {
+            None => (false, false),
+            Some(Pauli::X) => (true, false),
+            Some(Pauli::Y) => (true, true),
+            Some(Pauli::Z) => (false, true),
+        };
+        self.phase = (sign + (self.num_ys() % 4) as u8) % 4;
+    }
+
+    /// The number of qubits the Pauli acts non-trivially on
+    pub fn weight(&self) -> usize {
+        (0..self.num_qubits())
+            .filter(|&q| self.x[q] || self.z[q])
+            .count()
+    }
+
+    /// The number of Y's in the Pauli
+    fn num_ys(&self) -> usize {
+        (0..self.num_qubits())
+            .filter(|&q| self.x[q] && self.z[q])
+            .count()
+    }
+
+    /// The overall phase of the Pauli, as a power of i, when it is written as a
+    /// tensor product of I, X, Y and Z
+    pub fn sign(&self) -> u8 {
+        (self.phase + 4 - (self.num_ys() % 4) as u8) % 4
+    }
+
+    /// Returns true if the operator is Hermitian, i.e. its sign is +1 or -1
+    pub fn is_hermitian(&self) -> bool {
+        self.sign() % 2 == 0
+    }
+
+    /// Returns true if the operator is a multiple of the identity
+    pub fn is_identity(&self) -> bool {
+        self.weight() == 0
+    }
+
+    /// Multiplies the Pauli by i^k
+    pub fn mul_phase(&mut self, k: u8) {
+        self.phase = (self.phase + k) % 4;
+    }
+
+    /// Returns true if the Pauli commutes with `other`
+    pub fn commutes(&self, other: &PauliString) -> bool {
+        let mut c = false;
+        for q in 0..self.num_qubits() {
+            c ^= (self.x[q] & other.z[q]) ^ (self.z[q] & other.x[q]);
+        }
+        !c
+    }
+
+    /// Sets the Pauli to `self * other`
+    pub fn mul_right(&mut self, other: &PauliString) {
+        // X^x1 Z^z1 X^x2 Z^z2 = (-1)^(z1.x2) X^(x1+x2) Z^(z1+z2)
+        let mut ph = self.phase + other.phase;
+        for q in 0..self.num_qubits() {
+            if self.z[q] && other.x[q] {
+                ph += 2;
+            }
+            self.x[q] ^= other.x[q];
+            self.z[q] ^= other.z[q];
+        }
+        self.phase = ph % 4;
+    }
+
+    /// Conjugates the Pauli by a gate, i.e. replaces P with G P G†
+    ///
+    /// Returns false and leaves the Pauli unchanged if the gate isn't a unitary
+    /// Clifford gate.
+    pub fn apply_gate(&mut self, g: &Gate) -> bool {
+        if !is_unitary_clifford(g) {
+            return false;
+        }
+
+        // the number of quarter turns of a Clifford phase gate
+        let quarter_turns = (g.phase.to_rational() * 2).to_integer().rem_euclid(4);
+        match g.t {
+            ZPhase => (0..quarter_turns).for_each(|_| self.s(g.qs[0])),
+            XPhase => {
+                self.h(g.qs[0]);
+                (0..quarter_turns).for_each(|_| self.s(g.qs[0]));
+                self.h(g.qs[0]);
+            }
+            NOT => self.pauli_x(g.qs[0]),
+            Z => self.pauli_z(g.qs[0]),
+            S => self.s(g.qs[0]),
+            Sdg => self.sdg(g.qs[0]),
+            HAD => self.h(g.qs[0]),
+            CNOT => self.cnot(g.qs[0], g.qs[1]),
+            CZ => self.cz(g.qs[0], g.qs[1]),
+            XCX => {
+                self.h(g.qs[0]);
+                self.cnot(g.qs[0], g.qs[1]);
+                self.h(g.qs[0]);
+            }
+            SWAP => self.swap(g.qs[0], g.qs[1]),
+            _ => {}
+        }
+        true
+    }
+
+    pub(crate) fn h(&mut self, q: usize) {
+        if self.x[q] && self.z[q] {
+            self.phase = (self.phase + 2) % 4;
+        }
+        std::mem::swap(&mut self.x[q], &mut self.z[q]);
+    }
+
+    pub(crate) fn s(&mut self, q: usize) {
+        if self.x[q] {
+            self.phase = (self.phase + 1) % 4;
+            self.z[q] ^= true;
+        }
+    }
+
+    pub(crate) fn sdg(&mut self, q: usize) {
+        if self.x[q] {
+            self.phase = (self.phase + 3) % 4;
+            self.z[q] ^= true;
+        }
+    }
+
+    pub(crate) fn pauli_x(&mut self, q: usize) {
+        if self.z[q] {
+            self.phase = (self.phase + 2) % 4;
+        }
+    }
+
+    pub(crate) fn pauli_z(&mut self, q: usize) {
+        if self.x[q] {
+            self.phase = (self.phase + 2) % 4;
+        }
+    }
+
+    pub(crate) fn cnot(&mut self, c: usize, t: usize) {
+        self.x[t] ^= self.x[c];
+        self.z[c] ^= self.z[t];
+    }
+
+    pub(crate) fn cz(&mut self, q0: usize, q1: usize) {
+        if self.x[q0] && self.x[q1] {
+            self.phase = (self.phase + 2) % 4;
+        }
+        self.z[q0] ^= self.x[q1];
+        self.z[q1] ^= self.x[q0];
+    }
+
+    pub(crate) fn swap(&mut self, q0: usize, q1: usize) {
+        self.x.swap(q0, q1);
+        self.z.swap(q0, q1);
+    }
+}
+
+impl fmt::Display for PauliString {
+    /// Writes the Pauli as a sign followed by a string of I, X, Y and Z, e.g. "-iXIZ"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = ["+", "+i", "-", "-i"][self.sign() as usize];
+        write!(f, "{sign}")?;
+        for q in 0..self.num_qubits() {
+            let c = match self.get(q) {
+                None => 'I',
+                Some(Pauli::X) => 'X',
+                Some(Pauli::Y) => 'Y',
+                Some(Pauli::Z) => 'Z',
+            };
+            write!(f, "{c}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PauliString {
+    type Err = String;
+
+    /// Parses a string of I, X, Y and Z, with an optional sign of "+", "-", "i",
+    /// "+i" or "-i" in front
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (sign, rest) = if let Some(r) = s.strip_prefix("-i") {
+            (3, r)
+        } else if let Some(r) = s.strip_prefix("+i").or_else(|| s.strip_prefix('i')) {
+            (1, r)
+        } else if let Some(r) = s.strip_prefix('-') {
+            (2, r)
+        } else {
+            (0, s.strip_prefix('+').unwrap_or(s))
+        };
+
+        let mut p = PauliString::identity(rest.len());
+        for (q, c) in rest.chars().enumerate() {
+            match c {
+                'I' => {}
+                'X' => p.x[q] = true,
+                'Z' => p.z[q] = true,
+                'Y' => {
+                    p.x[q] = true;
+                    p.z[q] = true;
+                    p.phase += 1;
+                }
+                _ => {
+                    return Err(format!(
+                        "'{c}' is not a Pauli. Expected one of 'I', 'X', 'Y', 'Z'."
+                    ))
+                }
+            }
+        }
+        p.phase = (p.phase + sign) % 4;
+        Ok(p)
+    }
+}
+
+impl PauliString {
+    /// Conjugates the Pauli by every gate of a circuit in turn, i.e. replaces P with
+    /// U P U†, where U is the unitary of the circuit
+    ///
+    /// Returns false and leaves the Pauli unchanged if the circuit isn't Clifford.
+    pub fn apply_circuit(&mut self, c: &Circuit) -> bool {
+        let c = c.to_basic_gates();
+        if !c.gates.iter().all(is_unitary_clifford) {
+            return false;
+        }
+        for g in &c.gates {
+            self.apply_gate(g);
+        }
+        true
+    }
+
+    /// Moves the phase of the Pauli into a scalar
+    ///
+    /// The Pauli is left as a tensor product of I, X, Y and Z, with a + sign, and the
+    /// old sign is returned.
+    pub fn take_sign(&mut self) -> Scalar4 {
+        let sign = self.sign();
+        self.phase = (self.num_ys() % 4) as u8;
+        i_pow(sign)
+    }
+
+    /// The expectation value of the Pauli in the all-zero state
+    ///
+    /// This is its sign if it is a tensor product of I and Z, and zero otherwise.
+    pub fn zero_state_expval(&self) -> Scalar4 {
+        if self.x.iter().any(|&b| b) {
+            Scalar4::zero()
+        } else {
+            i_pow(self.sign())
+        }
+    }
+}
+
+/// Returns i^k as a scalar
+fn i_pow(k: u8) -> Scalar4 {
+    Scalar4::from_phase(num::Rational64::new(k as i64, 2))
+}
+
+/// A linear combination of Pauli strings, with exact coefficients
+///
+/// The Paulis are stored with a + sign, and any phases are moved into the
+/// coefficients.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PauliSum {
+    terms: FxHashMap<PauliString, Scalar4>,
+}
+
+impl PauliSum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `coeff` times `p` to the sum
+    pub fn add_term(&mut self, coeff: Scalar4, mut p: PauliString) {
+        let coeff = coeff * p.take_sign();
+        let c = self.terms.entry(p).or_insert_with(Scalar4::zero);
+        *c += coeff;
+        if c.is_zero() {
+            self.terms.retain(|_, c| !c.is_zero());
+        }
+    }
+
+    /// The number of terms with non-zero coefficients
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Iterates over the Pauli strings in the sum and their coefficients
+    pub fn terms(&self) -> impl Iterator<Item = (&PauliString, &Scalar4)> {
+        self.terms.iter()
+    }
+
+    /// The coefficient of a Pauli string in the sum
+    pub fn coeff(&self, p: &PauliString) -> Scalar4 {
+        let mut p = p.clone();
+        let sign = p.take_sign();
+        self.terms
+            .get(&p)
+            .map_or(Scalar4::zero(), |&c| c * sign.conj())
+    }
+
+    /// Conjugates every term by a gate, i.e. replaces P with G P G†
+    ///
+    /// As well as Clifford gates, this supports Z and X phase gates whose angles are
+    /// odd multiples of π/4, such as T gates, which split each term that doesn't
+    /// commute with them in two. Returns false and leaves the sum unchanged for any
+    /// other gate.
+    pub fn apply_gate(&mut self, g: &Gate) -> bool {
+        if is_unitary_clifford(g) {
+            let terms = std::mem::take(&mut self.terms);
+            for (mut p, c) in terms {
+                p.apply_gate(g);
+                self.add_term(c, p);
+            }
+            return true;
+        }
+
+        // the rotation angle of a non-Clifford phase gate, in multiples of π/4
+        let k = match g.t {
+            T => 1,
+            Tdg => -1,
+            ZPhase | XPhase => {
+                let k = g.phase.to_rational() * 4;
+                if g.is_conditional() || !k.is_integer() {
+                    return false;
+                }
+                k.to_integer()
+            }
+            _ => return false,
+        };
+
+        let q = g.qs[0];
+        let had = Gate::new(HAD, vec![q]);
+        let s = Gate::new(S, vec![q]);
+        // cos and sin of kπ/4, which are ±1/√2 for odd k
+        let (cos, sin) = match k.rem_euclid(8) {
+            1 => (1, 1),
+            3 => (-1, 1),
+            5 => (-1, -1),
+            _ => (1, -1),
+        };
+        let half = Scalar4::sqrt2_pow(-1);
+
+        let terms = std::mem::take(&mut self.terms);
+        for (mut p, c) in terms {
+            if g.t == XPhase {
+                p.apply_gate(&had);
+            }
+            if p.x[q] {
+                // a Z rotation by θ sends P to cos(θ) P + sin(θ) S P S†
+                let mut p1 = p.clone();
+                p1.apply_gate(&s);
+                let mut p0 = p;
+                for (p, sign) in [(&mut p0, cos), (&mut p1, sin)] {
+                    if g.t == XPhase {
+                        p.apply_gate(&had);
+                    }
+                    let mut c1 = c * half;
+                    if sign < 0 {
+                        c1 *= Scalar4::minus_one();
+                    }
+                    self.add_term(c1, p.clone());
+                }
+            } else {
+                if g.t == XPhase {
+                    p.apply_gate(&had);
+                }
+                self.add_term(c, p);
+            }
+        }
+        true
+    }
+
+    /// The expectation value of the sum in the all-zero state
+    pub fn zero_state_expval(&self) -> Scalar4 {
+        self.terms
+            .iter()
+            .map(|(p, c)| p.zero_state_expval() * c)
+            .sum()
+    }
+}
+
+impl From<PauliString> for PauliSum {
+    fn from(p: PauliString) -> Self {
+        let mut s = PauliSum::new();
+        s.add_term(Scalar4::one(), p);
+        s
+    }
+}
+
+impl fmt::Display for PauliSum {
+    /// Writes one term per line, as the coefficient followed by the Pauli string
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms: Vec<_> = self.terms.iter().map(|(p, c)| (p.to_string(), c)).collect();
+        terms.sort_by(|a, b| a.0.cmp(&b.0));
+        for (p, c) in terms {
+            writeln!(f, "{c} {p}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Circuit {
+    /// Pushes a Pauli through a Clifford circuit, returning U P U†
+    ///
+    /// Returns `None` if the circuit isn't Clifford. To compute U† P U instead, e.g.
+    /// for expectation values, use the adjoint of the circuit.
+    pub fn conjugate_pauli(&self, p: &PauliString) -> Option<PauliString> {
+        let mut p = p.clone();
+        p.apply_circuit(self).then_some(p)
+    }
+
+    /// Pushes a Pauli through a Clifford+T circuit, returning U P U† as a sum of Paulis
+    ///
+    /// Each T gate (or other phase gate with an angle that is a multiple of π/4) that
+    /// doesn't commute with a term splits it in two, so the number of terms can grow
+    /// exponentially with the T-count. Returns `None` if the circuit has any other
+    /// non-Clifford gates.
+    pub fn conjugate_pauli_sum(&self, p: &PauliString) -> Option<PauliSum> {
+        let mut s = PauliSum::from(p.clone());
+        for g in &self.to_basic_gates().gates {
+            if !s.apply_gate(g) {
+                return None;
+            }
+        }
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tableau::Tableau;
+
+    #[test]
+    fn pauli_strings() {
+        let p: PauliString = "-XYZI".parse().unwrap();
+        assert_eq!(p.to_string(), "-XYZI");
+        assert_eq!(p.weight(), 3);
+        assert!(p.is_hermitian());
+
+        let mut p1: PauliString = "XX".parse().unwrap();
+        let p2: PauliString = "ZI".parse().unwrap();
+        assert!(!p1.commutes(&p2));
+        p1.mul_right(&p2);
+        assert_eq!(p1.to_string(), "-iYX");
+        assert!(!p1.is_hermitian());
+        assert!("XQ".parse::<PauliString>().is_err());
+
+        let mut p: PauliString = "-XZ".parse().unwrap();
+        assert_eq!(p.get(1), Some(Pauli::Z));
+        p.set(1, Some(Pauli::Y));
+        p.set(0, None);
+        assert_eq!(p.to_string(), "-IY");
+    }
+
+    #[test]
+    fn clifford_propagation() {
+        for seed in [1, 2, 3] {
+            let c = Circuit::random_clifford().seed(seed).qubits(4).build();
+            let t = Tableau::from_circuit(&c).unwrap();
+            for s in ["XIII", "-YZIX", "IZZY"] {
+                let p: PauliString = s.parse().unwrap();
+                assert_eq!(c.conjugate_pauli(&p), Some(t.conjugate(&p)));
+            }
+        }
+
+        let c = Circuit::from_qasm("qreg q[1]; t q[0];").unwrap();
+        assert_eq!(c.conjugate_pauli(&"X".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn t_branching() {
+        let c = Circuit::from_qasm("qreg q[1]; t q[0];").unwrap();
+        let s = c.conjugate_pauli_sum(&"X".parse().unwrap()).unwrap();
+        assert_eq!(s.len(), 2);
+        let half = Scalar4::sqrt2_pow(-1);
+        assert_eq!(s.coeff(&"X".parse().unwrap()), half);
+        assert_eq!(s.coeff(&"Y".parse().unwrap()), half);
+        assert_eq!(s.coeff(&"-Y".parse().unwrap()), half * Scalar4::minus_one());
+
+        // Z commutes with T
+        let s = c.conjugate_pauli_sum(&"Z".parse().unwrap()).unwrap();
+        assert_eq!(s, PauliSum::from("Z".parse::<PauliString>().unwrap()));
+
+        let c = Circuit::from_qasm("qreg q[1]; rz(0.1*pi) q[0];").unwrap();
+        assert!(c.conjugate_pauli_sum(&"X".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn clifford_t_roundtrip() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(4)
+            .depth(40)
+            .clifford_t(0.15)
+            .build();
+        let c_adj = c.to_adjoint();
+        let mut max_terms = 0;
+        for s in ["XIII", "IYZI", "ZZZZ"] {
+            let p: PauliString = s.parse().unwrap();
+            let s1 = c.conjugate_pauli_sum(&p).unwrap();
+            max_terms = max_terms.max(s1.len());
+
+            let mut s2 = PauliSum::new();
+            for (p1, &c1) in s1.terms() {
+                for (p2, &c2) in c_adj.conjugate_pauli_sum(p1).unwrap().terms() {
+                    s2.add_term(c1 * c2, p2.clone());
+                }
+            }
+            assert_eq!(s2, PauliSum::from(p));
+        }
+        assert!(max_terms > 1);
+    }
+
+    #[test]
+    fn expval() {
+        // T H |0> = (|0> + e^(iπ/4)|1>)/√2, which has <X> = cos(π/4)
+        let c = Circuit::from_qasm("qreg q[1]; h q[0]; t q[0];").unwrap();
+        let s = c
+            .to_adjoint()
+            .conjugate_pauli_sum(&"X".parse().unwrap())
+            .unwrap();
+        assert_eq!(s.zero_state_expval(), Scalar4::sqrt2_pow(-1));
+
+        let c = Circuit::from_qasm("qreg q[2]; x q[0]; cx q[0], q[1];").unwrap();
+        let p = c
+            .to_adjoint()
+            .conjugate_pauli(&"IZ".parse().unwrap())
+            .unwrap();
+        assert_eq!(p.zero_state_expval(), Scalar4::minus_one());
+        let p = c
+            .to_adjoint()
+            .conjugate_pauli(&"XX".parse().unwrap())
+            .unwrap();
+        assert_eq!(p.zero_state_expval(), Scalar4::zero());
+    }
+}
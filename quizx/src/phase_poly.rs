@@ -0,0 +1,648 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! T-count optimisation of phase polynomials
+//!
+//! A region of a circuit made of CNOT, NOT, SWAP, CZ and Z-phase gates acts on
+//! basis states as |x> -> e^{i f(x)} |A x + b>, where the phase polynomial f is a
+//! sum of phases on parities of the inputs. The T gates of f are the columns of a
+//! parity matrix, which is reduced with the TODD algorithm from Heyfron and
+//! Campbell, "An efficient quantum compiler that reduces T count" (2018). Any
+//! Clifford phases needed to make up the difference are worked out exactly, and
+//! the region is synthesized again with GraySynth, from Amy, Azimzadeh and Mosca,
+//! "On the CNOT-complexity of CNOT-phase circuits" (2018), followed by
+//! [`Mat2::pmh_cnots`] for the linear part.
+
+use crate::circuit::*;
+use crate::gate::*;
+use crate::linalg::{Mat2, RowOps};
+use crate::optimize_circuit::denormalize;
+use crate::params::Var;
+use crate::phase::Phase;
+use num::{Rational64, Zero};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A CNOT-phase circuit on `n` qubits, as a phase polynomial and an affine map
+struct PhasePoly {
+    /// phases on parities of the inputs, with no repeated or zero parities
+    terms: FxHashMap<Vec<u8>, Phase>,
+    /// the parity of the inputs on each qubit at the end
+    out: Mat2,
+    /// qubits that are flipped at the end, on top of `out`
+    flips: Vec<u8>,
+}
+
+impl PhasePoly {
+    fn new(n: usize) -> PhasePoly {
+        PhasePoly {
+            terms: FxHashMap::default(),
+            out: Mat2::id(n),
+            flips: vec![0; n],
+        }
+    }
+
+    /// Build the phase polynomial of a region, or return `None` if it contains a
+    /// gate that isn't a CNOT-phase gate
+    fn from_gates(n: usize, gates: &[Gate]) -> Option<PhasePoly> {
+        let mut p = PhasePoly::new(n);
+        for g in gates {
+            match g.t {
+                CNOT => {
+                    p.out.row_add(g.qs[0], g.qs[1]);
+                    p.flips[g.qs[1]] ^= p.flips[g.qs[0]];
+                }
+                SWAP => {
+                    p.out.row_swap(g.qs[0], g.qs[1]);
+                    p.flips.swap(g.qs[0], g.qs[1]);
+                }
+                NOT => p.flips[g.qs[0]] ^= 1,
+                Z | S | Sdg | T | Tdg | ZPhase => {
+                    let q = g.qs[0];
+                    p.add_phase(p.out[q].clone(), p.flips[q], z_phase(g));
+                }
+                CZ => {
+                    // CZ = e^{i pi/2 (x_a + x_b - x_a ⊕ x_b)}
+                    let (a, b) = (g.qs[0], g.qs[1]);
+                    let ab: Vec<u8> = p.out[a].iter().zip(&p.out[b]).map(|(x, y)| x ^ y).collect();
+                    let half = Phase::new(Rational64::new(1, 2));
+                    p.add_phase(p.out[a].clone(), p.flips[a], half);
+                    p.add_phase(p.out[b].clone(), p.flips[b], half);
+                    p.add_phase(ab, p.flips[a] ^ p.flips[b], -half);
+                }
+                _ => return None,
+            }
+        }
+        Some(p)
+    }
+
+    /// Add a phase on the parity `s`, or its negation if `flip` is set
+    ///
+    /// A phase on a negated parity is the negated phase on the parity, up to a global
+    /// phase.
+    fn add_phase(&mut self, s: Vec<u8>, flip: u8, phase: Phase) {
+        let phase = if flip == 1 { -phase } else { phase };
+        let p = self.terms.entry(s).or_insert(Phase::zero());
+        *p += phase;
+    }
+
+    fn tcount(&self) -> usize {
+        self.terms.values().filter(|p| p.is_t()).count()
+    }
+
+    /// Reduce the number of T phases with TODD, keeping the phase polynomial the same
+    fn reduce_tcount(&mut self) {
+        let n = self.out.num_rows();
+        let mut poly = Monomials::default();
+        let mut tcols = vec![];
+        let mut terms = FxHashMap::default();
+        for (s, p) in self.terms.drain() {
+            let k = p.to_rational() * 4;
+            if k.is_integer() {
+                let k = k.to_integer().rem_euclid(8);
+                poly.add_parity(&s, k);
+                if k % 2 == 1 {
+                    tcols.push(s);
+                }
+            } else {
+                terms.insert(s, p);
+            }
+        }
+        self.terms = terms;
+
+        todd(&mut tcols);
+
+        // whatever is left over after taking away the new T phases is Clifford, as long
+        // as TODD preserves the signature tensor, but it is synthesized exactly anyway
+        let quarter = Phase::new(Rational64::new(1, 4));
+        for s in tcols {
+            poly.add_parity(&s, -1);
+            self.add_phase(s, 0, quarter);
+        }
+        for (s, k) in poly.into_parities(n) {
+            self.add_phase(s, 0, quarter * k);
+        }
+        self.terms.retain(|_, p| !p.is_zero());
+    }
+
+    /// Synthesize the region with GraySynth
+    fn to_gates(&self) -> Vec<Gate> {
+        let n = self.out.num_rows();
+        let mut synth = GraySynth::new(n, &self.terms);
+        let mut queue = vec![(synth.pending(), (0..n).collect::<Vec<_>>(), None)];
+        while let Some((cols, rows, target)) = queue.pop() {
+            let cols: Vec<_> = cols.into_iter().filter(|&c| !synth.done[c]).collect();
+            if cols.is_empty() {
+                continue;
+            }
+
+            // make the target the only row of 1s, so the parities get closer to it
+            if let Some(i) = target {
+                while let Some(j) =
+                    (0..n).find(|&j| j != i && cols.iter().all(|&c| synth.s[j][c] == 1))
+                {
+                    synth.cnot(j, i);
+                }
+            }
+            let cols: Vec<_> = cols.into_iter().filter(|&c| !synth.done[c]).collect();
+            if cols.is_empty() || rows.is_empty() {
+                continue;
+            }
+
+            // split the parities on the row that divides them most unevenly
+            let ones = |j: usize| cols.iter().filter(|&&c| synth.s[j][c] == 1).count();
+            let j = *rows
+                .iter()
+                .max_by_key(|&&j| ones(j).max(cols.len() - ones(j)))
+                .unwrap();
+            let (cols1, cols0): (Vec<_>, Vec<_>) = cols.iter().partition(|&&c| synth.s[j][c] == 1);
+            let rows: Vec<_> = rows.into_iter().filter(|&r| r != j).collect();
+            queue.push((cols0, rows.clone(), target));
+            queue.push((cols1, rows, target.or(Some(j))));
+        }
+
+        // GraySynth should have placed every phase by now, but to be safe, any that are
+        // left get a CNOT ladder of their own
+        for c in synth.pending() {
+            if synth.done[c] {
+                continue;
+            }
+            let k = (0..n).find(|&k| synth.s[k][c] == 1).unwrap();
+            for j in k + 1..n {
+                if synth.s[j][c] == 1 {
+                    synth.cnot(j, k);
+                }
+            }
+        }
+
+        // then map the parities on each qubit to the outputs
+        let inv = synth
+            .wires
+            .inverse()
+            .expect("wire parities should be invertible");
+        let linear = (&self.out * &inv)
+            .pmh_cnots()
+            .expect("region should be invertible");
+        let mut gates = synth.gates;
+        gates.extend(linear.gates);
+        for (q, &f) in self.flips.iter().enumerate() {
+            if f == 1 {
+                gates.push(Gate::new(NOT, vec![q]));
+            }
+        }
+        gates
+    }
+}
+
+/// The phase of a Z-phase gate, in half-turns
+fn z_phase(g: &Gate) -> Phase {
+    match g.t {
+        Z => Phase::new(1),
+        S => Phase::new(Rational64::new(1, 2)),
+        Sdg => Phase::new(Rational64::new(-1, 2)),
+        T => Phase::new(Rational64::new(1, 4)),
+        Tdg => Phase::new(Rational64::new(-1, 4)),
+        _ => g.phase,
+    }
+}
+
+/// A phase polynomial in multiples of pi/4, in terms of monomials of degree up to 3
+///
+/// Since x_1 ⊕ ... ⊕ x_k = Σ_{S ⊆ [k], S ≠ ∅} (-2)^{|S|-1} Π_{i ∈ S} x_i, higher degree
+/// monomials vanish mod 8. Every quadratic coefficient is even and every cubic one is
+/// a multiple of 4.
+#[derive(Default)]
+struct Monomials(FxHashMap<Vec<usize>, i64>);
+
+impl Monomials {
+    /// Add `k` pi/4 times the parity `s`
+    fn add_parity(&mut self, s: &[u8], k: i64) {
+        let vs: Vec<_> = (0..s.len()).filter(|&i| s[i] == 1).collect();
+        for (a, &i) in vs.iter().enumerate() {
+            self.add(vec![i], k);
+            for (b, &j) in vs.iter().enumerate().skip(a + 1) {
+                self.add(vec![i, j], -2 * k);
+                for &l in &vs[b + 1..] {
+                    self.add(vec![i, j, l], 4 * k);
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, m: Vec<usize>, k: i64) {
+        let w = self.0.entry(m).or_insert(0);
+        *w = (*w + k).rem_euclid(8);
+    }
+
+    /// Write the polynomial as a sum of phases on parities, in multiples of pi/4
+    ///
+    /// This uses 4 x_i x_j x_k = (x_i ⊕ x_j ⊕ x_k) - x_i - x_j - x_k + 2 (x_i x_j +
+    /// x_i x_k + x_j x_k), then 2 x_i x_j = x_i + x_j - (x_i ⊕ x_j).
+    fn into_parities(mut self, n: usize) -> Vec<(Vec<u8>, i64)> {
+        let parity = |vs: &[usize]| -> Vec<u8> { (0..n).map(|i| vs.contains(&i) as u8).collect() };
+        let mut terms = vec![];
+        for degree in [3, 2, 1] {
+            let mut ms: Vec<_> = self
+                .0
+                .iter()
+                .filter(|(m, &w)| m.len() == degree && w != 0)
+                .map(|(m, &w)| (m.clone(), w))
+                .collect();
+            ms.sort();
+            for (m, w) in ms {
+                match degree {
+                    3 => {
+                        let c = w / 4;
+                        terms.push((parity(&m), c));
+                        for a in 0..3 {
+                            self.add(vec![m[a]], -c);
+                            for b in a + 1..3 {
+                                self.add(vec![m[a], m[b]], 2 * c);
+                            }
+                        }
+                    }
+                    2 => {
+                        let h = w / 2;
+                        terms.push((parity(&m), -h));
+                        self.add(vec![m[0]], h);
+                        self.add(vec![m[1]], h);
+                    }
+                    _ => terms.push((parity(&m), w)),
+                }
+            }
+        }
+        terms
+    }
+}
+
+/// Reduce the number of columns of a parity matrix without changing its signature
+/// tensor, using TODD
+///
+/// The columns are the parities with a T phase. For each pair of columns a, b and
+/// z = a ⊕ b, this looks for a vector y in the nullspace of the matrix χ(A, z) made
+/// of the rows of A and the rows z_i A_j ⊕ z_j A_i, for i < j, with y_a ≠ y_b. Adding
+/// z to the columns picked out by y (plus a new zero column, if there are an odd
+/// number of them) then makes columns a and b equal, so they can be removed.
+fn todd(cols: &mut Vec<Vec<u8>>) {
+    remove_pairs(cols);
+    while let Some((z, y)) = todd_step(cols) {
+        for (c, &yc) in y.iter().enumerate() {
+            if yc == 1 {
+                cols[c].iter_mut().zip(&z).for_each(|(a, b)| *a ^= b);
+            }
+        }
+        if y.iter().filter(|&&yc| yc == 1).count() % 2 == 1 {
+            cols.push(z);
+        }
+        remove_pairs(cols);
+    }
+}
+
+/// Find a vector z and a y in the nullspace of χ(A, z), as in [`todd`]
+fn todd_step(cols: &[Vec<u8>]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let m = cols.len();
+    if m < 2 {
+        return None;
+    }
+
+    // work with a basis for the rows of A, which is just as good and much smaller
+    let mut a = Mat2::build(cols[0].len(), m, |i, j| cols[j][i] == 1);
+    let rank = a.gauss(true);
+    let r = Mat2::build(rank, m, |i, j| a[i][j] == 1);
+
+    for i in 0..m {
+        for j in i + 1..m {
+            let z: Vec<u8> = (0..rank).map(|k| r[k][i] ^ r[k][j]).collect();
+            let mut rows: Vec<Vec<u8>> = (0..rank).map(|k| r[k].clone()).collect();
+            for k in 0..rank {
+                for l in k + 1..rank {
+                    if z[k] == 1 || z[l] == 1 {
+                        rows.push(
+                            (0..m)
+                                .map(|c| (z[k] & r[l][c]) ^ (z[l] & r[k][c]))
+                                .collect(),
+                        );
+                    }
+                }
+            }
+            if let Some(y) = Mat2::new(rows)
+                .nullspace()
+                .into_iter()
+                .find(|y| y[0][i] != y[0][j])
+            {
+                let z = cols[i].iter().zip(&cols[j]).map(|(x, y)| x ^ y).collect();
+                return Some((z, y[0].clone()));
+            }
+        }
+    }
+    None
+}
+
+/// Remove zero columns and pairs of equal columns
+fn remove_pairs(cols: &mut Vec<Vec<u8>>) {
+    let mut count: FxHashMap<Vec<u8>, usize> = FxHashMap::default();
+    for c in cols.drain(..) {
+        *count.entry(c).or_default() += 1;
+    }
+    cols.extend(
+        count
+            .into_iter()
+            .filter(|(c, k)| k % 2 == 1 && c.contains(&1))
+            .map(|(c, _)| c),
+    );
+    cols.sort();
+}
+
+/// State for GraySynth
+///
+/// The parities still to be placed are the columns of `s`, written in terms of the
+/// parities currently on the wires, so that a column e_i can be placed on wire i.
+struct GraySynth {
+    s: Mat2,
+    phases: Vec<Phase>,
+    done: Vec<bool>,
+    wires: Mat2,
+    gates: Vec<Gate>,
+}
+
+impl GraySynth {
+    fn new(n: usize, terms: &FxHashMap<Vec<u8>, Phase>) -> GraySynth {
+        let mut terms: Vec<_> = terms.iter().collect();
+        terms.sort_by_key(|&(s, _)| s);
+        let mut synth = GraySynth {
+            s: Mat2::build(n, terms.len(), |i, j| terms[j].0[i] == 1),
+            phases: terms.iter().map(|&(_, &p)| p).collect(),
+            done: vec![false; terms.len()],
+            wires: Mat2::id(n),
+            gates: vec![],
+        };
+        synth.place_phases();
+        synth
+    }
+
+    fn pending(&self) -> Vec<usize> {
+        (0..self.phases.len()).filter(|&c| !self.done[c]).collect()
+    }
+
+    /// Apply CNOT(j, i), i.e. add wire j to wire i
+    fn cnot(&mut self, j: usize, i: usize) {
+        self.gates.push(Gate::new(CNOT, vec![j, i]));
+        self.wires.row_add(j, i);
+        self.s.row_add(i, j);
+        self.place_phases();
+    }
+
+    /// Place every phase whose parity is on a wire
+    fn place_phases(&mut self) {
+        for c in 0..self.phases.len() {
+            if self.done[c] {
+                continue;
+            }
+            let mut ones = (0..self.s.num_rows()).filter(|&i| self.s[i][c] == 1);
+            if let (Some(q), None) = (ones.next(), ones.next()) {
+                self.done[c] = true;
+                let g = Gate::new_with_phase(ZPhase, vec![q], self.phases[c]);
+                self.gates.push(denormalize(g));
+            }
+        }
+    }
+}
+
+/// Returns `true` if the gate can be part of a phase polynomial region
+fn is_phase_poly_gate(g: &Gate) -> bool {
+    matches!(g.t, CNOT | SWAP | NOT | CZ | Z | S | Sdg | T | Tdg | ZPhase) && !g.is_conditional()
+}
+
+/// The qubits and classical bits used by gates that have been held back
+struct Blocked {
+    qubits: Vec<bool>,
+    bits: FxHashSet<Var>,
+}
+
+impl Blocked {
+    fn new(nqubits: usize) -> Self {
+        Blocked {
+            qubits: vec![false; nqubits],
+            bits: FxHashSet::default(),
+        }
+    }
+
+    /// Returns `true` if the gate touches a blocked qubit or bit
+    fn touches(&self, g: &Gate) -> bool {
+        g.qs.iter().any(|&q| self.qubits[q]) || g.bits().any(|v| self.bits.contains(&v))
+    }
+
+    /// Returns `true` if the gate touches a blocked qubit or bit, then blocks all of
+    /// its qubits and bits
+    ///
+    /// Bits are the ones a measurement writes to, or a conditional gate reads. A gate
+    /// with no qubits blocks all of the qubits.
+    fn block(&mut self, g: &Gate) -> bool {
+        let was_blocked = self.touches(g) || (g.qs.is_empty() && self.qubits.contains(&true));
+        if g.qs.is_empty() {
+            self.qubits.iter_mut().for_each(|b| *b = true);
+        }
+        g.qs.iter().for_each(|&q| self.qubits[q] = true);
+        self.bits.extend(g.bits());
+        was_blocked
+    }
+}
+
+impl Circuit {
+    /// Reduce the T-count by optimising the phase polynomials of CNOT-phase regions
+    ///
+    /// The circuit is decomposed into basic gates, then split into regions of CNOT,
+    /// NOT, SWAP, CZ and Z-phase gates, which are as large as possible, given that
+    /// other gates act as barriers on their qubits. The T phases of each region are
+    /// reduced with TODD, and the region is synthesized again with GraySynth. Regions
+    /// where this doesn't lower the T-count are left as they are. This is cubic in the
+    /// number of T gates in a region and quartic in the number of qubits, so it is
+    /// best used on circuits that have already been simplified.
+    ///
+    /// Returns the stats of the circuit before and after, see
+    /// [`CircuitStats::tcount`].
+    pub fn phase_poly_optimize(&mut self) -> (CircuitStats, CircuitStats) {
+        let before = self.stats();
+        let n = self.num_qubits();
+        let mut gates: Vec<Gate> = self.to_basic_gates().gates.into();
+        let mut out = Vec::with_capacity(gates.len());
+
+        while !gates.is_empty() {
+            // take the largest region at the start of the circuit, which commutes past
+            // the gates that are left
+            let mut blocked = Blocked::new(n);
+            let (mut region, mut rest) = (vec![], vec![]);
+            for g in gates {
+                if is_phase_poly_gate(&g) && !blocked.touches(&g) {
+                    region.push(g);
+                } else {
+                    blocked.block(&g);
+                    rest.push(g);
+                }
+            }
+
+            if let Some(mut p) = PhasePoly::from_gates(n, &region) {
+                let tcount = region.iter().filter(|g| z_phase(g).is_t()).count();
+                p.reduce_tcount();
+                if p.tcount() < tcount {
+                    region = p.to_gates();
+                }
+            }
+            out.extend(region);
+
+            // then take the other gates from the start of what is left
+            let mut blocked = Blocked::new(n);
+            gates = vec![];
+            for g in rest {
+                if is_phase_poly_gate(&g) {
+                    blocked.block(&g);
+                    gates.push(g);
+                } else if blocked.block(&g) {
+                    gates.push(g);
+                } else {
+                    out.push(g);
+                }
+            }
+        }
+
+        self.gates = out.into();
+        (before, self.stats())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+
+    fn tcount_after(c: &Circuit) -> usize {
+        let mut c1 = c.clone();
+        let (before, after) = c1.phase_poly_optimize();
+        assert_eq!(before, c.stats());
+        assert_eq!(after, c1.stats());
+        assert!(Tensor4::scalar_compare(c, &c1));
+        after.tcount
+    }
+
+    #[test]
+    fn merge_phases() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            cx q[0], q[1];
+            cx q[1], q[0];
+            cx q[0], q[1];
+            t q[1];
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.stats().tcount, 2);
+        assert_eq!(tcount_after(&c), 0);
+    }
+
+    #[test]
+    fn ccz_pair() {
+        // two CCZs with a qubit flipped in between make a CZ
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[3];
+            ccz q[0], q[1], q[2];
+            x q[0];
+            ccz q[0], q[1], q[2];
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.stats().tcount, 14);
+        assert_eq!(tcount_after(&c), 0);
+    }
+
+    #[test]
+    fn todd_reduces() {
+        // 15 T gates on all of the non-zero parities of 4 variables is equivalent
+        // to a Clifford, by the [[15,1,3]] quantum Reed-Muller code
+        let mut c = Circuit::new(4);
+        for s in 1..16usize {
+            let qs: Vec<_> = (0..4).filter(|&i| s & (1 << i) != 0).collect();
+            Gate::new_with_phase(ParityPhase, qs, Rational64::new(1, 4)).push_basic_gates(&mut c);
+        }
+        assert_eq!(c.stats().tcount, 15);
+        assert_eq!(tcount_after(&c), 0);
+    }
+
+    #[test]
+    fn random_circuits() {
+        for seed in [1337, 143, 105] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(40)
+                .p_t(0.3)
+                .with_cliffords()
+                .build();
+            assert!(tcount_after(&c) <= c.stats().tcount);
+        }
+
+        let c = Circuit::random_hidden_shift()
+            .seed(1337)
+            .qubits(6)
+            .n_ccz(3)
+            .build()
+            .0;
+        assert!(tcount_after(&c) <= c.stats().tcount);
+    }
+
+    #[test]
+    fn barriers() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            creg b[1];
+            t q[0];
+            h q[1];
+            measure q[1] -> b[0];
+            cx q[1], q[0];
+            t q[0];
+            if (b == 1) z q[0];
+            tdg q[0];
+            "#,
+        )
+        .unwrap();
+        let mut c1 = c.clone();
+        let (before, after) = c1.phase_poly_optimize();
+        assert_eq!(before.tcount, 3);
+        assert_eq!(after.tcount, 3);
+        assert_eq!(c1, c);
+    }
+
+    #[test]
+    fn conditional_after_measurement() {
+        // the conditional gate must stay after the measurement that writes its bit
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            creg b[1];
+            h q[1];
+            t q[1];
+            measure q[1] -> b[0];
+            if (b == 1) z q[0];
+            "#,
+        )
+        .unwrap();
+        let mut c1 = c.clone();
+        c1.phase_poly_optimize();
+        assert_eq!(c1, c);
+    }
+}
@@ -0,0 +1,1643 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reading and writing OpenQASM 3 circuits
+//!
+//! This supports the subset of OpenQASM 3 that maps onto [`Circuit`]: qubit and bit
+//! declarations (including the OpenQASM 2 style `qreg`/`creg`), `gate` definitions,
+//! gates from `stdgates.inc`, `measure`, `reset`, `barrier`, and gates conditioned
+//! with `if` on a single bit or on the value of a register. Other classical control
+//! flow, subroutines, gate modifiers and the like are rejected with a
+//! [`Qasm3Error::Unsupported`] error.
+//!
+//! Global phases (including explicit `gphase` calls) are dropped, as they are
+//! everywhere else in [`Circuit`].
+
+use crate::circuit::{
+    Circuit, ClassicalRegisters, QasmAngle, QasmCondition, QasmImport, QasmOptions,
+};
+use crate::gate::*;
+use crate::params::{Parity, Var};
+use crate::phase::Phase;
+use derive_more::{Display, Error, From};
+use num::traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub};
+use num::{One, Rational64, ToPrimitive, Zero};
+use rustc_hash::FxHashMap;
+use std::fmt::Write;
+
+/// An error that can occur when reading an OpenQASM 3 program.
+#[derive(Debug, Display, Error, From)]
+#[non_exhaustive]
+pub enum Qasm3Error {
+    /// The program is not valid OpenQASM.
+    #[display("line {line}: syntax error: {msg}")]
+    Syntax { line: usize, msg: String },
+    /// The program is valid, but uses a feature that can't be represented as a [`Circuit`].
+    #[display("line {line}: unsupported: {msg}")]
+    Unsupported { line: usize, msg: String },
+    /// The program refers to something undefined, or uses something inconsistently.
+    #[display("line {line}: {msg}")]
+    Invalid { line: usize, msg: String },
+    /// An angle is not a rational multiple of pi, and [`QasmOptions::exact`] is set.
+    #[display("line {line}: angle {angle}*pi is not a rational multiple of pi")]
+    InexactAngle { line: usize, angle: f64 },
+    /// Error reading the input file.
+    #[display("can't read file: {_0}")]
+    #[from]
+    IO(std::io::Error),
+}
+
+type Result<T> = std::result::Result<T, Qasm3Error>;
+
+fn syntax<T>(line: usize, msg: impl Into<String>) -> Result<T> {
+    Err(Qasm3Error::Syntax {
+        line,
+        msg: msg.into(),
+    })
+}
+
+fn unsupported<T>(line: usize, msg: impl Into<String>) -> Result<T> {
+    Err(Qasm3Error::Unsupported {
+        line,
+        msg: msg.into(),
+    })
+}
+
+fn invalid<T>(line: usize, msg: impl Into<String>) -> Result<T> {
+    Err(Qasm3Error::Invalid {
+        line,
+        msg: msg.into(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Num(String),
+    Str(String),
+    Sym(&'static str),
+    Eof,
+}
+
+impl std::fmt::Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tok::Ident(s) | Tok::Num(s) => write!(f, "'{s}'"),
+            Tok::Str(s) => write!(f, "\"{s}\""),
+            Tok::Sym(s) => write!(f, "'{s}'"),
+            Tok::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+}
+
+const SYMBOLS: [&str; 26] = [
+    "->", "==", "!=", "<=", ">=", "**", "&&", "||", ";", ",", "(", ")", "[", "]", "{", "}", "=",
+    "+", "-", "*", "/", "@", "<", ">", ":", "!",
+];
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let cs: Vec<char> = src.chars().collect();
+    let mut toks = vec![];
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < cs.len() {
+        let c = cs[i];
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && cs.get(i + 1) == Some(&'/') {
+            while i < cs.len() && cs[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && cs.get(i + 1) == Some(&'*') {
+            let start = line;
+            i += 2;
+            loop {
+                if i + 1 >= cs.len() {
+                    return syntax(start, "unterminated comment");
+                }
+                if cs[i] == '*' && cs[i + 1] == '/' {
+                    i += 2;
+                    break;
+                }
+                if cs[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < cs.len() && (cs[i].is_alphanumeric() || cs[i] == '_') {
+                i += 1;
+            }
+            toks.push(Token {
+                tok: Tok::Ident(cs[start..i].iter().collect()),
+                line,
+            });
+        } else if c.is_ascii_digit()
+            || (c == '.' && cs.get(i + 1).is_some_and(char::is_ascii_digit))
+        {
+            let start = i;
+            while i < cs.len() && (cs[i].is_ascii_digit() || cs[i] == '.') {
+                i += 1;
+            }
+            if i < cs.len() && (cs[i] == 'e' || cs[i] == 'E') {
+                i += 1;
+                if i < cs.len() && (cs[i] == '+' || cs[i] == '-') {
+                    i += 1;
+                }
+                while i < cs.len() && cs[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            toks.push(Token {
+                tok: Tok::Num(cs[start..i].iter().collect()),
+                line,
+            });
+        } else if c == '"' {
+            let start = i + 1;
+            i += 1;
+            while i < cs.len() && cs[i] != '"' && cs[i] != '\n' {
+                i += 1;
+            }
+            if i >= cs.len() || cs[i] != '"' {
+                return syntax(line, "unterminated string");
+            }
+            toks.push(Token {
+                tok: Tok::Str(cs[start..i].iter().collect()),
+                line,
+            });
+            i += 1;
+        } else {
+            let sym = SYMBOLS.iter().find(|s| {
+                s.chars()
+                    .enumerate()
+                    .all(|(j, sc)| cs.get(i + j) == Some(&sc))
+            });
+            if let Some(s) = sym {
+                toks.push(Token {
+                    tok: Tok::Sym(s),
+                    line,
+                });
+                i += s.len();
+            } else {
+                return syntax(line, format!("unexpected character '{c}'"));
+            }
+        }
+    }
+
+    toks.push(Token {
+        tok: Tok::Eof,
+        line,
+    });
+    Ok(toks)
+}
+
+/// An angle of the form `a + b * pi`, where `a` and `b` are rational
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Angle {
+    a: Rational64,
+    b: Rational64,
+}
+
+impl Angle {
+    fn constant(a: Rational64) -> Angle {
+        Angle {
+            a,
+            b: Rational64::zero(),
+        }
+    }
+
+    fn pi() -> Angle {
+        Angle {
+            a: Rational64::zero(),
+            b: Rational64::one(),
+        }
+    }
+}
+
+/// Parse a numeric literal exactly, falling back to a floating point
+/// approximation if it has too many digits, unless `exact` is set
+fn parse_number(s: &str, line: usize, exact: bool) -> Result<Rational64> {
+    let (mantissa, exp) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "0"),
+    };
+    let exp: i32 = exp
+        .parse()
+        .or_else(|_| syntax(line, format!("bad number '{s}'")))?;
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    if frac.contains('.') {
+        return syntax(line, format!("bad number '{s}'"));
+    }
+
+    let exact_value = || -> Option<Rational64> {
+        let digits: i64 = format!("{int}{frac}").parse().ok()?;
+        let shift = exp - frac.len() as i32;
+        let p = 10i64.checked_pow(shift.unsigned_abs())?;
+        if shift >= 0 {
+            Some(Rational64::from_integer(digits.checked_mul(p)?))
+        } else {
+            Some(Rational64::new(digits, p))
+        }
+    };
+
+    match exact_value() {
+        Some(r) => Ok(r),
+        None if exact => unsupported(
+            line,
+            format!("number '{s}' has too many digits to be read exactly"),
+        ),
+        None => s
+            .parse::<f64>()
+            .ok()
+            .and_then(Rational64::approximate_float)
+            .map_or_else(|| syntax(line, format!("bad number '{s}'")), Ok),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(Rational64),
+    Pi,
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(&'static str, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, env: &FxHashMap<String, Angle>, line: usize) -> Result<Angle> {
+        let overflow = || Qasm3Error::Unsupported {
+            line,
+            msg: "angle expression is too large".into(),
+        };
+        let nonlinear = || Qasm3Error::Unsupported {
+            line,
+            msg: "angle expression is not linear in pi".into(),
+        };
+
+        match self {
+            Expr::Num(r) => Ok(Angle::constant(*r)),
+            Expr::Pi => Ok(Angle::pi()),
+            Expr::Var(v) => env
+                .get(v)
+                .copied()
+                .map_or_else(|| invalid(line, format!("undefined parameter '{v}'")), Ok),
+            Expr::Neg(e) => {
+                let x = e.eval(env, line)?;
+                Ok(Angle { a: -x.a, b: -x.b })
+            }
+            Expr::Bin(op, e1, e2) => {
+                let x = e1.eval(env, line)?;
+                let y = e2.eval(env, line)?;
+                match *op {
+                    "+" => Ok(Angle {
+                        a: x.a.checked_add(&y.a).ok_or_else(overflow)?,
+                        b: x.b.checked_add(&y.b).ok_or_else(overflow)?,
+                    }),
+                    "-" => Ok(Angle {
+                        a: x.a.checked_sub(&y.a).ok_or_else(overflow)?,
+                        b: x.b.checked_sub(&y.b).ok_or_else(overflow)?,
+                    }),
+                    "*" => {
+                        let (x, c) = if y.b.is_zero() {
+                            (x, y.a)
+                        } else if x.b.is_zero() {
+                            (y, x.a)
+                        } else {
+                            return Err(nonlinear());
+                        };
+                        Ok(Angle {
+                            a: x.a.checked_mul(&c).ok_or_else(overflow)?,
+                            b: x.b.checked_mul(&c).ok_or_else(overflow)?,
+                        })
+                    }
+                    "/" => {
+                        if y.a.is_zero() && y.b.is_zero() {
+                            invalid(line, "division by zero")
+                        } else if y.b.is_zero() {
+                            Ok(Angle {
+                                a: x.a.checked_div(&y.a).ok_or_else(overflow)?,
+                                b: x.b.checked_div(&y.a).ok_or_else(overflow)?,
+                            })
+                        } else if y.a.is_zero() && x.a.is_zero() {
+                            Ok(Angle::constant(x.b.checked_div(&y.b).ok_or_else(overflow)?))
+                        } else {
+                            Err(nonlinear())
+                        }
+                    }
+                    "**" => {
+                        if !x.b.is_zero() || !y.b.is_zero() || !y.a.is_integer() {
+                            return Err(nonlinear());
+                        }
+                        let e = y.a.to_integer();
+                        let e32 = i32::try_from(e).map_err(|_| overflow())?;
+                        if x.a.is_zero() && e32 < 0 {
+                            return invalid(line, "division by zero");
+                        }
+                        // exponentiation by squaring, so large exponents of 0 and ±1 are cheap
+                        let (mut r, mut base, mut n) = (Rational64::one(), x.a, e32.unsigned_abs());
+                        while n > 0 {
+                            if n & 1 == 1 {
+                                r = r.checked_mul(&base).ok_or_else(overflow)?;
+                            }
+                            n >>= 1;
+                            if n > 0 {
+                                base = base.checked_mul(&base).ok_or_else(overflow)?;
+                            }
+                        }
+                        Ok(Angle::constant(if e32 < 0 { r.recip() } else { r }))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+/// A reference to a single qubit/bit, or a whole register
+#[derive(Debug, Clone)]
+struct Operand {
+    reg: String,
+    index: Option<usize>,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct GateCall {
+    name: String,
+    params: Vec<Expr>,
+    args: Vec<Operand>,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct GateDef {
+    params: Vec<String>,
+    qargs: Vec<String>,
+    body: Vec<GateCall>,
+}
+
+/// A register of qubits or bits, given by its offset and size
+#[derive(Debug, Clone, Copy)]
+struct Register {
+    offset: usize,
+    size: usize,
+}
+
+struct Reader {
+    toks: Vec<Token>,
+    pos: usize,
+    qregs: FxHashMap<String, Register>,
+    cregs: FxHashMap<String, Register>,
+    nqubits: usize,
+    nbits: usize,
+    defs: FxHashMap<String, GateDef>,
+    gates: Vec<Gate>,
+    options: QasmOptions,
+    angles: Vec<QasmAngle>,
+}
+
+impl Reader {
+    fn new(toks: Vec<Token>, options: &QasmOptions) -> Reader {
+        Reader {
+            toks,
+            pos: 0,
+            qregs: FxHashMap::default(),
+            cregs: FxHashMap::default(),
+            nqubits: 0,
+            nbits: 0,
+            defs: FxHashMap::default(),
+            gates: vec![],
+            options: *options,
+            angles: vec![],
+        }
+    }
+
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].tok
+    }
+
+    fn line(&self) -> usize {
+        self.toks[self.pos].line
+    }
+
+    fn next(&mut self) -> Tok {
+        let t = self.toks[self.pos].tok.clone();
+        if self.pos + 1 < self.toks.len() {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn is_sym(&self, s: &str) -> bool {
+        matches!(self.peek(), Tok::Sym(s1) if *s1 == s)
+    }
+
+    fn eat_sym(&mut self, s: &str) -> bool {
+        if self.is_sym(s) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_sym(&mut self, s: &str) -> Result<()> {
+        if self.eat_sym(s) {
+            Ok(())
+        } else {
+            syntax(
+                self.line(),
+                format!("expected '{s}', found {}", self.peek()),
+            )
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String> {
+        let line = self.line();
+        match self.next() {
+            Tok::Ident(s) => Ok(s),
+            t => syntax(line, format!("expected identifier, found {t}")),
+        }
+    }
+
+    fn expect_int(&mut self) -> Result<usize> {
+        let line = self.line();
+        match self.next() {
+            Tok::Num(s) => s
+                .parse()
+                .or_else(|_| syntax(line, format!("expected integer, found '{s}'"))),
+            t => syntax(line, format!("expected integer, found {t}")),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr> {
+        let mut e = self.parse_term()?;
+        loop {
+            let op = if self.eat_sym("+") {
+                "+"
+            } else if self.eat_sym("-") {
+                "-"
+            } else {
+                return Ok(e);
+            };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.parse_term()?));
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Expr> {
+        let mut e = self.parse_unary()?;
+        loop {
+            let op = if self.eat_sym("*") {
+                "*"
+            } else if self.eat_sym("/") {
+                "/"
+            } else {
+                return Ok(e);
+            };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat_sym("-") {
+            Ok(Expr::Neg(Box::new(self.parse_unary()?)))
+        } else if self.eat_sym("+") {
+            self.parse_unary()
+        } else {
+            self.parse_power()
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Expr> {
+        let e = self.parse_atom()?;
+        if self.eat_sym("**") {
+            Ok(Expr::Bin("**", Box::new(e), Box::new(self.parse_unary()?)))
+        } else {
+            Ok(e)
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Expr> {
+        let line = self.line();
+        match self.next() {
+            Tok::Num(s) => Ok(Expr::Num(parse_number(&s, line, self.options.exact)?)),
+            Tok::Ident(s) if s == "pi" || s == "π" => Ok(Expr::Pi),
+            Tok::Ident(s) => {
+                if self.is_sym("(") {
+                    unsupported(line, format!("function call '{s}' in expression"))
+                } else {
+                    Ok(Expr::Var(s))
+                }
+            }
+            Tok::Sym("(") => {
+                let e = self.parse_expr()?;
+                self.expect_sym(")")?;
+                Ok(e)
+            }
+            t => syntax(line, format!("expected expression, found {t}")),
+        }
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        let line = self.line();
+        let reg = self.expect_ident()?;
+        let index = if self.eat_sym("[") {
+            let i = self.expect_int()?;
+            self.expect_sym("]")?;
+            Some(i)
+        } else {
+            None
+        };
+        Ok(Operand { reg, index, line })
+    }
+
+    /// Parse the remainder of a gate call whose name has already been consumed
+    fn parse_gate_call(&mut self, name: String, line: usize) -> Result<GateCall> {
+        let mut params = vec![];
+        if self.eat_sym("(") && !self.eat_sym(")") {
+            loop {
+                params.push(self.parse_expr()?);
+                if self.eat_sym(")") {
+                    break;
+                }
+                self.expect_sym(",")?;
+            }
+        }
+
+        if self.is_sym("@") {
+            return unsupported(line, "gate modifiers");
+        }
+
+        let mut args = vec![];
+        if !self.is_sym(";") {
+            loop {
+                args.push(self.parse_operand()?);
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+        }
+        self.expect_sym(";")?;
+
+        Ok(GateCall {
+            name,
+            params,
+            args,
+            line,
+        })
+    }
+
+    fn parse_gate_def(&mut self) -> Result<()> {
+        let line = self.line();
+        let name = self.expect_ident()?;
+        let mut params = vec![];
+        if self.eat_sym("(") && !self.eat_sym(")") {
+            loop {
+                params.push(self.expect_ident()?);
+                if self.eat_sym(")") {
+                    break;
+                }
+                self.expect_sym(",")?;
+            }
+        }
+
+        let mut qargs = vec![];
+        loop {
+            qargs.push(self.expect_ident()?);
+            if !self.eat_sym(",") {
+                break;
+            }
+        }
+
+        self.expect_sym("{")?;
+        let mut body = vec![];
+        while !self.eat_sym("}") {
+            let l = self.line();
+            match self.next() {
+                Tok::Ident(s) if is_reserved(&s) => {
+                    return unsupported(l, format!("'{s}' inside a gate definition"))
+                }
+                Tok::Ident(s) => {
+                    let call = self.parse_gate_call(s, l)?;
+                    if let Some(op) = call.args.iter().find(|op| op.index.is_some()) {
+                        return invalid(op.line, "gate arguments can't be indexed");
+                    }
+                    body.push(call);
+                }
+                t => return syntax(l, format!("expected gate call, found {t}")),
+            }
+        }
+
+        if self.defs.contains_key(&name) {
+            return invalid(line, format!("gate '{name}' is already defined"));
+        }
+        self.defs.insert(
+            name,
+            GateDef {
+                params,
+                qargs,
+                body,
+            },
+        );
+        Ok(())
+    }
+
+    fn declare(&mut self, name: String, size: usize, quantum: bool, line: usize) -> Result<()> {
+        if self.qregs.contains_key(&name) || self.cregs.contains_key(&name) {
+            return invalid(line, format!("'{name}' is already declared"));
+        }
+        if quantum {
+            self.qregs.insert(
+                name,
+                Register {
+                    offset: self.nqubits,
+                    size,
+                },
+            );
+            self.nqubits += size;
+        } else {
+            self.cregs.insert(
+                name,
+                Register {
+                    offset: self.nbits,
+                    size,
+                },
+            );
+            self.nbits += size;
+        }
+        Ok(())
+    }
+
+    /// Parse `qubit[n] name;` or `bit[n] name;`, after the keyword
+    fn parse_declaration(&mut self, quantum: bool, line: usize) -> Result<()> {
+        let size = if self.eat_sym("[") {
+            let n = self.expect_int()?;
+            self.expect_sym("]")?;
+            n
+        } else {
+            1
+        };
+        let name = self.expect_ident()?;
+        if self.is_sym("=") {
+            return unsupported(line, "initialised declarations");
+        }
+        self.expect_sym(";")?;
+        self.declare(name, size, quantum, line)
+    }
+
+    /// Parse `qreg name[n];` or `creg name[n];`, after the keyword
+    fn parse_old_declaration(&mut self, quantum: bool, line: usize) -> Result<()> {
+        let name = self.expect_ident()?;
+        self.expect_sym("[")?;
+        let size = self.expect_int()?;
+        self.expect_sym("]")?;
+        self.expect_sym(";")?;
+        self.declare(name, size, quantum, line)
+    }
+
+    /// Resolve an operand to a list of global qubit or bit indices
+    fn resolve(&self, op: &Operand, quantum: bool) -> Result<Vec<usize>> {
+        let regs = if quantum { &self.qregs } else { &self.cregs };
+        let kind = if quantum { "qubit" } else { "bit" };
+        let Some(r) = regs.get(&op.reg) else {
+            return invalid(op.line, format!("undefined {kind} register '{}'", op.reg));
+        };
+        match op.index {
+            Some(i) if i < r.size => Ok(vec![r.offset + i]),
+            Some(i) => invalid(
+                op.line,
+                format!("index {i} out of range for register '{}'", op.reg),
+            ),
+            None => Ok((r.offset..r.offset + r.size).collect()),
+        }
+    }
+
+    /// Resolve a list of operands, broadcasting over whole registers
+    fn broadcast(&self, args: &[Vec<usize>], line: usize) -> Result<Vec<Vec<usize>>> {
+        let n = args
+            .iter()
+            .map(|a| a.len())
+            .filter(|&l| l != 1)
+            .max()
+            .unwrap_or(1);
+        if args.iter().any(|a| a.len() != 1 && a.len() != n) {
+            return invalid(line, "registers of different sizes");
+        }
+        Ok((0..n)
+            .map(|i| {
+                args.iter()
+                    .map(|a| if a.len() == 1 { a[0] } else { a[i] })
+                    .collect()
+            })
+            .collect())
+    }
+
+    fn apply_gate(
+        &mut self,
+        call: &GateCall,
+        params: Vec<Angle>,
+        qs: Vec<usize>,
+        depth: usize,
+    ) -> Result<()> {
+        let line = call.line;
+        if depth > 64 {
+            return invalid(line, format!("gate '{}' is defined recursively", call.name));
+        }
+
+        for (i, &q) in qs.iter().enumerate() {
+            if qs[..i].contains(&q) {
+                return invalid(line, format!("qubit used twice in call to '{}'", call.name));
+            }
+        }
+
+        if let Some(def) = self.defs.get(&call.name).cloned() {
+            if def.params.len() != params.len() || def.qargs.len() != qs.len() {
+                return invalid(
+                    line,
+                    format!(
+                        "gate '{}' takes {} parameters and {} qubits",
+                        call.name,
+                        def.params.len(),
+                        def.qargs.len()
+                    ),
+                );
+            }
+            let env: FxHashMap<String, Angle> = def.params.iter().cloned().zip(params).collect();
+            let qenv: FxHashMap<&str, usize> = def
+                .qargs
+                .iter()
+                .map(String::as_str)
+                .zip(qs.iter().copied())
+                .collect();
+            for inner in &def.body {
+                let ps = inner
+                    .params
+                    .iter()
+                    .map(|e| e.eval(&env, inner.line))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut iqs = vec![];
+                for op in &inner.args {
+                    match qenv.get(op.reg.as_str()) {
+                        Some(&q) => iqs.push(q),
+                        None => return invalid(op.line, format!("undefined qubit '{}'", op.reg)),
+                    }
+                }
+                self.apply_gate(inner, ps, iqs, depth + 1)?;
+            }
+            return Ok(());
+        }
+
+        let t = match call.name.as_str() {
+            "gphase" => return Ok(()),
+            name => GType::from_qasm_name(name),
+        };
+
+        let params = params
+            .into_iter()
+            .map(|p| self.read_angle(p, line))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(&(_, nparams, nqubits)) = STD_GATES.iter().find(|(n, _, _)| *n == call.name) {
+            if params.len() != nparams || qs.len() != nqubits {
+                return invalid(
+                    line,
+                    format!(
+                        "gate '{}' takes {nparams} parameters and {nqubits} qubits",
+                        call.name
+                    ),
+                );
+            }
+            let mut c = Circuit::new(0);
+            Gate::push_std_gate_decomp(&mut c, &call.name, &params, &qs);
+            self.gates.extend(c.gates);
+            return Ok(());
+        }
+
+        let nparams = match t {
+            ZPhase | XPhase | ParityPhase => 1,
+            UnknownGate => return unsupported(line, format!("gate '{}'", call.name)),
+            _ => 0,
+        };
+
+        if params.len() != nparams {
+            return invalid(
+                line,
+                format!("gate '{}' takes {} parameters", call.name, nparams),
+            );
+        }
+        if t.num_qubits().is_some_and(|n| n != qs.len()) || qs.is_empty() {
+            return invalid(
+                line,
+                format!("wrong number of qubits for gate '{}'", call.name),
+            );
+        }
+
+        let phase = params.first().map_or(Phase::zero(), |&p| Phase::new(p));
+        self.gates.push(Gate::new_with_phase(t, qs, phase));
+        Ok(())
+    }
+
+    fn parse_measure(&mut self, line: usize, target: Option<Operand>) -> Result<()> {
+        let src = self.parse_operand()?;
+        let target = match target {
+            Some(t) => Some(t),
+            None if self.eat_sym("->") => Some(self.parse_operand()?),
+            None => None,
+        };
+        self.expect_sym(";")?;
+
+        let qs = self.resolve(&src, true)?;
+        match target {
+            Some(t) => {
+                let bs = self.resolve(&t, false)?;
+                if qs.len() != bs.len() {
+                    return invalid(line, "measured registers have different sizes");
+                }
+                for (q, b) in qs.into_iter().zip(bs) {
+                    self.gates.push(Gate::new_with_phase_and_vars(
+                        Measure,
+                        vec![q],
+                        Phase::zero(),
+                        Parity::single(b as Var),
+                    ));
+                }
+            }
+            None => {
+                for q in qs {
+                    self.gates.push(Gate::new(Measure, vec![q]));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_statement(&mut self) -> Result<bool> {
+        let line = self.line();
+        let word = match self.next() {
+            Tok::Eof => return Ok(false),
+            Tok::Ident(s) => s,
+            Tok::Sym(";") => return Ok(true),
+            t => return syntax(line, format!("expected statement, found {t}")),
+        };
+
+        match word.as_str() {
+            "OPENQASM" => {
+                let l = self.line();
+                match self.next() {
+                    Tok::Num(v) if v == "3" || v.starts_with("3.") => {}
+                    Tok::Num(v) => {
+                        return unsupported(l, format!("OpenQASM version {v}"));
+                    }
+                    t => return syntax(l, format!("expected version, found {t}")),
+                }
+                self.expect_sym(";")?;
+            }
+            "include" => {
+                let l = self.line();
+                match self.next() {
+                    Tok::Str(f) if f == "stdgates.inc" => {}
+                    Tok::Str(f) => return unsupported(l, format!("including '{f}'")),
+                    t => return syntax(l, format!("expected file name, found {t}")),
+                }
+                self.expect_sym(";")?;
+            }
+            "qubit" => self.parse_declaration(true, line)?,
+            "bit" => self.parse_declaration(false, line)?,
+            "qreg" => self.parse_old_declaration(true, line)?,
+            "creg" => self.parse_old_declaration(false, line)?,
+            "gate" => self.parse_gate_def()?,
+            "measure" => self.parse_measure(line, None)?,
+            "reset" => {
+                let op = self.parse_operand()?;
+                self.expect_sym(";")?;
+                for q in self.resolve(&op, true)? {
+                    self.gates.push(Gate::new(MeasureReset, vec![q]));
+                }
+            }
+            "barrier" => {
+                let mut qs = vec![];
+                if self.is_sym(";") {
+                    qs.extend(0..self.nqubits);
+                } else {
+                    loop {
+                        let op = self.parse_operand()?;
+                        qs.extend(self.resolve(&op, true)?);
+                        if !self.eat_sym(",") {
+                            break;
+                        }
+                    }
+                }
+                self.expect_sym(";")?;
+                qs.sort();
+                qs.dedup();
+                self.gates.push(Gate::new(Barrier, qs));
+            }
+            "if" => self.parse_if(line)?,
+            w if is_reserved(w) => return unsupported(line, format!("'{w}' statements")),
+            _ => {
+                if self.is_sym("[") || self.is_sym("=") {
+                    // could be an assignment `c[i] = measure q[j];`
+                    let save = self.pos;
+                    self.pos -= 1;
+                    let target = self.parse_operand()?;
+                    if self.eat_sym("=") {
+                        if matches!(self.peek(), Tok::Ident(s) if s == "measure") {
+                            self.next();
+                            return self.parse_measure(line, Some(target)).map(|_| true);
+                        }
+                        return unsupported(line, "classical assignments");
+                    }
+                    self.pos = save;
+                }
+
+                let call = self.parse_gate_call(word, line)?;
+                let params = call
+                    .params
+                    .iter()
+                    .map(|e| e.eval(&FxHashMap::default(), line))
+                    .collect::<Result<Vec<_>>>()?;
+                let args = call
+                    .args
+                    .iter()
+                    .map(|op| self.resolve(op, true))
+                    .collect::<Result<Vec<_>>>()?;
+                for qs in self.broadcast(&args, line)? {
+                    self.apply_gate(&call, params.clone(), qs, 0)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Parse `if (cond) body` with an optional `else body`, after the keyword
+    ///
+    /// The condition is either on a single bit: `c[i]`, `!c[i]`, `c[i] == k` or
+    /// `c[i] != k`, where `c[i]` can also be a register of size 1, or compares a
+    /// register with an integer: `c == k`, which can't have an `else`. The body is a
+    /// statement or a block, and can't contain measurements, resets or further
+    /// conditions.
+    fn parse_if(&mut self, line: usize) -> Result<()> {
+        self.expect_sym("(")?;
+        let negated = self.eat_sym("!");
+        let op = self.parse_operand()?;
+        let bits = self.resolve(&op, false)?;
+        if bits.len() != 1 {
+            if negated || !self.eat_sym("==") {
+                return unsupported(line, "conditions on more than one bit other than '=='");
+            }
+            let value = self.expect_int()?;
+            self.expect_sym(")")?;
+            let cond = QasmCondition::equals(bits[0] as Var, bits.len(), value as u64);
+            self.parse_conditional_body(&cond, line)?;
+            if matches!(self.peek(), Tok::Ident(s) if s == "else") {
+                return unsupported(line, "'else' after a condition on more than one bit");
+            }
+            return Ok(());
+        }
+        let bit = Parity::single(bits[0] as Var);
+
+        let mut cond = if negated { bit.negated() } else { bit.clone() };
+        if !negated && (self.is_sym("==") || self.is_sym("!=")) {
+            let eq = self.eat_sym("==");
+            if !eq {
+                self.next();
+            }
+            cond = match self.expect_int()? {
+                0 => bit.negated(),
+                1 => bit,
+                _ => Parity::zero(),
+            };
+            if !eq {
+                cond = if cond.is_zero() {
+                    Parity::one()
+                } else {
+                    cond.negated()
+                };
+            }
+        }
+        self.expect_sym(")")?;
+
+        self.parse_conditional_body(&QasmCondition::Parity(cond.clone()), line)?;
+        if matches!(self.peek(), Tok::Ident(s) if s == "else") {
+            self.next();
+            let cond = if cond.is_zero() {
+                Parity::one()
+            } else {
+                cond.negated()
+            };
+            self.parse_conditional_body(&QasmCondition::Parity(cond), line)?;
+        }
+        Ok(())
+    }
+
+    /// Parse a statement or block, controlling the gates it produces by `cond`
+    ///
+    /// Gates whose condition never holds are dropped. A parity of one always holds, so
+    /// those gates are unconditional.
+    fn parse_conditional_body(&mut self, cond: &QasmCondition, line: usize) -> Result<()> {
+        let start = self.gates.len();
+        if self.eat_sym("{") {
+            while !self.eat_sym("}") {
+                if !self.parse_statement()? {
+                    return syntax(self.line(), "expected '}', found end of input");
+                }
+            }
+        } else if !self.parse_statement()? {
+            return syntax(self.line(), "expected statement, found end of input");
+        }
+
+        for g in &mut self.gates[start..] {
+            if g.t != Barrier {
+                cond.control(g).or_else(|what| unsupported(line, what))?;
+            }
+        }
+        if cond.is_never() {
+            self.gates.truncate(start);
+        }
+        Ok(())
+    }
+
+    /// Converts an angle into a number of half-turns, without reducing modulo 2
+    fn read_angle(&mut self, angle: Angle, line: usize) -> Result<Rational64> {
+        let a = angle.a.to_f64().unwrap_or(0.0);
+        let gate = self.gates.len();
+        self.options
+            .read_angle(a, angle.b, gate, &mut self.angles)
+            .map_err(|angle| Qasm3Error::InexactAngle { line, angle })
+    }
+
+    fn read(mut self) -> Result<QasmImport> {
+        while self.parse_statement()? {}
+        let mut c = Circuit::new(self.nqubits);
+        for g in self.gates {
+            c.push(g);
+        }
+        Ok(QasmImport {
+            circuit: c,
+            angles: self.angles,
+        })
+    }
+}
+
+/// Keywords for OpenQASM 3 features that we don't support
+fn is_reserved(s: &str) -> bool {
+    matches!(
+        s,
+        "else"
+            | "for"
+            | "while"
+            | "break"
+            | "continue"
+            | "end"
+            | "return"
+            | "def"
+            | "defcal"
+            | "defcalgrammar"
+            | "cal"
+            | "extern"
+            | "input"
+            | "output"
+            | "const"
+            | "let"
+            | "int"
+            | "uint"
+            | "float"
+            | "angle"
+            | "bool"
+            | "complex"
+            | "duration"
+            | "stretch"
+            | "array"
+            | "box"
+            | "delay"
+            | "ctrl"
+            | "negctrl"
+            | "inv"
+            | "pow"
+            | "pragma"
+            | "switch"
+    )
+}
+
+/// Format a phase as an exact OpenQASM angle expression
+fn phase_to_qasm(p: Phase) -> String {
+    let r = p.to_rational();
+    let (n, d) = (*r.numer(), *r.denom());
+    let num = match n {
+        0 => return "0".into(),
+        1 => "pi".into(),
+        -1 => "-pi".into(),
+        _ => format!("{n}*pi"),
+    };
+    if d == 1 {
+        num
+    } else {
+        format!("{num}/{d}")
+    }
+}
+
+impl Circuit {
+    /// Reads a circuit from an OpenQASM 3 program
+    ///
+    /// See the [`qasm3`](crate::qasm3) module for the supported subset of the language.
+    pub fn from_qasm3(source: &str) -> std::result::Result<Circuit, Qasm3Error> {
+        Circuit::from_qasm3_with_options(source, &QasmOptions::default()).map(|imp| imp.circuit)
+    }
+
+    /// Reads a circuit from an OpenQASM 3 file
+    pub fn from_qasm3_file(name: &str) -> std::result::Result<Circuit, Qasm3Error> {
+        let source = std::fs::read_to_string(name)?;
+        Circuit::from_qasm3(&source)
+    }
+
+    /// Reads a circuit from an OpenQASM 3 program, reporting any angles that could
+    /// not be read exactly
+    pub fn from_qasm3_with_options(
+        source: &str,
+        options: &QasmOptions,
+    ) -> std::result::Result<QasmImport, Qasm3Error> {
+        Reader::new(tokenize(source)?, options).read()
+    }
+
+    /// Writes the circuit as an OpenQASM 3 program
+    ///
+    /// Gates with no counterpart in `stdgates.inc` are either given a `gate`
+    /// definition, or (for the pyzx-specific gates, such as `init_anc`) written
+    /// using their pyzx name, as [`Circuit::to_qasm`] does.
+    pub fn to_qasm3(&self) -> String {
+        let mut s = String::from("OPENQASM 3.0;\ninclude \"stdgates.inc\";\n");
+
+        let uses = |t: GType| self.gates.iter().any(|g| g.t == t);
+        if uses(CCZ) {
+            s += "gate ccz a, b, c { h c; ccx a, b, c; h c; }\n";
+        }
+        if uses(XCX) {
+            s += "gate xcx a, b { h a; h b; cz a, b; h a; h b; }\n";
+        }
+
+        writeln!(s, "qubit[{}] q;", self.num_qubits()).unwrap();
+
+        // a single register, unless some conditions compare several bits with a value
+        let (nbits, bits) = self.classical_bits();
+        let regs = ClassicalRegisters::new(self, nbits);
+        let single = regs.regs.iter().all(|&(_, len)| len == 1);
+        if single && nbits > 0 {
+            writeln!(s, "bit[{nbits}] c;").unwrap();
+        } else if !single {
+            for &(start, len) in &regs.regs {
+                if len == 1 {
+                    writeln!(s, "bit c{start};").unwrap();
+                } else {
+                    writeln!(s, "bit[{len}] c{start};").unwrap();
+                }
+            }
+        }
+        let bit_name = |b: usize| {
+            let ((start, len), i) = regs.locate(b);
+            if single {
+                format!("c[{b}]")
+            } else if len == 1 {
+                format!("c{start}")
+            } else {
+                format!("c{start}[{i}]")
+            }
+        };
+
+        let write_gate = |s: &mut String, g: &Gate, b: Option<usize>| {
+            let qs: Vec<String> = g.qs.iter().map(|i| format!("q[{i}]")).collect();
+            let qs = qs.join(", ");
+            let call = match g.t {
+                ZPhase | XPhase => format!("{}({}) {qs};", g.qasm_name(), phase_to_qasm(g.phase)),
+                Measure | MeasureReset => {
+                    if let Some(b) = b {
+                        writeln!(s, "{} = measure {qs};", bit_name(b)).unwrap();
+                    }
+                    if g.t == MeasureReset {
+                        writeln!(s, "reset {qs};").unwrap();
+                    }
+                    return;
+                }
+                _ => format!("{} {qs};", g.qasm_name()),
+            };
+
+            if !g.is_conditional() {
+                writeln!(s, "{call}").unwrap();
+                return;
+            }
+            for cond in regs.conditions(g) {
+                match cond {
+                    Some(((start, len), k)) if len > 1 => {
+                        writeln!(s, "if (c{start} == {k}) {call}").unwrap()
+                    }
+                    Some(((start, _), k)) => {
+                        writeln!(s, "if ({} == {k}) {call}", bit_name(start)).unwrap()
+                    }
+                    None => writeln!(s, "{call}").unwrap(),
+                }
+            }
+        };
+
+        for (g, b) in self.gates.iter().zip(bits) {
+            if g.t == ParityPhase {
+                let mut c = Circuit::new(self.num_qubits());
+                g.push_basic_gates(&mut c);
+                for g1 in &c.gates {
+                    write_gate(&mut s, g1, None);
+                }
+            } else {
+                write_gate(&mut s, g, b);
+            }
+        }
+
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+
+    #[test]
+    fn basic() {
+        let c = Circuit::from_qasm3(
+            r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[2] q;
+            qubit r;
+            h q[0];
+            cx q[0], r;
+            rz(pi/4) q[1];
+            p(-0.5*pi) r;
+            id q[0];
+        "#,
+        )
+        .unwrap();
+
+        let mut c1 = Circuit::new(3);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate("cx", vec![0, 2]);
+        c1.add_gate_with_phase("rz", vec![1], Rational64::new(1, 4));
+        c1.add_gate_with_phase("rz", vec![2], Rational64::new(-1, 2));
+        assert_eq!(c, c1);
+    }
+
+    #[test]
+    fn qasm2_style() {
+        let src = r#"
+            qreg q[2];
+            qreg r[3];
+            cx q[0], q[1];
+            cx q[1], r[0];
+            cx r[0], r[1];
+            cx r[1], r[2];
+        "#;
+        assert_eq!(
+            Circuit::from_qasm3(src).unwrap(),
+            Circuit::from_qasm(src).unwrap()
+        );
+    }
+
+    #[test]
+    fn angles() {
+        let c = Circuit::from_qasm3(
+            r#"
+            qubit q;
+            rz(pi) q;
+            rz(-pi) q;
+            rz(2*pi/3) q;
+            rz(2/3 * π) q;
+            rz(0.25 * pi) q;
+            rz(pi * 2 ** -3) q;
+            rz((pi - pi/2) / 2) q;
+            rz(1.5707963267948966) q;
+        "#,
+        )
+        .unwrap();
+        let phases: Vec<_> = c.gates.iter().map(|g| g.phase.to_rational()).collect();
+        assert_eq!(
+            phases,
+            vec![
+                Rational64::new(1, 1),
+                Rational64::new(1, 1),
+                Rational64::new(2, 3),
+                Rational64::new(2, 3),
+                Rational64::new(1, 4),
+                Rational64::new(1, 8),
+                Rational64::new(1, 4),
+                Rational64::new(1, 2),
+            ]
+        );
+
+        let c = Circuit::from_qasm3(
+            "qubit q;\nrz(pi * 1 ** 2000000000) q;\nrz(pi * (-1) ** -2000000001) q;",
+        )
+        .unwrap();
+        assert_eq!(c.gates[0].phase.to_rational(), Rational64::new(1, 1));
+        assert_eq!(c.gates[1].phase.to_rational(), Rational64::new(1, 1));
+        let err = Circuit::from_qasm3("qubit q;\nrz(pi * 3 ** 100) q;").unwrap_err();
+        assert!(matches!(err, Qasm3Error::Unsupported { line: 2, .. }));
+    }
+
+    #[test]
+    fn inexact_angles() {
+        let src = r#"
+            qubit q;
+            rz(pi/1024 + 1e-9) q;
+            rz(0.000001 * pi) q;
+            ry(1) q;
+        "#;
+        let imp = Circuit::from_qasm3_with_options(src, &QasmOptions::default()).unwrap();
+        let rounded: Vec<_> = imp.rounded_angles().map(|a| a.gate).collect();
+        assert_eq!(rounded, vec![0, 2]);
+        assert_eq!(
+            imp.circuit.gates[1].phase,
+            Phase::new(Rational64::new(1, 1000000))
+        );
+
+        let options = QasmOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let err = Circuit::from_qasm3_with_options(src, &options).unwrap_err();
+        assert!(matches!(err, Qasm3Error::InexactAngle { line: 3, .. }));
+
+        // too many digits to read the literal as a fraction
+        let src = "qubit q;\nrz(0.1234567890123456789012345 * pi) q;";
+        assert!(Circuit::from_qasm3_with_options(src, &QasmOptions::default()).is_ok());
+        let err = Circuit::from_qasm3_with_options(src, &options).unwrap_err();
+        assert!(matches!(err, Qasm3Error::Unsupported { line: 2, .. }));
+    }
+
+    #[test]
+    fn gate_definitions() {
+        let c = Circuit::from_qasm3(
+            r#"
+            OPENQASM 3;
+            include "stdgates.inc";
+            gate mycz a, b { h b; cx a, b; h b; }
+            gate twice(theta) a { rz(theta) a; rz(theta) a; }
+            gate nested(theta) a, b { twice(theta/2) a; mycz b, a; }
+            qubit[2] q;
+            nested(pi/2) q[1], q[0];
+        "#,
+        )
+        .unwrap();
+
+        let mut c1 = Circuit::new(2);
+        c1.add_gate_with_phase("rz", vec![1], Rational64::new(1, 4));
+        c1.add_gate_with_phase("rz", vec![1], Rational64::new(1, 4));
+        c1.add_gate("h", vec![1]);
+        c1.add_gate("cx", vec![0, 1]);
+        c1.add_gate("h", vec![1]);
+        assert_eq!(c, c1);
+    }
+
+    #[test]
+    fn broadcast() {
+        let c = Circuit::from_qasm3(
+            r#"
+            qubit[3] a;
+            qubit[3] b;
+            qubit t;
+            h a;
+            cx a, b;
+            cz a, t;
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.num_qubits(), 7);
+        assert_eq!(c.num_gates_of_type(HAD), 3);
+        assert_eq!(c.num_gates_of_type(CNOT), 3);
+        assert_eq!(c.num_gates_of_type(CZ), 3);
+        assert_eq!(c.gates[4].qs, vec![1, 4]);
+        assert_eq!(c.gates[8].qs, vec![2, 6]);
+    }
+
+    #[test]
+    fn std_gates() {
+        let src = r#"
+            OPENQASM 3.0;
+            include "stdgates.inc";
+            qubit[3] q;
+            U(pi/2, 0, pi) q[0];
+            u3(pi/4, pi/2, -pi/2) q[1];
+            cp(pi/2) q[0], q[1];
+            crx(pi) q[1], q[2];
+            cu(pi/2, 0, pi, pi/4) q[2], q[0];
+            cswap q[0], q[1], q[2];
+        "#;
+        let c = Circuit::from_qasm3(src).unwrap();
+        assert_eq!(c.num_gates_of_type(UnknownGate), 0);
+
+        let qasm2 = src
+            .replace("OPENQASM 3.0;", "OPENQASM 2.0;")
+            .replace("stdgates.inc", "qelib1.inc")
+            .replace("qubit[3] q;", "qreg q[3];");
+        assert_eq!(c, Circuit::from_qasm(&qasm2).unwrap());
+
+        assert!(matches!(
+            Circuit::from_qasm3("qubit q; u3(0, 0) q;"),
+            Err(Qasm3Error::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn measure_reset() {
+        let c = Circuit::from_qasm3(
+            r#"
+            qubit[2] q;
+            bit[2] c;
+            bit d;
+            c[1] = measure q[0];
+            measure q[1] -> d;
+            c = measure q;
+            reset q[0];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.num_gates_of_type(Measure), 4);
+        assert_eq!(c.num_gates_of_type(MeasureReset), 1);
+        assert_eq!(c.gates[0].vars, Parity::single(1));
+        assert_eq!(c.gates[1].vars, Parity::single(2));
+        assert_eq!(c.gates[3].vars, Parity::single(1));
+        assert_eq!(c.gates[3].qs, vec![1]);
+    }
+
+    #[test]
+    fn dynamic_circuits() {
+        let c = Circuit::from_qasm3(
+            r#"
+            qubit[3] q;
+            bit[2] c;
+            bit d;
+            c[0] = measure q[0];
+            barrier q[0], q[1];
+            if (c[0]) x q[1];
+            if (!c[0]) { z q[1]; x q[2]; } else y q[2];
+            if (d == 0) z q;
+            if (c[1] != 1) x q[0];
+            barrier;
+        "#,
+        )
+        .unwrap();
+        let b0 = Parity::single(0);
+        let conds: Vec<_> = c.gates.iter().map(|g| (g.t, g.vars.clone())).collect();
+        assert_eq!(conds[1], (Barrier, Parity::zero()));
+        assert_eq!(conds[2], (NOT, b0.clone()));
+        assert_eq!(conds[3], (Z, b0.negated()));
+        assert_eq!(conds[4], (NOT, b0.negated()));
+        assert!(conds[5..7].iter().all(|(_, p)| *p == b0));
+        assert!(conds[7..10]
+            .iter()
+            .all(|(t, p)| *t == Z && *p == Parity::single(2).negated()));
+        assert_eq!(conds[10], (NOT, Parity::single(1).negated()));
+        assert_eq!(c.gates[11].qs, vec![0, 1, 2]);
+
+        let s = c.to_qasm3();
+        assert!(s.contains("barrier q[0], q[1];"), "{s}");
+        assert!(s.contains("if (c[0] == 1) x q[1];"), "{s}");
+        assert!(s.contains("if (c[0] == 0) z q[1];"), "{s}");
+        assert_eq!(Circuit::from_qasm3(&s).unwrap(), c);
+    }
+
+    #[test]
+    fn register_conditions() {
+        let c = Circuit::from_qasm3(
+            r#"
+            qubit[2] q;
+            bit[2] c;
+            bit d;
+            c[0] = measure q[0];
+            if (c == 1) { h q[0]; cx q[0], q[1]; }
+            if (d) s q[1];
+            if (c == 7) x q[0];
+        "#,
+        )
+        .unwrap();
+        let cond = Some(RegisterCondition {
+            start: 0,
+            len: 2,
+            value: 1,
+        });
+        assert_eq!(c.num_gates(), 4);
+        assert!(c.gates.range(1..3).all(|g| g.condition == cond));
+        assert_eq!(c.gates[3].t, S);
+        assert_eq!(c.gates[3].vars, Parity::single(2));
+
+        let s = c.to_qasm3();
+        assert!(s.contains("bit[2] c0;"), "{s}");
+        assert!(s.contains("bit c2;"), "{s}");
+        assert!(s.contains("c0[0] = measure q[0];"), "{s}");
+        assert!(s.contains("if (c0 == 1) cx q[0], q[1];"), "{s}");
+        assert!(s.contains("if (c2 == 1) s q[1];"), "{s}");
+        assert_eq!(Circuit::from_qasm3(&s).unwrap(), c);
+        assert_eq!(Circuit::from_qasm(&c.to_qasm()).unwrap(), c);
+    }
+
+    #[test]
+    fn errors() {
+        let unsupported = [
+            "qubit q; bit[2] c; if (c != 1) x q;",
+            "qubit q; bit[2] c; if (c == 1) x q; else z q;",
+            "qubit q; bit c; if (c) c = measure q;",
+            "qubit q; bit c; if (c) { if (c) x q; }",
+            "qubit q; ctrl @ x q, q;",
+            "qubit q; rz(sin(pi)) q;",
+            "OPENQASM 2.0; qubit q;",
+            "include \"qelib1.inc\";",
+            "qubit q; rz(pi * pi) q;",
+            "qubit q; foo q;",
+        ];
+        for src in unsupported {
+            assert!(
+                matches!(
+                    Circuit::from_qasm3(src),
+                    Err(Qasm3Error::Unsupported { .. })
+                ),
+                "expected unsupported: {src}"
+            );
+        }
+
+        let invalid = [
+            "qubit q; h r;",
+            "qubit[2] q; h q[2];",
+            "qubit[2] q; cx q[0];",
+            "qubit[2] q; cx q[0], q[0];",
+            "qubit q; h(pi) q;",
+            "qubit q; qubit q;",
+            "gate g(t) a { rz(s) a; } qubit q; g(0) q;",
+        ];
+        for src in invalid {
+            assert!(
+                matches!(Circuit::from_qasm3(src), Err(Qasm3Error::Invalid { .. })),
+                "expected invalid: {src}"
+            );
+        }
+
+        let err = Circuit::from_qasm3("qubit q;\n\nh q").unwrap_err();
+        assert!(matches!(err, Qasm3Error::Syntax { line: 3, .. }));
+        assert_eq!(
+            err.to_string(),
+            "line 3: syntax error: expected ';', found end of input"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut c = Circuit::random()
+            .seed(1337)
+            .qubits(4)
+            .depth(40)
+            .p_t(0.2)
+            .p_cz(0.1)
+            .with_cliffords()
+            .build();
+        c.add_gate_with_phase("rx", vec![1], Rational64::new(-3, 8));
+        c.add_gate("ccz", vec![0, 1, 2]);
+        c.add_gate("xcx", vec![3, 1]);
+
+        let c1 = Circuit::from_qasm3(&c.to_qasm3()).unwrap();
+        assert_eq!(c1.num_qubits(), c.num_qubits());
+        // compare the ZX translations, since the tensor of a circuit ignores xcx gates
+        let g: Graph = c.to_graph();
+        let g1: Graph = c1.to_graph();
+        assert!(Tensor4::scalar_compare(&g, &g1));
+
+        let mut c = Circuit::new(2);
+        c.add_gate("h", vec![0]);
+        c.add_gate_with_phase_and_vars("measure_d", vec![0], Phase::zero(), Parity::single(1));
+        c.add_gate("measure_d", vec![1]);
+        let c1 = Circuit::from_qasm3(&c.to_qasm3()).unwrap();
+        assert_eq!(c1.gates[1].vars, Parity::single(1));
+        assert_eq!(c1.gates[2].vars, Parity::single(2));
+    }
+}
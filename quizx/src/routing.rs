@@ -0,0 +1,791 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Routing circuits onto devices with restricted connectivity
+//!
+//! A [`CouplingMap`] gives the pairs of physical qubits that two-qubit gates can act
+//! on. A [`Router`] places the qubits of a [`Circuit`] on a device and inserts SWAP
+//! gates (or, optionally, bridge gates) so that every two-qubit gate acts on
+//! neighbouring qubits.
+
+use crate::circuit::Circuit;
+use crate::gate::*;
+use derive_more::{Display, Error};
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+/// An error that can occur when routing a circuit.
+#[derive(Debug, Display, Error)]
+#[non_exhaustive]
+pub enum RoutingError {
+    /// The circuit doesn't fit on the device.
+    #[display("circuit has {circuit} qubits, but the device only has {device}")]
+    TooManyQubits { circuit: usize, device: usize },
+    /// A gate acts on qubits that have no path between them in the coupling map.
+    #[display("no path between physical qubits {q0} and {q1}")]
+    Disconnected { q0: usize, q1: usize },
+    /// A coupling map couldn't be parsed.
+    #[display("invalid coupling map: {msg}")]
+    InvalidMap { msg: String },
+}
+
+fn invalid_map<T>(msg: impl Into<String>) -> Result<T, RoutingError> {
+    Err(RoutingError::InvalidMap { msg: msg.into() })
+}
+
+/// The connectivity of a device, as an undirected graph on its physical qubits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CouplingMap {
+    adj: Vec<Vec<usize>>,
+    /// all-pairs distances, with `usize::MAX` for qubits that aren't connected
+    dist: Vec<Vec<usize>>,
+}
+
+impl CouplingMap {
+    /// Builds a coupling map on `nqubits` qubits from a list of edges
+    ///
+    /// Edges are undirected, and duplicate edges and self-loops are ignored. Panics
+    /// if an edge refers to a qubit `>= nqubits`.
+    pub fn new(nqubits: usize, edges: impl IntoIterator<Item = (usize, usize)>) -> Self {
+        let mut adj = vec![vec![]; nqubits];
+        for (a, b) in edges {
+            assert!(
+                a < nqubits && b < nqubits,
+                "edge ({a}, {b}) out of range for {nqubits} qubits"
+            );
+            if a != b && !adj[a].contains(&b) {
+                adj[a].push(b);
+                adj[b].push(a);
+            }
+        }
+        adj.iter_mut().for_each(|ns| ns.sort());
+
+        let dist = (0..nqubits)
+            .map(|s| {
+                let mut d = vec![usize::MAX; nqubits];
+                let mut queue = VecDeque::from([s]);
+                d[s] = 0;
+                while let Some(v) = queue.pop_front() {
+                    for &w in &adj[v] {
+                        if d[w] == usize::MAX {
+                            d[w] = d[v] + 1;
+                            queue.push_back(w);
+                        }
+                    }
+                }
+                d
+            })
+            .collect();
+
+        CouplingMap { adj, dist }
+    }
+
+    /// A line of qubits `0 - 1 - ... - (n-1)`
+    pub fn line(n: usize) -> Self {
+        CouplingMap::new(n, (1..n).map(|i| (i - 1, i)))
+    }
+
+    /// A line of qubits with the ends joined up
+    pub fn ring(n: usize) -> Self {
+        CouplingMap::new(n, (0..n).map(|i| (i, (i + 1) % n)))
+    }
+
+    /// A `rows` x `cols` square grid, with qubits numbered row by row
+    pub fn grid(rows: usize, cols: usize) -> Self {
+        let mut edges = vec![];
+        for r in 0..rows {
+            for c in 0..cols {
+                let q = r * cols + c;
+                if c + 1 < cols {
+                    edges.push((q, q + 1));
+                }
+                if r + 1 < rows {
+                    edges.push((q, q + cols));
+                }
+            }
+        }
+        CouplingMap::new(rows * cols, edges)
+    }
+
+    /// A heavy-hex lattice of `rows` x `cols` hexagonal cells
+    ///
+    /// This is a hexagonal lattice, laid out as a brick wall, with an extra qubit
+    /// in the middle of every edge, so all qubits have degree 2 or 3.
+    pub fn heavy_hex(rows: usize, cols: usize) -> Self {
+        // brick wall: rows + 1 horizontal lines joined by alternating rungs
+        let width = 2 * cols + 2;
+        let idx = |r: usize, c: usize| r * width + c;
+        let mut edges = vec![];
+        for r in 0..=rows {
+            for c in 0..width {
+                if c + 1 < width {
+                    edges.push((idx(r, c), idx(r, c + 1)));
+                }
+                if r < rows && (c + r) % 2 == 0 {
+                    edges.push((idx(r, c), idx(r + 1, c)));
+                }
+            }
+        }
+
+        // drop the dangling ends of lines, then renumber the remaining vertices
+        let nverts = (rows + 1) * width;
+        let mut degree = vec![0; nverts];
+        for &(a, b) in &edges {
+            degree[a] += 1;
+            degree[b] += 1;
+        }
+        let mut index = vec![usize::MAX; nverts];
+        let mut n = 0;
+        for v in 0..nverts {
+            if degree[v] > 1 {
+                index[v] = n;
+                n += 1;
+            }
+        }
+        let edges: Vec<_> = edges
+            .into_iter()
+            .filter(|&(a, b)| degree[a] > 1 && degree[b] > 1)
+            .map(|(a, b)| (index[a], index[b]))
+            .collect();
+
+        // put a new qubit on every edge
+        let heavy_edges = edges
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &(a, b))| [(a, n + i), (n + i, b)]);
+        CouplingMap::new(n + edges.len(), heavy_edges)
+    }
+
+    /// Every qubit is connected to every other one
+    pub fn all_to_all(n: usize) -> Self {
+        CouplingMap::new(n, (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b))))
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.adj.len()
+    }
+
+    /// Returns the edges of the coupling map, as pairs `(a, b)` with `a < b`
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.adj
+            .iter()
+            .enumerate()
+            .flat_map(|(a, ns)| ns.iter().filter(move |&&b| a < b).map(move |&b| (a, b)))
+    }
+
+    pub fn neighbors(&self, q: usize) -> &[usize] {
+        &self.adj[q]
+    }
+
+    /// Returns `true` if there is an edge between `a` and `b`
+    pub fn connected(&self, a: usize, b: usize) -> bool {
+        self.dist[a][b] == 1
+    }
+
+    /// Returns the length of the shortest path from `a` to `b`, or `None` if
+    /// there is no path
+    pub fn distance(&self, a: usize, b: usize) -> Option<usize> {
+        Some(self.dist[a][b]).filter(|&d| d != usize::MAX)
+    }
+
+    /// Returns a shortest path from `a` to `b`, including both ends, or `None` if there
+    /// is no path
+    pub fn shortest_path(&self, a: usize, b: usize) -> Option<Vec<usize>> {
+        self.distance(a, b)?;
+        let mut path = vec![a];
+        let mut v = a;
+        while v != b {
+            v = *self.adj[v]
+                .iter()
+                .find(|&&w| self.dist[w][b] < self.dist[v][b])
+                .expect("a vertex on a path has a neighbour closer to the end");
+            path.push(v);
+        }
+        Some(path)
+    }
+
+    /// Returns the coupling map induced on the qubits `qs`, with `qs[i]` renumbered to `i`
+    pub fn subgraph(&self, qs: &[usize]) -> CouplingMap {
+        let mut index = vec![usize::MAX; self.num_qubits()];
+        for (i, &q) in qs.iter().enumerate() {
+            index[q] = i;
+        }
+        let edges = self
+            .edges()
+            .map(|(a, b)| (index[a], index[b]))
+            .filter(|&(a, b)| a != usize::MAX && b != usize::MAX);
+        CouplingMap::new(qs.len(), edges)
+    }
+
+    /// Implements CNOT(`control`, `target`) with CNOTs between neighbouring qubits
+    ///
+    /// The CNOTs run back and forth along a shortest path, which takes 4(d - 1) CNOTs for
+    /// qubits at distance d > 1, and leaves every other qubit unchanged. Returns `None` if
+    /// there is no path.
+    pub fn long_range_cnot(&self, control: usize, target: usize) -> Option<Vec<Gate>> {
+        let path = self.shortest_path(control, target)?;
+        let mut gates = vec![];
+
+        // add path[0] to every other qubit on the path
+        let mut add_to_path = |p: &[usize]| {
+            let cnot = |i: usize| Gate::new(CNOT, vec![p[i], p[i + 1]]);
+            gates.extend((1..p.len() - 1).rev().map(cnot));
+            gates.extend((0..p.len() - 1).map(cnot));
+        };
+        add_to_path(&path);
+        if path.len() > 2 {
+            add_to_path(&path[..path.len() - 1]);
+        }
+        Some(gates)
+    }
+
+    /// Returns `true` if the circuit can run on the device as it is
+    ///
+    /// That is, the circuit fits on the device and every gate on two qubits acts on
+    /// neighbouring qubits. Gates on more than two qubits (other than barriers)
+    /// aren't allowed.
+    pub fn respects(&self, c: &Circuit) -> bool {
+        c.num_qubits() <= self.num_qubits()
+            && c.gates.iter().all(|g| match g.qs.len() {
+                2 if g.t != Barrier => self.connected(g.qs[0], g.qs[1]),
+                0 | 1 => true,
+                _ => g.t == Barrier,
+            })
+    }
+}
+
+/// Parses a coupling map, either given by name or as a list of edges
+///
+/// The named maps are `line:N`, `ring:N`, `grid:RxC`, `heavy-hex:RxC` and
+/// `all:N`. A list of edges is either a JSON list of pairs, e.g. `[[0, 1], [1, 2]]`,
+/// or pairs `a-b` (or `a b`) separated by commas, semicolons or newlines, where
+/// lines starting with `#` are comments. The number of qubits is one more than
+/// the largest qubit in an edge.
+impl FromStr for CouplingMap {
+    type Err = RoutingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some((name, size)) = s.split_once(':') {
+            let num = |x: &str| {
+                x.trim()
+                    .parse::<usize>()
+                    .or_else(|_| invalid_map(format!("bad size '{size}' for '{name}'")))
+            };
+            let dims = || match size.split_once('x') {
+                Some((r, c)) => Ok((num(r)?, num(c)?)),
+                None => invalid_map(format!("expected ROWSxCOLS for '{name}', got '{size}'")),
+            };
+            return match name.trim() {
+                "line" => Ok(CouplingMap::line(num(size)?)),
+                "ring" => Ok(CouplingMap::ring(num(size)?)),
+                "all" => Ok(CouplingMap::all_to_all(num(size)?)),
+                "grid" => dims().map(|(r, c)| CouplingMap::grid(r, c)),
+                "heavy-hex" => dims().map(|(r, c)| CouplingMap::heavy_hex(r, c)),
+                _ => invalid_map(format!("unknown coupling map '{name}'")),
+            };
+        }
+
+        let edges: Vec<(usize, usize)> = if s.starts_with('[') {
+            serde_json::from_str(s).or_else(|e| invalid_map(e.to_string()))?
+        } else {
+            s.lines()
+                .filter(|l| !l.trim_start().starts_with('#'))
+                .flat_map(|l| l.split([',', ';']))
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(|e| {
+                    let qs: Vec<_> = e
+                        .split(['-', ' ', '\t'])
+                        .filter(|q| !q.is_empty())
+                        .collect();
+                    match qs[..] {
+                        [a, b] => match (a.parse(), b.parse()) {
+                            (Ok(a), Ok(b)) => Ok((a, b)),
+                            _ => invalid_map(format!("bad edge '{e}'")),
+                        },
+                        _ => invalid_map(format!("bad edge '{e}'")),
+                    }
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        if edges.is_empty() {
+            return invalid_map("no edges");
+        }
+        let n = edges.iter().map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+        Ok(CouplingMap::new(n, edges))
+    }
+}
+
+/// A circuit that has been routed onto a device, see [`Router`]
+#[derive(Debug, Clone)]
+pub struct RoutedCircuit {
+    /// The routed circuit, acting on the physical qubits of the device
+    pub circuit: Circuit,
+    /// The physical qubit holding each logical qubit at the start of the circuit
+    pub initial_layout: Vec<usize>,
+    /// The physical qubit holding each logical qubit at the end of the circuit
+    pub final_layout: Vec<usize>,
+}
+
+/// Routes circuits onto a device given by a [`CouplingMap`]
+///
+/// Logical qubit `i` starts out on physical qubit `i`. The circuit is first
+/// decomposed into gates on at most two qubits. Then, whenever a two-qubit gate
+/// acts on qubits that aren't neighbours, SWAP gates are inserted to bring them
+/// together. Each SWAP brings the qubits of the gate one step closer, and among the
+/// SWAPs that do, the one that brings the qubits of the next few two-qubit gates
+/// closest together is chosen.
+///
+/// For example, to route a circuit onto a 3x3 grid, keeping the qubits where they
+/// started:
+///
+/// ```
+/// use quizx::circuit::Circuit;
+/// use quizx::routing::{CouplingMap, Router};
+/// let c = Circuit::from_qasm("qreg q[9]; cx q[0], q[8]; cz q[2], q[6];").unwrap();
+/// let map = CouplingMap::grid(3, 3);
+/// let routed = Router::new(&map).restore_layout().route(&c).unwrap();
+/// assert!(map.respects(&routed.circuit));
+/// ```
+pub struct Router<'a> {
+    map: &'a CouplingMap,
+    bridges: bool,
+    restore_layout: bool,
+    lookahead: usize,
+}
+
+impl<'a> Router<'a> {
+    pub fn new(map: &'a CouplingMap) -> Self {
+        Router {
+            map,
+            bridges: false,
+            restore_layout: false,
+            lookahead: 20,
+        }
+    }
+
+    /// Use bridge gates for CNOT and CZ gates between qubits at distance 2
+    ///
+    /// A bridge implements CNOT(a, c) as 4 CNOTs via a common neighbour `b`, which
+    /// leaves the layout unchanged, rather than as a SWAP (3 CNOTs) followed by a
+    /// CNOT.
+    pub fn bridges(&mut self) -> &mut Self {
+        self.bridges = true;
+        self
+    }
+
+    /// Add SWAPs at the end of the circuit to move every qubit back to where it started
+    ///
+    /// The routed circuit then implements the same unitary as the original (on the
+    /// first qubits of the device).
+    pub fn restore_layout(&mut self) -> &mut Self {
+        self.restore_layout = true;
+        self
+    }
+
+    /// Set the number of upcoming two-qubit gates taken into account when choosing
+    /// a SWAP (default: 20)
+    pub fn lookahead(&mut self, n: usize) -> &mut Self {
+        self.lookahead = n;
+        self
+    }
+
+    /// Route the circuit `c`
+    pub fn route(&self, c: &Circuit) -> Result<RoutedCircuit, RoutingError> {
+        let n = self.map.num_qubits();
+        if c.num_qubits() > n {
+            return Err(RoutingError::TooManyQubits {
+                circuit: c.num_qubits(),
+                device: n,
+            });
+        }
+
+        // layout[l] is the physical qubit holding logical qubit l, and qubit[p] the
+        // logical qubit on physical qubit p. Qubits the circuit doesn't use are
+        // treated as idle logical qubits.
+        let mut layout: Vec<usize> = (0..n).collect();
+        let mut qubit: Vec<usize> = (0..n).collect();
+
+        let gates: Vec<Gate> = c.to_basic_gates().gates.into_iter().collect();
+        let twoq: Vec<usize> = (0..gates.len())
+            .filter(|&i| gates[i].qs.len() == 2 && gates[i].t != Barrier)
+            .collect();
+        let mut next_twoq = 0;
+
+        let mut out = Circuit::new(n);
+        for g in &gates {
+            if g.qs.len() == 2 && g.t != Barrier {
+                next_twoq += 1;
+                let upcoming = &twoq[next_twoq..(next_twoq + self.lookahead).min(twoq.len())];
+                let (a, b) = (g.qs[0], g.qs[1]);
+                let mut d = self.distance(layout[a], layout[b])?;
+
+                if self.bridges && d == 2 && matches!(g.t, CNOT | CZ) && !g.is_conditional() {
+                    self.push_bridge(&mut out, g, layout[a], layout[b]);
+                    continue;
+                }
+
+                while d > 1 {
+                    let (p, x) =
+                        self.choose_swap(&layout, a, b, upcoming.iter().map(|&i| &gates[i]));
+                    out.push(Gate::new(SWAP, vec![p, x]));
+                    qubit.swap(p, x);
+                    layout[qubit[p]] = p;
+                    layout[qubit[x]] = x;
+                    d -= 1;
+                }
+            }
+
+            let mut g = g.clone();
+            g.qs.iter_mut().for_each(|q| *q = layout[*q]);
+            out.push(g);
+        }
+
+        if self.restore_layout {
+            self.push_restore(&mut out, &mut layout, &mut qubit);
+        }
+
+        Ok(RoutedCircuit {
+            circuit: out,
+            initial_layout: (0..c.num_qubits()).collect(),
+            final_layout: layout[..c.num_qubits()].to_vec(),
+        })
+    }
+
+    fn distance(&self, p0: usize, p1: usize) -> Result<usize, RoutingError> {
+        self.map
+            .distance(p0, p1)
+            .ok_or(RoutingError::Disconnected { q0: p0, q1: p1 })
+    }
+
+    /// Choose a SWAP that brings logical qubits `a` and `b` closer together
+    ///
+    /// Candidates are SWAPs between the physical qubit of `a` or `b` and a neighbour
+    /// on a shortest path to the other one. Returns the pair of physical qubits to swap.
+    fn choose_swap<'g>(
+        &self,
+        layout: &[usize],
+        a: usize,
+        b: usize,
+        upcoming: impl Iterator<Item = &'g Gate> + Clone,
+    ) -> (usize, usize) {
+        let (pa, pb) = (layout[a], layout[b]);
+        let d = self.map.dist[pa][pb];
+        let candidates = self.map.adj[pa]
+            .iter()
+            .filter(|&&x| self.map.dist[x][pb] < d)
+            .map(|&x| (pa, x))
+            .chain(
+                self.map.adj[pb]
+                    .iter()
+                    .filter(|&&x| self.map.dist[pa][x] < d)
+                    .map(|&x| (pb, x)),
+            );
+
+        // the cost of a SWAP is the total distance between the qubits of upcoming gates
+        let cost = |(p, x): (usize, usize)| -> usize {
+            let phys = |l: usize| match layout[l] {
+                q if q == p => x,
+                q if q == x => p,
+                q => q,
+            };
+            upcoming
+                .clone()
+                .map(|g| self.map.dist[phys(g.qs[0])][phys(g.qs[1])].min(self.map.num_qubits()))
+                .sum()
+        };
+
+        candidates
+            .min_by_key(|&s| cost(s))
+            .expect("a shortest path always gives a candidate SWAP")
+    }
+
+    /// Push a bridge implementing the CNOT or CZ `g` between physical qubits at distance 2
+    fn push_bridge(&self, out: &mut Circuit, g: &Gate, p0: usize, p1: usize) {
+        let m = *self.map.adj[p0]
+            .iter()
+            .find(|&&m| self.map.connected(m, p1))
+            .expect("qubits at distance 2 have a common neighbour");
+        if g.t == CZ {
+            out.push(Gate::new(HAD, vec![p1]));
+        }
+        for _ in 0..2 {
+            out.push(Gate::new(CNOT, vec![p0, m]));
+            out.push(Gate::new(CNOT, vec![m, p1]));
+        }
+        if g.t == CZ {
+            out.push(Gate::new(HAD, vec![p1]));
+        }
+    }
+
+    /// Push SWAPs moving every logical qubit back to the physical qubit with the same index
+    ///
+    /// This uses a BFS spanning tree of each connected component. Vertices are filled
+    /// in reverse BFS order, moving the right qubit along the tree path to each one.
+    /// The vertices that are left always form a subtree, so filled vertices are never
+    /// disturbed.
+    fn push_restore(&self, out: &mut Circuit, layout: &mut [usize], qubit: &mut [usize]) {
+        let n = self.map.num_qubits();
+        let mut parent = vec![usize::MAX; n];
+        let mut order = Vec::with_capacity(n);
+        let mut seen = vec![false; n];
+        for root in 0..n {
+            if seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let start = order.len();
+            order.push(root);
+            let mut i = start;
+            while i < order.len() {
+                let v = order[i];
+                for &w in &self.map.adj[v] {
+                    if !seen[w] {
+                        seen[w] = true;
+                        parent[w] = v;
+                        order.push(w);
+                    }
+                }
+                i += 1;
+            }
+        }
+
+        let ancestors = |mut v: usize| {
+            let mut path = vec![v];
+            while parent[v] != usize::MAX {
+                v = parent[v];
+                path.push(v);
+            }
+            path
+        };
+
+        for &v in order.iter().rev() {
+            let p = layout[v];
+            if p == v {
+                continue;
+            }
+
+            // tree path from p to v, via their lowest common ancestor
+            let mut up = ancestors(p);
+            let mut down = ancestors(v);
+            while up.len() > 1 && down.len() > 1 && up[up.len() - 2] == down[down.len() - 2] {
+                up.pop();
+                down.pop();
+            }
+            if up.last() == down.last() {
+                down.pop();
+            }
+            up.extend(down.into_iter().rev());
+
+            for w in up.windows(2) {
+                out.push(Gate::new(SWAP, vec![w[0], w[1]]));
+                qubit.swap(w[0], w[1]);
+                layout[qubit[w[0]]] = w[0];
+                layout[qubit[w[1]]] = w[1];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+
+    /// the circuit `c` on the first qubits of an `n`-qubit register
+    fn pad(c: &Circuit, n: usize) -> Circuit {
+        let mut c1 = Circuit::new(n);
+        c1 += c;
+        c1
+    }
+
+    #[test]
+    fn maps() {
+        let line = CouplingMap::line(4);
+        assert_eq!(
+            line.edges().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2), (2, 3)]
+        );
+        assert_eq!(line.distance(0, 3), Some(3));
+        assert!(CouplingMap::ring(4).connected(0, 3));
+
+        let grid = CouplingMap::grid(3, 4);
+        assert_eq!(grid.num_qubits(), 12);
+        assert_eq!(grid.edges().count(), 17);
+        assert_eq!(grid.distance(0, 11), Some(5));
+
+        let hex = CouplingMap::heavy_hex(1, 1);
+        assert_eq!(hex.num_qubits(), 12);
+        assert_eq!(hex.distance(0, 6), Some(1));
+        let hex = CouplingMap::heavy_hex(3, 2);
+        assert!((0..hex.num_qubits()).all(|q| (2..=3).contains(&hex.neighbors(q).len())));
+        assert!((0..hex.num_qubits()).all(|q| hex.distance(0, q).is_some()));
+
+        let disconnected = CouplingMap::new(4, [(0, 1), (2, 3), (1, 0), (2, 2)]);
+        assert_eq!(disconnected.edges().count(), 2);
+        assert_eq!(disconnected.distance(1, 2), None);
+    }
+
+    #[test]
+    fn paths() {
+        let grid = CouplingMap::grid(3, 3);
+        let path = grid.shortest_path(0, 8).unwrap();
+        assert_eq!(path.len(), 5);
+        assert!(path.windows(2).all(|w| grid.connected(w[0], w[1])));
+
+        let sub = grid.subgraph(&[4, 0, 1, 3]);
+        assert_eq!(
+            sub.edges().collect::<Vec<_>>(),
+            vec![(0, 2), (0, 3), (1, 2), (1, 3)]
+        );
+
+        for (a, b) in [(0, 1), (0, 8), (6, 2), (5, 3)] {
+            let gates = grid.long_range_cnot(a, b).unwrap();
+            let d = grid.distance(a, b).unwrap();
+            assert_eq!(gates.len(), if d == 1 { 1 } else { 4 * (d - 1) });
+            let mut c = Circuit::new(9);
+            gates.into_iter().for_each(|g| c.push(g));
+            assert!(grid.respects(&c));
+            let mut cnot = Circuit::new(9);
+            cnot.push(Gate::new(CNOT, vec![a, b]));
+            assert_eq!(c.to_tensor4(), cnot.to_tensor4());
+        }
+
+        let disconnected = CouplingMap::new(4, [(0, 1), (2, 3)]);
+        assert!(disconnected.long_range_cnot(0, 3).is_none());
+    }
+
+    #[test]
+    fn parse() {
+        let grid: CouplingMap = "grid:2x3".parse().unwrap();
+        assert_eq!(grid, CouplingMap::grid(2, 3));
+        let line: CouplingMap = "0-1, 1-2;2 3".parse().unwrap();
+        assert_eq!(line, CouplingMap::line(4));
+        let line: CouplingMap = "# a line\n0 1\n\n1 2\n".parse().unwrap();
+        assert_eq!(line, CouplingMap::line(3));
+        let ring: CouplingMap = "[[0, 1], [1, 2], [2, 0]]".parse().unwrap();
+        assert_eq!(ring, CouplingMap::ring(3));
+
+        for bad in [
+            "grid:3",
+            "torus:4",
+            "line:x",
+            "0-1-2",
+            "0-a",
+            "",
+            "[[0, 1, 2]]",
+        ] {
+            assert!(
+                matches!(
+                    bad.parse::<CouplingMap>(),
+                    Err(RoutingError::InvalidMap { .. })
+                ),
+                "expected an error for '{bad}'"
+            );
+        }
+    }
+
+    #[test]
+    fn route_random() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(6)
+            .depth(60)
+            .p_t(0.2)
+            .p_cz(0.2)
+            .with_cliffords()
+            .build();
+        for map in [
+            CouplingMap::line(6),
+            CouplingMap::ring(6),
+            CouplingMap::grid(2, 3),
+        ] {
+            let routed = Router::new(&map).route(&c).unwrap();
+            assert!(map.respects(&routed.circuit));
+            assert!(!map.respects(&c) || routed.circuit.num_gates_of_type(SWAP) == 0);
+
+            let mut final_layout = routed.final_layout.clone();
+            final_layout.sort();
+            assert_eq!(final_layout, routed.initial_layout);
+
+            let routed = Router::new(&map).restore_layout().route(&c).unwrap();
+            assert!(map.respects(&routed.circuit));
+            assert_eq!(routed.final_layout, routed.initial_layout);
+            assert!(Tensor4::scalar_compare(&c, &routed.circuit));
+        }
+    }
+
+    #[test]
+    fn route_bigger_device() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[4];
+            ccx q[0], q[1], q[3];
+            cx q[3], q[0];
+            cz q[2], q[1];
+            swap q[0], q[2];
+        "#,
+        )
+        .unwrap();
+        let map = CouplingMap::new(6, [(0, 4), (4, 1), (1, 5), (5, 2), (2, 3), (3, 0)]);
+        let routed = Router::new(&map).restore_layout().route(&c).unwrap();
+        assert!(map.respects(&routed.circuit));
+        assert!(Tensor4::scalar_compare(&pad(&c, 6), &routed.circuit));
+
+        let hex = CouplingMap::heavy_hex(2, 2);
+        let c = Circuit::random()
+            .seed(42)
+            .qubits(20)
+            .depth(200)
+            .p_cz(0.3)
+            .with_cliffords()
+            .build();
+        let routed = Router::new(&hex).route(&c).unwrap();
+        assert!(hex.respects(&routed.circuit));
+        assert_eq!(routed.circuit.num_qubits(), hex.num_qubits());
+    }
+
+    #[test]
+    fn bridges() {
+        let c = Circuit::from_qasm("qreg q[3]; cx q[0], q[2]; cz q[2], q[0]; t q[1];").unwrap();
+        let map = CouplingMap::line(3);
+        let routed = Router::new(&map).bridges().route(&c).unwrap();
+        assert!(map.respects(&routed.circuit));
+        assert_eq!(routed.circuit.num_gates_of_type(SWAP), 0);
+        assert_eq!(routed.circuit.num_gates_of_type(CNOT), 8);
+        assert_eq!(routed.final_layout, vec![0, 1, 2]);
+        assert!(Tensor4::scalar_compare(&c, &routed.circuit));
+    }
+
+    #[test]
+    fn errors() {
+        let c = Circuit::from_qasm("qreg q[3]; cx q[0], q[2];").unwrap();
+        assert!(matches!(
+            Router::new(&CouplingMap::line(2)).route(&c),
+            Err(RoutingError::TooManyQubits {
+                circuit: 3,
+                device: 2
+            })
+        ));
+        assert!(matches!(
+            Router::new(&CouplingMap::new(3, [(0, 1)])).route(&c),
+            Err(RoutingError::Disconnected { q0: 0, q1: 2 })
+        ));
+    }
+}
@@ -18,6 +18,7 @@ use approx::AbsDiffEq;
 use num::complex::Complex;
 pub use num::traits::identities::{One, Zero};
 use num::{Rational64, ToPrimitive};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::{PI, SQRT_2};
 use std::fmt;
 use std::iter::{Product, Sum};
@@ -44,7 +45,7 @@ pub use dyadic::Dyadic;
 /// Note that ω² = i, and [`Dyadic`] can store any [`f64`] losslessly. Hence, for all
 /// other complex numbers, [`Scalar4`] gives an approximate representation of that number
 /// as a + c ω² = a + i c. This allows easy conversions to and from [`Complex<f64>`].
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Scalar4([Dyadic; 4]);
 
 impl Scalar4 {
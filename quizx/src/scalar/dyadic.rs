@@ -1,6 +1,7 @@
 use approx::AbsDiffEq;
 use derive_more::derive::{Display, Error};
 use num::{Float, Zero};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::fmt;
 use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
@@ -21,7 +22,7 @@ const APPROX: u8 = 0x02;
 const APPROX_OFF: u8 = 0xff ^ APPROX;
 
 // A dyadic is essentially a floating point number, but we have more control over precision
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dyadic {
     flags: u8,
     exp: Exponent,
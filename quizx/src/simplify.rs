@@ -306,6 +306,7 @@ pub fn fuse_gadgets(g: &mut impl GraphLike) -> bool {
             let mut ph = Phase::zero();
             for (u, v) in gs.iter().skip(1).copied() {
                 ph += g.phase(v);
+                g.fuse_phases(gs[0].1, v);
                 g.remove_vertex(u);
                 g.remove_vertex(v);
             }
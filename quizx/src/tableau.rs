@@ -0,0 +1,485 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stabilizer tableaux for Clifford unitaries
+//!
+//! A [`Tableau`] stores a Clifford unitary U (up to global phase) as the images
+//! U P U† of the single-qubit Pauli operators X and Z on each qubit, as in
+//! Aaronson and Gottesman, "Improved simulation of stabilizer circuits"
+//! (arXiv:quant-ph/0406196). Two Clifford circuits are equal up to global phase
+//! exactly when their tableaux are equal, so this gives a cheap way to compare
+//! Clifford circuits without contracting any tensors.
+
+use crate::circuit::Circuit;
+use crate::extract::ToCircuit;
+use crate::gate::*;
+use crate::graph::GraphLike;
+use crate::pauli::{is_unitary_clifford, PauliString};
+use crate::simplify::clifford_simp;
+use rand::Rng;
+use std::fmt;
+
+/// A Clifford unitary, stored as the images of single-qubit Paulis
+///
+/// For a Clifford U on n qubits, this stores U X_q U† and U Z_q U† for each qubit
+/// q. These are the destabilizers and stabilizers of Aaronson and Gottesman,
+/// respectively. Global phases are not tracked, so circuits that are equal up to
+/// a global phase give equal tableaux.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tableau {
+    nqubits: usize,
+    /// the images of X_0, ..., X_(n-1), followed by the images of Z_0, ..., Z_(n-1)
+    rows: Vec<PauliString>,
+}
+
+impl Tableau {
+    /// The tableau of the identity on `nqubits` qubits
+    pub fn new(nqubits: usize) -> Self {
+        let rows = (0..nqubits)
+            .map(|q| PauliString::x(nqubits, q))
+            .chain((0..nqubits).map(|q| PauliString::z(nqubits, q)))
+            .collect();
+        Tableau { nqubits, rows }
+    }
+
+    /// Builds the tableau of a Clifford circuit
+    ///
+    /// Returns `None` if the circuit contains any gates that are not unitary
+    /// Clifford gates, such as T gates, measurements or classically controlled
+    /// gates. Barriers are ignored.
+    pub fn from_circuit(c: &Circuit) -> Option<Self> {
+        let mut t = Tableau::new(c.num_qubits());
+        for g in &c.to_basic_gates().gates {
+            if !t.apply_gate(g) {
+                return None;
+            }
+        }
+        Some(t)
+    }
+
+    /// Builds the tableau of a Clifford ZX-diagram
+    ///
+    /// The diagram is first reduced with [`clifford_simp`], which puts any Clifford
+    /// diagram with a circuit-like structure into GSLC normal form, and a circuit is
+    /// extracted from that. Returns `None` if the diagram has a non-Clifford phase or
+    /// boolean variables, or if a circuit can't be extracted from it.
+    pub fn from_graph<G: ToCircuit>(g: &G) -> Option<Self> {
+        if g.inputs().len() != g.outputs().len()
+            || g.vertices()
+                .any(|v| !g.phase(v).is_clifford() || !g.vars(v).is_empty())
+        {
+            return None;
+        }
+        let mut g = g.clone();
+        clifford_simp(&mut g);
+        let c = g.to_circuit_mut().ok()?;
+        Tableau::from_circuit(&c)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.nqubits
+    }
+
+    /// The image U X_q U† of X on qubit `q`
+    pub fn x_image(&self, q: usize) -> &PauliString {
+        &self.rows[q]
+    }
+
+    /// The image U Z_q U† of Z on qubit `q`
+    pub fn z_image(&self, q: usize) -> &PauliString {
+        &self.rows[self.nqubits + q]
+    }
+
+    /// Returns true if this is the tableau of the identity, i.e. of a circuit that
+    /// is the identity up to global phase
+    pub fn is_identity(&self) -> bool {
+        *self == Tableau::new(self.nqubits)
+    }
+
+    /// Computes U P U†, where U is the Clifford of this tableau
+    pub fn conjugate(&self, p: &PauliString) -> PauliString {
+        let mut p1 = PauliString::identity(self.nqubits);
+        p1.phase = p.phase;
+        for q in 0..self.nqubits {
+            if p.x[q] {
+                p1.mul_right(self.x_image(q));
+            }
+            if p.z[q] {
+                p1.mul_right(self.z_image(q));
+            }
+        }
+        p1
+    }
+
+    /// The tableau of the Clifford that applies this one, followed by `other`
+    ///
+    /// Panics if the tableaux are on a different number of qubits.
+    pub fn then(&self, other: &Tableau) -> Tableau {
+        assert_eq!(
+            self.nqubits, other.nqubits,
+            "Tableaux have different numbers of qubits"
+        );
+        Tableau {
+            nqubits: self.nqubits,
+            rows: self.rows.iter().map(|p| other.conjugate(p)).collect(),
+        }
+    }
+
+    /// The tableau of the inverse Clifford
+    pub fn inverse(&self) -> Tableau {
+        let mut t = Tableau::new(self.nqubits);
+        for g in &self.inverse_gates().gates {
+            t.apply_gate(g);
+        }
+        t
+    }
+
+    /// Synthesizes a Clifford circuit with this tableau
+    ///
+    /// The circuit uses H, S, Sdg, CNOT, CZ, SWAP and Pauli gates. It is built one
+    /// qubit at a time by Gaussian elimination on the tableau, so has O(n^2) gates.
+    /// The global phase of the circuit is arbitrary.
+    pub fn to_circuit(&self) -> Circuit {
+        self.inverse_gates().to_adjoint()
+    }
+
+    /// Converts the tableau to a ZX-diagram, by way of [`Tableau::to_circuit`]
+    pub fn to_graph<G: GraphLike>(&self) -> G {
+        self.to_circuit().to_graph()
+    }
+
+    /// Applies a gate after the Clifford, returning false and leaving the tableau
+    /// unchanged if the gate isn't a unitary Clifford gate
+    pub fn apply_gate(&mut self, g: &Gate) -> bool {
+        if !is_unitary_clifford(g) {
+            return false;
+        }
+        self.rows.iter_mut().for_each(|p| {
+            p.apply_gate(g);
+        });
+        true
+    }
+
+    /// Applies a Hadamard gate on qubit `q` after the Clifford
+    pub fn h(&mut self, q: usize) {
+        self.rows.iter_mut().for_each(|p| p.h(q));
+    }
+
+    /// Applies an S gate on qubit `q` after the Clifford
+    pub fn s(&mut self, q: usize) {
+        self.rows.iter_mut().for_each(|p| p.s(q));
+    }
+
+    /// Applies an S† gate on qubit `q` after the Clifford
+    pub fn sdg(&mut self, q: usize) {
+        self.rows.iter_mut().for_each(|p| p.sdg(q));
+    }
+
+    /// Applies an X gate on qubit `q` after the Clifford
+    pub fn x(&mut self, q: usize) {
+        self.rows.iter_mut().for_each(|p| p.pauli_x(q));
+    }
+
+    /// Applies a Z gate on qubit `q` after the Clifford
+    pub fn z(&mut self, q: usize) {
+        self.rows.iter_mut().for_each(|p| p.pauli_z(q));
+    }
+
+    /// Applies a CNOT gate after the Clifford
+    pub fn cnot(&mut self, c: usize, t: usize) {
+        self.rows.iter_mut().for_each(|p| p.cnot(c, t));
+    }
+
+    /// Applies a CZ gate after the Clifford
+    pub fn cz(&mut self, q0: usize, q1: usize) {
+        self.rows.iter_mut().for_each(|p| p.cz(q0, q1));
+    }
+
+    /// Applies a SWAP gate after the Clifford
+    pub fn swap(&mut self, q0: usize, q1: usize) {
+        self.rows.iter_mut().for_each(|p| p.swap(q0, q1));
+    }
+
+    /// Returns a circuit for the inverse Clifford
+    ///
+    /// Gates are applied to a copy of the tableau until it becomes the identity, so
+    /// together they give the inverse. For each qubit q in turn, the images of X_q
+    /// and Z_q are reduced with [`reduce_pair`]. Since the other images commute with
+    /// X_q and Z_q, they no longer act on qubit q, so later steps don't undo earlier
+    /// ones. Signs are fixed at the end.
+    fn inverse_gates(&self) -> Circuit {
+        let n = self.nqubits;
+        let mut t = self.clone();
+        let mut c = Circuit::new(n);
+        for q in 0..n {
+            reduce_pair(&mut t.rows, q, n + q, q, &mut c);
+        }
+
+        for q in 0..n {
+            if t.rows[q].phase != 0 {
+                let g = Gate::new(Z, vec![q]);
+                t.apply_gate(&g);
+                c.push(g);
+            }
+            if t.rows[n + q].phase != 0 {
+                let g = Gate::new(NOT, vec![q]);
+                t.apply_gate(&g);
+                c.push(g);
+            }
+        }
+
+        debug_assert!(t.is_identity());
+        c
+    }
+
+    /// Samples a Clifford on `nqubits` qubits uniformly at random
+    ///
+    /// This uses the method of Koenig and Smolin, "How to efficiently select an
+    /// arbitrary Clifford group element" (arXiv:1406.2170). A uniformly random
+    /// Clifford U can be written as U = G_0 (I ⊗ U'), where U' is a uniformly random
+    /// Clifford on the remaining qubits, and G_0 maps X_0 and Z_0 to a uniformly
+    /// random pair of anticommuting Paulis. Signs are chosen by a random Pauli at
+    /// the start.
+    pub fn random(nqubits: usize, rng: &mut impl Rng) -> Tableau {
+        let n = nqubits;
+        let mut c = Circuit::new(n);
+        for q in 0..n {
+            if rng.gen_bool(0.5) {
+                c.push(Gate::new(NOT, vec![q]));
+            }
+            if rng.gen_bool(0.5) {
+                c.push(Gate::new(Z, vec![q]));
+            }
+        }
+        let mut gs = Vec::with_capacity(n);
+        for q in 0..n {
+            let p = loop {
+                let p = PauliString::random_from(n, q, rng);
+                if !p.is_identity() {
+                    break p;
+                }
+            };
+            let p1 = loop {
+                let p1 = PauliString::random_from(n, q, rng);
+                if !p1.commutes(&p) {
+                    break p1;
+                }
+            };
+
+            // G_q is the inverse of the gates reducing the pair to X_q, Z_q
+            let mut r = Circuit::new(n);
+            reduce_pair(&mut [p, p1], 0, 1, q, &mut r);
+            gs.push(r.to_adjoint());
+        }
+        for g in gs.into_iter().rev() {
+            c.gates.extend(g.gates);
+        }
+        Tableau::from_circuit(&c).expect("Circuit should be Clifford")
+    }
+}
+
+/// Reduces a pair of anticommuting Paulis to X and Z on qubit `q`, up to sign
+///
+/// The Paulis are `ps[ix]` and `ps[iz]`, which should act only on qubits `q` and
+/// higher. Gates on these qubits are applied to every Pauli in `ps`, and added to
+/// `c`, until `ps[ix]` is ±X_q and `ps[iz]` is ±Z_q.
+fn reduce_pair(ps: &mut [PauliString], ix: usize, iz: usize, q: usize, c: &mut Circuit) {
+    let n = c.num_qubits();
+    let mut push = |ps: &mut [PauliString], g: Gate| {
+        ps.iter_mut().for_each(|p| {
+            p.apply_gate(&g);
+        });
+        c.push(g);
+    };
+
+    // move some non-identity part of the first Pauli to an X on qubit q
+    let q1 = (q..n)
+        .find(|&q1| ps[ix].x[q1] || ps[ix].z[q1])
+        .expect("Paulis should anticommute");
+    if ps[ix].z[q1] {
+        let gt = if ps[ix].x[q1] { S } else { HAD };
+        push(ps, Gate::new(gt, vec![q1]));
+    }
+    if q1 != q {
+        push(ps, Gate::new(SWAP, vec![q, q1]));
+    }
+
+    // reduce the first Pauli to X_q, up to sign
+    for q1 in q + 1..n {
+        if ps[ix].x[q1] {
+            push(ps, Gate::new(CNOT, vec![q, q1]));
+        }
+    }
+    for q1 in q + 1..n {
+        if ps[ix].z[q1] {
+            push(ps, Gate::new(CZ, vec![q, q1]));
+        }
+    }
+    if ps[ix].z[q] {
+        push(ps, Gate::new(S, vec![q]));
+    }
+
+    // the second Pauli anticommutes with X_q, so has a Z on qubit q. Swap X_q and
+    // Z_q with a Hadamard, then reduce the second Pauli in the same way
+    push(ps, Gate::new(HAD, vec![q]));
+    for q1 in q + 1..n {
+        if ps[iz].x[q1] {
+            push(ps, Gate::new(CNOT, vec![q, q1]));
+        }
+    }
+    for q1 in q + 1..n {
+        if ps[iz].z[q1] {
+            push(ps, Gate::new(CZ, vec![q, q1]));
+        }
+    }
+    if ps[iz].z[q] {
+        push(ps, Gate::new(S, vec![q]));
+    }
+    push(ps, Gate::new(HAD, vec![q]));
+}
+
+impl fmt::Display for Tableau {
+    /// Writes the image of each X_q and Z_q on its own line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for q in 0..self.nqubits {
+            writeln!(f, "X{q} -> {}", self.x_image(q))?;
+        }
+        for q in 0..self.nqubits {
+            writeln!(f, "Z{q} -> {}", self.z_image(q))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::ToTensor;
+    use crate::vec_graph::Graph;
+
+    fn random_clifford(seed: u64, qubits: usize, depth: usize) -> Circuit {
+        Circuit::random()
+            .seed(seed)
+            .qubits(qubits)
+            .depth(depth)
+            .p_cz(0.1)
+            .with_cliffords()
+            .build()
+    }
+
+    /// Compares two circuits up to a global phase by contracting their tensors
+    fn equal_up_to_phase(c1: &Circuit, c2: &Circuit) -> bool {
+        let (a, b) = (c1.to_tensorf(), c2.to_tensorf());
+        let i = a.iter().position(|x| x.norm() > 1e-6).unwrap();
+        let r = b.iter().nth(i).unwrap() / a.iter().nth(i).unwrap();
+        a.iter()
+            .zip(b.iter())
+            .all(|(x, y)| (x * r - y).norm() < 1e-6)
+    }
+
+    #[test]
+    fn single_gates() {
+        let c = Circuit::from_qasm("qreg q[2]; h q[0]; s q[1]; cx q[0], q[1];").unwrap();
+        let t = Tableau::from_circuit(&c).unwrap();
+        assert_eq!(t.x_image(0).to_string(), "+ZI");
+        assert_eq!(t.z_image(0).to_string(), "+XX");
+        assert_eq!(t.x_image(1).to_string(), "+ZY");
+        assert_eq!(t.z_image(1).to_string(), "+ZZ");
+
+        let c = Circuit::from_qasm("qreg q[1]; x q[0]; s q[0]; rx(0.5*pi) q[0];").unwrap();
+        let t = Tableau::from_circuit(&c).unwrap();
+        assert_eq!(t.x_image(0).to_string(), "+Z");
+        assert_eq!(t.z_image(0).to_string(), "+Y");
+    }
+
+    #[test]
+    fn non_clifford() {
+        let c = Circuit::from_qasm("qreg q[1]; t q[0];").unwrap();
+        assert_eq!(Tableau::from_circuit(&c), None);
+        let c = Circuit::from_qasm("qreg q[1]; rz(0.5*pi) q[0]; rz(-pi) q[0];").unwrap();
+        assert!(Tableau::from_circuit(&c).is_some());
+    }
+
+    #[test]
+    fn global_phase() {
+        let c1 = Circuit::from_qasm("qreg q[1]; s q[0]; s q[0];").unwrap();
+        let c2 = Circuit::from_qasm("qreg q[1]; z q[0];").unwrap();
+        let c3 = Circuit::from_qasm("qreg q[1]; h q[0]; x q[0]; h q[0];").unwrap();
+        let c4 = Circuit::from_qasm("qreg q[1]; x q[0];").unwrap();
+        let t1 = Tableau::from_circuit(&c1).unwrap();
+        assert_eq!(t1, Tableau::from_circuit(&c2).unwrap());
+        assert_eq!(t1, Tableau::from_circuit(&c3).unwrap());
+        assert_ne!(t1, Tableau::from_circuit(&c4).unwrap());
+    }
+
+    #[test]
+    fn synthesis() {
+        for seed in [1337, 800, 40104, 42] {
+            let c = random_clifford(seed, 5, 80);
+            let t = Tableau::from_circuit(&c).unwrap();
+            let c1 = t.to_circuit();
+            assert_eq!(Tableau::from_circuit(&c1).unwrap(), t);
+            assert!(equal_up_to_phase(&c, &c1));
+        }
+    }
+
+    #[test]
+    fn compose_and_invert() {
+        let c1 = random_clifford(1, 4, 40);
+        let c2 = random_clifford(2, 4, 40);
+        let t1 = Tableau::from_circuit(&c1).unwrap();
+        let t2 = Tableau::from_circuit(&c2).unwrap();
+
+        let mut c = c1.clone();
+        c.gates.extend(c2.gates.iter().cloned());
+        assert_eq!(t1.then(&t2), Tableau::from_circuit(&c).unwrap());
+
+        assert!(t1.then(&t1.inverse()).is_identity());
+        assert!(t1.inverse().then(&t1).is_identity());
+        assert_eq!(
+            t1.inverse(),
+            Tableau::from_circuit(&c1.to_adjoint()).unwrap()
+        );
+    }
+
+    #[test]
+    fn conjugate() {
+        let c = random_clifford(7, 3, 30);
+        let t = Tableau::from_circuit(&c).unwrap();
+        // conjugation preserves products
+        let p1: PauliString = "XYZ".parse().unwrap();
+        let p2: PauliString = "ZZX".parse().unwrap();
+        let mut p = p1.clone();
+        p.mul_right(&p2);
+        let mut q = t.conjugate(&p1);
+        q.mul_right(&t.conjugate(&p2));
+        assert_eq!(t.conjugate(&p), q);
+    }
+
+    #[test]
+    fn graph_conversion() {
+        for seed in [3, 4, 5] {
+            let c = random_clifford(seed, 4, 50);
+            let t = Tableau::from_circuit(&c).unwrap();
+            let mut g: Graph = c.to_graph();
+            clifford_simp(&mut g);
+            assert_eq!(Tableau::from_graph(&g), Some(t.clone()));
+
+            let g1: Graph = t.to_graph();
+            assert_eq!(Tableau::from_graph(&g1), Some(t));
+        }
+    }
+}
@@ -122,7 +122,10 @@ pub trait QubitOps<A: TensorElem> {
     fn hadamard_at(&mut self, i: usize);
 
     /// split into two non-overlapping pieces, where index q=0 and q=1
-    fn slice_qubit_mut(&mut self, q: usize) -> (ArrayViewMut<A, IxDyn>, ArrayViewMut<A, IxDyn>);
+    fn slice_qubit_mut(
+        &mut self,
+        q: usize,
+    ) -> (ArrayViewMut<'_, A, IxDyn>, ArrayViewMut<'_, A, IxDyn>);
 
     /// contract the last n qubit indices with the first n qubits of other
     ///
@@ -177,7 +180,10 @@ impl<A: TensorElem> CompareTensors for Tensor<A> {
 }
 
 impl<A: TensorElem> QubitOps<A> for Tensor<A> {
-    fn slice_qubit_mut(&mut self, q: usize) -> (ArrayViewMut<A, IxDyn>, ArrayViewMut<A, IxDyn>) {
+    fn slice_qubit_mut(
+        &mut self,
+        q: usize,
+    ) -> (ArrayViewMut<'_, A, IxDyn>, ArrayViewMut<'_, A, IxDyn>) {
         let slice0: SliceInfo<_, IxDyn, IxDyn> =
             SliceInfo::try_from(Vec::from_iter((0..self.ndim()).map(|i| {
                 if i == q {
@@ -410,6 +416,10 @@ impl ToTensor for Circuit {
         // computes the transpose of the circuit, but all the gates are self-
         // transposed, so we can get the circuit itself if we just reverse the order.
         for g in self.gates.iter().rev() {
+            if g.is_conditional() {
+                panic!("Unsupported classically controlled gate: {g:?}")
+            }
+
             match g.t {
                 ZPhase => a.cphase_at(g.phase, &g.qs),
                 Z | CZ | CCZ => a.cphase_at(1, &g.qs),
@@ -463,6 +473,7 @@ impl ToTensor for Circuit {
                 MeasureReset => {
                     panic!("Unsupported gate: MeasureReset")
                 }
+                Barrier => {}
                 UnknownGate => {} // unknown gates are quietly ignored
             }
         }
@@ -0,0 +1,615 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluation of ZX-diagrams as tensor networks.
+//!
+//! Unlike [`ToTensor`](crate::tensor::ToTensor), which contracts a diagram one vertex at a
+//! time into a single dense tensor, a [`TensorNetwork`] keeps one small tensor per spider and
+//! contracts them pairwise in an order chosen from the structure of the diagram. This makes
+//! it possible to compute scalars and small tensors of large diagrams, as long as they have
+//! low treewidth (e.g. shallow circuits).
+
+use crate::graph::*;
+use crate::tensor::{Tensor, TensorElem};
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Spiders with more legs than this are split into a chain of smaller tensors.
+const MAX_SPIDER_RANK: usize = 3;
+
+/// A tensor whose axes are labelled by the indices of the network they belong to.
+#[derive(Debug, Clone)]
+struct LabelledTensor<A> {
+    tensor: Tensor<A>,
+    labels: Vec<usize>,
+}
+
+/// An order in which to contract the tensors of a [`TensorNetwork`].
+///
+/// Each step contracts two tensors and appends the result to the list of tensors, i.e. the
+/// result of the k-th step gets the index `n + k`, where `n` is the number of tensors in the
+/// network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractionPath {
+    pub steps: Vec<(usize, usize)>,
+    /// Estimated number of multiply-adds needed to perform the contraction.
+    pub flops: f64,
+    /// Largest rank of a tensor occurring during the contraction. Memory usage is
+    /// proportional to `2^max_rank`.
+    pub max_rank: usize,
+}
+
+/// A ZX-diagram, viewed as a network of tensors.
+///
+/// The open indices of the network are the inputs followed by the outputs of the diagram,
+/// which is the same axis order as [`ToTensor::to_tensor`](crate::tensor::ToTensor::to_tensor).
+#[derive(Debug, Clone)]
+pub struct TensorNetwork<A> {
+    tensors: Vec<LabelledTensor<A>>,
+    open: Vec<usize>,
+}
+
+impl<A: TensorElem> TensorNetwork<A> {
+    /// Builds a tensor network from a graph.
+    ///
+    /// Every Z or X spider becomes a phased delta tensor, split into a chain of rank 3 tensors
+    /// if it has many legs, and every Hadamard edge becomes a Hadamard matrix. The scalar of
+    /// the graph is kept as a rank 0 tensor.
+    ///
+    /// Panics if the graph contains vertices other than boundaries and Z or X spiders.
+    pub fn from_graph<G: GraphLike + Clone>(g: &G) -> Self {
+        let mut g = g.clone();
+        g.x_to_z();
+        for v in g.vertices() {
+            let t = g.vertex_type(v);
+            if t != VType::B && t != VType::Z {
+                panic!("Vertex type currently unsupported: {t:?}");
+            }
+        }
+
+        let mut tn = TensorNetwork {
+            tensors: vec![LabelledTensor {
+                tensor: Tensor::from_elem(vec![], A::try_from(*g.scalar()).unwrap()),
+                labels: vec![],
+            }],
+            open: vec![],
+        };
+        let mut next_label = 0;
+        let mut fresh = || {
+            next_label += 1;
+            next_label - 1
+        };
+
+        let mut legs: FxHashMap<V, Vec<usize>> = FxHashMap::default();
+        for (s, t, et) in g.edges() {
+            let l0 = fresh();
+            let l1 = if et == EType::H {
+                let l1 = fresh();
+                tn.tensors.push(LabelledTensor {
+                    tensor: hadamard(),
+                    labels: vec![l0, l1],
+                });
+                l1
+            } else if g.vertex_type(s) == VType::B && g.vertex_type(t) == VType::B {
+                // a bare wire between two boundaries still needs a tensor to carry it
+                let l1 = fresh();
+                tn.tensors.push(LabelledTensor {
+                    tensor: spider(A::one(), 2),
+                    labels: vec![l0, l1],
+                });
+                l1
+            } else {
+                l0
+            };
+            legs.entry(s).or_default().push(l0);
+            legs.entry(t).or_default().push(l1);
+        }
+
+        for v in g.vertices() {
+            if g.vertex_type(v) == VType::B {
+                continue;
+            }
+            let ls = legs.remove(&v).unwrap_or_default();
+            let phase = A::from_phase(g.phase(v));
+            if ls.len() <= MAX_SPIDER_RANK {
+                tn.tensors.push(LabelledTensor {
+                    tensor: spider(phase, ls.len()),
+                    labels: ls,
+                });
+            } else {
+                // split into a chain of rank 3 deltas, with the phase on the first one
+                let mut prev = fresh();
+                tn.tensors.push(LabelledTensor {
+                    tensor: spider(phase, 3),
+                    labels: vec![ls[0], ls[1], prev],
+                });
+                for &l in &ls[2..ls.len() - 2] {
+                    let next = fresh();
+                    tn.tensors.push(LabelledTensor {
+                        tensor: spider(A::one(), 3),
+                        labels: vec![prev, l, next],
+                    });
+                    prev = next;
+                }
+                tn.tensors.push(LabelledTensor {
+                    tensor: spider(A::one(), 3),
+                    labels: vec![prev, ls[ls.len() - 2], ls[ls.len() - 1]],
+                });
+            }
+        }
+
+        for &b in g.inputs().iter().chain(g.outputs()) {
+            match legs.get(&b).map(|ls| ls.as_slice()) {
+                Some(&[l]) => tn.open.push(l),
+                _ => panic!("Boundary vertex {b} should have exactly one edge"),
+            }
+        }
+
+        if legs.len() != tn.open.len() {
+            panic!("All boundary vertices must be an input or an output");
+        }
+
+        tn
+    }
+
+    /// Returns the number of tensors in the network.
+    pub fn num_tensors(&self) -> usize {
+        self.tensors.len()
+    }
+
+    fn initial_labels(&self) -> Vec<Vec<usize>> {
+        self.tensors
+            .iter()
+            .map(|t| {
+                let mut ls = t.labels.clone();
+                ls.sort();
+                ls
+            })
+            .collect()
+    }
+
+    /// Finds a contraction path by repeatedly contracting the pair of neighbouring tensors
+    /// that shrinks the network the most (or grows it the least).
+    pub fn greedy_path(&self) -> ContractionPath {
+        let mut planner = Planner::new(self.initial_labels());
+        loop {
+            let best = planner
+                .contractible_pairs()
+                .map(|(a, b)| {
+                    let r = planner.result_rank(a, b);
+                    let cost = rank_size(r)
+                        - rank_size(planner.labels[a].len())
+                        - rank_size(planner.labels[b].len());
+                    (cost, a, b)
+                })
+                .min_by(|x, y| x.partial_cmp(y).unwrap());
+            match best {
+                Some((_, a, b)) => planner.contract(a, b),
+                None => break,
+            }
+        }
+        planner.finish()
+    }
+
+    /// Finds a contraction path from a tree decomposition of the network.
+    ///
+    /// The indices of the network are eliminated in a min-fill order on their interaction
+    /// graph, i.e. the line graph of the network, and eliminating an index contracts the two
+    /// tensors it connects. The largest rank occurring is then bounded by the width of the
+    /// resulting tree decomposition.
+    pub fn min_fill_path(&self) -> ContractionPath {
+        let labels = self.initial_labels();
+        let open: FxHashSet<usize> = self.open.iter().copied().collect();
+
+        let mut adj: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for ls in &labels {
+            for &l in ls {
+                let nhd = adj.entry(l).or_default();
+                nhd.extend(ls.iter().copied().filter(|&l1| l1 != l));
+            }
+        }
+
+        let fill = |adj: &BTreeMap<usize, BTreeSet<usize>>, l: usize| {
+            let nhd: Vec<_> = adj[&l].iter().copied().collect();
+            let mut f = 0;
+            for (i, x) in nhd.iter().enumerate() {
+                for y in &nhd[i + 1..] {
+                    if !adj[x].contains(y) {
+                        f += 1;
+                    }
+                }
+            }
+            (f, nhd.len(), l)
+        };
+
+        let mut queue: BTreeSet<(usize, usize, usize)> = adj
+            .keys()
+            .filter(|l| !open.contains(l))
+            .map(|&l| fill(&adj, l))
+            .collect();
+        let mut key: FxHashMap<usize, (usize, usize, usize)> =
+            queue.iter().map(|&k| (k.2, k)).collect();
+
+        let mut order = vec![];
+        while let Some((_, _, l)) = queue.pop_first() {
+            key.remove(&l);
+            order.push(l);
+
+            let nhd = adj.remove(&l).unwrap();
+            for &x in &nhd {
+                let nx = adj.get_mut(&x).unwrap();
+                nx.remove(&l);
+                nx.extend(nhd.iter().copied().filter(|&y| y != x));
+            }
+
+            // only the fill of the neighbourhood and its neighbours can have changed
+            let mut dirty: BTreeSet<usize> = nhd.clone();
+            for x in &nhd {
+                dirty.extend(adj[x].iter().copied());
+            }
+            for x in dirty {
+                if let Some(k) = key.get_mut(&x) {
+                    queue.remove(k);
+                    *k = fill(&adj, x);
+                    queue.insert(*k);
+                }
+            }
+        }
+
+        let mut planner = Planner::new(labels);
+        for l in order {
+            if let Some(&[a, b]) = planner.holders.get(&l).map(|hs| hs.as_slice()) {
+                planner.contract(a, b);
+            }
+        }
+        planner.finish()
+    }
+
+    /// Returns the cheaper of [`TensorNetwork::greedy_path`] and
+    /// [`TensorNetwork::min_fill_path`].
+    pub fn best_path(&self) -> ContractionPath {
+        let p0 = self.greedy_path();
+        let p1 = self.min_fill_path();
+        if p1.flops < p0.flops {
+            p1
+        } else {
+            p0
+        }
+    }
+
+    /// Contracts the network along the given path.
+    ///
+    /// Panics if the path does not contract the network down to a single tensor.
+    pub fn contract_with(self, path: &ContractionPath) -> Tensor<A> {
+        let mut ts: Vec<Option<LabelledTensor<A>>> = self.tensors.into_iter().map(Some).collect();
+        for &(a, b) in &path.steps {
+            let ta = ts[a].take().expect("Tensor already contracted");
+            let tb = ts[b].take().expect("Tensor already contracted");
+            ts.push(Some(contract_pair(ta, tb)));
+        }
+
+        let mut rest = ts.into_iter().flatten();
+        let t = rest.next().expect("Empty tensor network");
+        if rest.next().is_some() {
+            panic!("Contraction path does not contract the whole network");
+        }
+
+        let perm: Vec<usize> = self
+            .open
+            .iter()
+            .map(|l| t.labels.iter().position(|l1| l1 == l).unwrap())
+            .collect();
+        t.tensor
+            .permuted_axes(perm)
+            .as_standard_layout()
+            .into_owned()
+    }
+
+    /// Contracts the network along the path given by [`TensorNetwork::best_path`].
+    pub fn contract(self) -> Tensor<A> {
+        let path = self.best_path();
+        self.contract_with(&path)
+    }
+}
+
+/// Keeps track of the labels of the tensors during the planning of a contraction.
+struct Planner {
+    labels: Vec<Vec<usize>>,
+    alive: BTreeSet<usize>,
+    holders: BTreeMap<usize, Vec<usize>>,
+    steps: Vec<(usize, usize)>,
+    flops: f64,
+    max_rank: usize,
+}
+
+impl Planner {
+    fn new(labels: Vec<Vec<usize>>) -> Self {
+        let mut holders: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, ls) in labels.iter().enumerate() {
+            for &l in ls {
+                holders.entry(l).or_default().push(i);
+            }
+        }
+        Planner {
+            alive: (0..labels.len()).collect(),
+            max_rank: labels.iter().map(|ls| ls.len()).max().unwrap_or(0),
+            labels,
+            holders,
+            steps: vec![],
+            flops: 0.0,
+        }
+    }
+
+    /// Pairs of alive tensors sharing at least one index, possibly with repetitions.
+    fn contractible_pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.holders.values().filter_map(|hs| match hs.as_slice() {
+            &[a, b] => Some((a, b)),
+            _ => None,
+        })
+    }
+
+    fn result_rank(&self, a: usize, b: usize) -> usize {
+        let (la, lb) = (&self.labels[a], &self.labels[b]);
+        let shared = la.iter().filter(|l| lb.binary_search(l).is_ok()).count();
+        la.len() + lb.len() - 2 * shared
+    }
+
+    fn contract(&mut self, a: usize, b: usize) {
+        let (la, lb) = (&self.labels[a], &self.labels[b]);
+        let mut all: Vec<usize> = la.iter().chain(lb).copied().collect();
+        all.sort();
+        let before = all.len();
+        all.dedup();
+        let involved = all.len();
+        let shared = before - involved;
+        let result: Vec<usize> = all
+            .into_iter()
+            .filter(|l| la.binary_search(l).is_err() || lb.binary_search(l).is_err())
+            .collect();
+
+        let id = self.labels.len();
+        for l in la.iter().chain(lb) {
+            if let Some(hs) = self.holders.get_mut(l) {
+                hs.retain(|&h| h != a && h != b);
+            }
+        }
+        for &l in &result {
+            self.holders.get_mut(&l).unwrap().push(id);
+        }
+        if shared > 0 {
+            self.holders.retain(|_, hs| !hs.is_empty());
+        }
+
+        self.flops += rank_size(involved);
+        self.max_rank = self.max_rank.max(result.len());
+        self.alive.remove(&a);
+        self.alive.remove(&b);
+        self.alive.insert(id);
+        self.labels.push(result);
+        self.steps.push((a, b));
+    }
+
+    /// Combines the remaining disconnected tensors, smallest first.
+    fn finish(mut self) -> ContractionPath {
+        while self.alive.len() > 1 {
+            let mut by_rank: Vec<usize> = self.alive.iter().copied().collect();
+            by_rank.sort_by_key(|&i| (self.labels[i].len(), i));
+            self.contract(by_rank[0], by_rank[1]);
+        }
+        ContractionPath {
+            steps: self.steps,
+            flops: self.flops,
+            max_rank: self.max_rank,
+        }
+    }
+}
+
+fn rank_size(rank: usize) -> f64 {
+    2f64.powi(rank as i32)
+}
+
+/// A Z-spider with `rank` legs and the given phase factor on the all-ones entry.
+fn spider<A: TensorElem>(phase: A, rank: usize) -> Tensor<A> {
+    if rank == 0 {
+        return Tensor::from_elem(vec![], A::one() + phase);
+    }
+    Tensor::from_shape_fn(vec![2; rank], |ix| {
+        if (0..rank).all(|i| ix[i] == 0) {
+            A::one()
+        } else if (0..rank).all(|i| ix[i] == 1) {
+            phase
+        } else {
+            A::zero()
+        }
+    })
+}
+
+fn hadamard<A: TensorElem>() -> Tensor<A> {
+    let n = A::one_over_sqrt2();
+    Tensor::from_shape_vec(vec![2, 2], vec![n, n, n, A::minus_one() * n]).unwrap()
+}
+
+/// Contracts all the indices shared by two tensors, as a matrix multiplication.
+fn contract_pair<A: TensorElem>(a: LabelledTensor<A>, b: LabelledTensor<A>) -> LabelledTensor<A> {
+    let shared: Vec<usize> = a
+        .labels
+        .iter()
+        .copied()
+        .filter(|l| b.labels.contains(l))
+        .collect();
+    let a_free: Vec<usize> = a
+        .labels
+        .iter()
+        .copied()
+        .filter(|l| !shared.contains(l))
+        .collect();
+    let b_free: Vec<usize> = b
+        .labels
+        .iter()
+        .copied()
+        .filter(|l| !shared.contains(l))
+        .collect();
+
+    let position = |ls: &[usize], l: &usize| ls.iter().position(|l1| l1 == l).unwrap();
+    let perm_a: Vec<usize> = a_free
+        .iter()
+        .chain(&shared)
+        .map(|l| position(&a.labels, l))
+        .collect();
+    let perm_b: Vec<usize> = shared
+        .iter()
+        .chain(&b_free)
+        .map(|l| position(&b.labels, l))
+        .collect();
+    let ta = a.tensor.permuted_axes(perm_a);
+    let ta = ta.as_standard_layout();
+    let tb = b.tensor.permuted_axes(perm_b);
+    let tb = tb.as_standard_layout();
+    let (sa, sb) = (ta.as_slice().unwrap(), tb.as_slice().unwrap());
+
+    let k = 1 << shared.len();
+    let n = 1 << b_free.len();
+    let mut c = vec![A::zero(); (1 << a_free.len()) * n];
+    c.par_chunks_mut(n).enumerate().for_each(|(i, row)| {
+        for l in 0..k {
+            let x = sa[i * k + l];
+            if x.is_zero() {
+                continue;
+            }
+            for (z, &y) in row.iter_mut().zip(&sb[l * n..(l + 1) * n]) {
+                *z = *z + x * y;
+            }
+        }
+    });
+
+    let mut labels = a_free;
+    labels.extend(b_free);
+    LabelledTensor {
+        tensor: Tensor::from_shape_vec(vec![2; labels.len()], c).unwrap(),
+        labels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::scalar::Scalar4;
+    use crate::scalar_traits::Sqrt2;
+    use crate::simplify::{clifford_simp, full_simp};
+    use crate::tensor::{Tensor4, ToTensor};
+    use crate::vec_graph::Graph;
+    use num::Zero;
+
+    #[test]
+    fn matches_to_tensor() {
+        for seed in 0..5 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(20)
+                .p_t(0.2)
+                .with_cliffords()
+                .build();
+            let mut g: Graph = c.to_graph();
+            let t: Tensor4 = TensorNetwork::from_graph(&g).contract();
+            assert_eq!(t, g.to_tensor4());
+
+            // graph-like diagrams have hadamard edges and high-degree spiders
+            clifford_simp(&mut g);
+            let tn = TensorNetwork::<Scalar4>::from_graph(&g);
+            let (p0, p1) = (tn.greedy_path(), tn.min_fill_path());
+            assert_eq!(p0.steps.len(), tn.num_tensors() - 1);
+            assert_eq!(p1.steps.len(), tn.num_tensors() - 1);
+            assert_eq!(tn.clone().contract_with(&p0), g.to_tensor4());
+            assert_eq!(tn.contract_with(&p1), g.to_tensor4());
+        }
+    }
+
+    #[test]
+    fn boundary_wires() {
+        let mut g = Graph::new();
+        let i0 = g.add_vertex(VType::B);
+        let i1 = g.add_vertex(VType::B);
+        let o0 = g.add_vertex(VType::B);
+        let o1 = g.add_vertex(VType::B);
+        g.add_edge(i0, o1);
+        g.add_edge_with_type(i1, o0, EType::H);
+        g.set_inputs(vec![i0, i1]);
+        g.set_outputs(vec![o0, o1]);
+        let t: Tensor4 = TensorNetwork::from_graph(&g).contract();
+        assert_eq!(t, g.to_tensor4());
+    }
+
+    #[test]
+    fn scalars() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(40)
+            .p_t(0.2)
+            .with_cliffords()
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 5]);
+        g.plug_outputs(&[BasisElem::X1; 5]);
+        let t: Tensor4 = TensorNetwork::from_graph(&g).contract();
+        assert_eq!(t.ndim(), 0);
+        assert_eq!(t, g.to_tensor4());
+
+        full_simp(&mut g);
+        let t1: Tensor4 = TensorNetwork::from_graph(&g).contract();
+        assert_eq!(t, t1);
+    }
+
+    #[test]
+    fn large_shallow_circuit() {
+        // a GHZ state on 60 qubits is far too large for a dense tensor
+        let n = 60;
+        let mut c = Circuit::new(n);
+        c.add_gate("h", vec![0]);
+        for q in 1..n {
+            c.add_gate("cx", vec![q - 1, q]);
+        }
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&vec![BasisElem::Z0; n]);
+
+        let mut h = g.clone();
+        h.plug_outputs(&vec![BasisElem::Z1; n]);
+        let tn = TensorNetwork::from_graph(&h);
+        assert!(tn.best_path().max_rank < 10);
+        let t: Tensor4 = tn.contract();
+        assert_eq!(t.first(), Some(&Scalar4::sqrt2_pow(-1)));
+
+        let mut h = g.clone();
+        let mut bits = vec![BasisElem::Z1; n];
+        bits[n / 2] = BasisElem::Z0;
+        h.plug_outputs(&bits);
+        let t: Tensor4 = TensorNetwork::from_graph(&h).contract();
+        assert!(t.first().unwrap().is_zero());
+
+        // leave a few outputs open
+        let mut h = g.clone();
+        let mut bits = vec![BasisElem::Z0; n];
+        bits[..3].fill(BasisElem::SKIP);
+        h.plug_outputs(&bits);
+        let t: Tensor4 = TensorNetwork::from_graph(&h).contract();
+        assert_eq!(t.shape(), &[2, 2, 2]);
+        assert_eq!(t[[0, 0, 0]], Scalar4::sqrt2_pow(-1));
+        assert!(t[[1, 1, 1]].is_zero());
+    }
+}
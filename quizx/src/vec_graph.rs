@@ -38,6 +38,7 @@ pub struct Graph {
     nume: usize,
     scalar: Scalar4,
     scalar_factors: FxHashMap<Expr, Scalar4>,
+    phase_tracker: Option<PhaseTracker>,
 }
 
 impl Graph {
@@ -104,6 +105,7 @@ impl GraphLike for Graph {
             nume: 0,
             scalar: 1.into(),
             scalar_factors: FxHashMap::default(),
+            phase_tracker: None,
         }
     }
 
@@ -200,6 +202,9 @@ impl GraphLike for Graph {
         self.holes.push(v);
 
         self.vdata[v] = None;
+        if let Some(t) = self.phase_tracker.as_mut() {
+            t.remove(v);
+        }
         let adj = mem::take(&mut self.edata[v]).expect("No such vertex.");
 
         for (v1, _) in adj {
@@ -388,6 +393,22 @@ impl GraphLike for Graph {
         }
     }
 
+    fn clear_scalar_factors(&mut self) {
+        self.scalar_factors.clear();
+    }
+
+    fn phase_tracker(&self) -> Option<&PhaseTracker> {
+        self.phase_tracker.as_ref()
+    }
+
+    fn phase_tracker_mut(&mut self) -> Option<&mut PhaseTracker> {
+        self.phase_tracker.as_mut()
+    }
+
+    fn set_phase_tracker(&mut self, tracker: Option<PhaseTracker>) {
+        self.phase_tracker = tracker;
+    }
+
     #[allow(clippy::needless_range_loop)]
     fn pack(&mut self, force: bool) {
         if force || self.holes.len() * PACK_RATIO > self.vdata.len() {
@@ -416,6 +437,9 @@ impl GraphLike for Graph {
 
             self.inputs = self.inputs.iter().map(|v| vtab[*v]).collect();
             self.outputs = self.outputs.iter().map(|v| vtab[*v]).collect();
+            if let Some(t) = self.phase_tracker.as_mut() {
+                t.rename(|v| vtab[v]);
+            }
         }
     }
 }
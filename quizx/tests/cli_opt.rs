@@ -2,6 +2,8 @@
 mod test {
     use assert_cmd::Command;
     use predicates::str::contains;
+    use quizx::circuit::Circuit;
+    use quizx::routing::CouplingMap;
     use rstest::{fixture, rstest};
 
     const CIRC: &str = "../circuits/small/mod5_4.qasm";
@@ -33,6 +35,151 @@ mod test {
         cmd.arg(CIRC).arg("--clifford").assert().success();
     }
 
+    #[rstest]
+    fn no_basic_opt(mut cmd: Command) {
+        cmd.arg(CIRC).arg("--no-basic-opt").assert().success();
+    }
+
+    #[rstest]
+    fn teleport(mut cmd: Command) {
+        cmd.arg(CIRC).arg("--teleport").assert().success();
+    }
+
+    #[rstest]
+    fn phase_poly(mut cmd: Command) {
+        cmd.arg(CIRC).arg("--phase-poly").assert().success();
+    }
+
+    #[rstest]
+    fn measurement(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("measure.qasm");
+        std::fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg b[1];\nh q[1];\nmeasure q[1] -> b[0];\n",
+        )
+        .unwrap();
+        let output = cmd.arg(&input).output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("using --teleport"));
+        let c = Circuit::from_qasm(&String::from_utf8(output.stdout).unwrap()).unwrap();
+        assert_eq!(c.num_qubits(), 2);
+        assert!(c.is_dynamic());
+        assert!(c.to_qasm().contains("measure q[1] -> c0[0];"));
+    }
+
+    #[rstest]
+    fn conditional(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("conditional.qasm");
+        std::fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\ncreg b[1];\nh q[1];\nt q[1];\nmeasure q[1] -> b[0];\nif(b==1) z q[0];\n",
+        )
+        .unwrap();
+        let output = cmd.arg(&input).output().unwrap();
+        assert!(output.status.success());
+        let out = String::from_utf8(output.stdout).unwrap();
+        assert!(out.contains("if(c0==1) z q[0];"), "{out}");
+        assert!(out.find("measure").unwrap() < out.find("if(").unwrap());
+    }
+
+    #[rstest]
+    fn register_condition(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("register.qasm");
+        std::fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[1];\ncreg c[2];\nif(c==2) x q[0];\n",
+        )
+        .unwrap();
+        cmd.arg(&input).assert().failure().stderr(contains(
+            "Unsupported circuit: 'x q[0]' is controlled by a register",
+        ));
+    }
+
+    #[rstest]
+    fn qasm3_roundtrip(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.qasm");
+        cmd.arg(CIRC).arg("--qasm3").arg("-o").arg(&out);
+        cmd.assert().success();
+        assert!(std::fs::read_to_string(&out)
+            .unwrap()
+            .starts_with("OPENQASM 3.0;"));
+
+        let mut cmd = Command::cargo_bin("quizx").unwrap();
+        cmd.arg("opt")
+            .arg(&out)
+            .assert()
+            .success()
+            .stdout(contains("OPENQASM 2.0;"));
+    }
+
+    #[rstest]
+    fn exact_angles(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.qasm");
+        std::fs::write(&input, "OPENQASM 2.0;\nqreg q[1];\nrz(0.1) q[0];\n").unwrap();
+
+        cmd.arg(&input)
+            .assert()
+            .success()
+            .stderr(contains("Warning: rounded 1 angle(s)"));
+
+        let mut cmd = Command::cargo_bin("quizx").unwrap();
+        cmd.arg("opt")
+            .arg(&input)
+            .arg("--exact-angles")
+            .assert()
+            .failure()
+            .stderr(contains("is not a rational multiple of pi"));
+    }
+
+    #[rstest]
+    fn coupling_map(mut cmd: Command) {
+        let out = cmd
+            .arg(CIRC)
+            .arg("--coupling-map")
+            .arg("line:6")
+            .assert()
+            .success();
+        let c = Circuit::from_qasm(std::str::from_utf8(&out.get_output().stdout).unwrap()).unwrap();
+        assert_eq!(c.num_qubits(), 6);
+        assert!(CouplingMap::line(6).respects(&c));
+
+        let dir = tempfile::tempdir().unwrap();
+        let map = dir.path().join("map.txt");
+        std::fs::write(&map, "0-1\n1-2\n2-3\n1-4\n").unwrap();
+        let mut cmd = Command::cargo_bin("quizx").unwrap();
+        let out = cmd
+            .arg("opt")
+            .arg(CIRC)
+            .arg("--coupling-map")
+            .arg(&map)
+            .arg("--bridges")
+            .assert()
+            .success();
+        let c = Circuit::from_qasm(std::str::from_utf8(&out.get_output().stdout).unwrap()).unwrap();
+        assert!("0-1,1-2,2-3,1-4"
+            .parse::<CouplingMap>()
+            .unwrap()
+            .respects(&c));
+
+        let mut cmd = Command::cargo_bin("quizx").unwrap();
+        cmd.arg("opt")
+            .arg(CIRC)
+            .arg("--coupling-map")
+            .arg("line:3")
+            .assert()
+            .failure()
+            .stderr(contains(
+                "Error routing circuit: circuit has 5 qubits, but the device only has 3",
+            ));
+    }
+
     #[rstest]
     fn doesnt_exist(mut cmd: Command) {
         cmd.arg("blah")
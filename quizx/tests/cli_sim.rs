@@ -3,9 +3,12 @@ mod test {
     use assert_cmd::Command;
     use predicates::{ord::eq, str::contains};
     use rstest::{fixture, rstest};
+    use std::fs;
 
     const CIRC: &str = "../circuits/small/mod5_4.qasm";
     const SAMPLE: &str = "00001\n";
+    const QFT: &str = "../circuits/small/qft_4.qasm";
+    const QFT_AMPLITUDE: &str = "0.12499956838835699\n";
 
     #[fixture]
     fn cmd() -> Command {
@@ -79,6 +82,43 @@ mod test {
             .stdout(eq("0\n"));
     }
 
+    #[rstest]
+    fn expectation_sign(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--expval=-Z")
+            .assert()
+            .success()
+            .stdout(eq("1\n"));
+    }
+
+    #[rstest]
+    fn expectation_clifford(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("bell.qasm");
+        std::fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nh q[0];\ncx q[0], q[1];\ny q[1];\n",
+        )
+        .unwrap();
+        for (p, ev) in [
+            ("XX", "-1\n"),
+            ("ZZ", "-1\n"),
+            ("YY", "-1\n"),
+            ("ZI", "0\n"),
+        ] {
+            Command::cargo_bin("quizx")
+                .unwrap()
+                .arg("sim")
+                .arg(&input)
+                .arg("--expval")
+                .arg(p)
+                .assert()
+                .success()
+                .stdout(eq(ev));
+        }
+        cmd.arg(&input).arg("--expval").arg("XX").assert().success();
+    }
+
     #[rstest]
     fn bss(mut cmd: Command) {
         cmd.arg(CIRC)
@@ -97,6 +137,437 @@ mod test {
             .stdout(eq(SAMPLE));
     }
 
+    #[rstest]
+    fn hybrid(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--hybrid")
+            .assert()
+            .success()
+            .stdout(eq(SAMPLE));
+    }
+
+    #[rstest]
+    fn hybrid_amplitude(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--hybrid")
+            .arg("--amplitude")
+            .arg("00001")
+            .assert()
+            .success()
+            .stdout(eq("1\n"));
+    }
+
+    #[rstest]
+    fn memoize(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--memoize")
+            .assert()
+            .success()
+            .stdout(eq(SAMPLE));
+    }
+
+    #[rstest]
+    fn checkpoint_resume(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let checkpoint = dir.path().join("checkpoint.json");
+        cmd.arg(CIRC)
+            .arg("--amplitude")
+            .arg("00001")
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--checkpoint-interval")
+            .arg("0")
+            .assert()
+            .success()
+            .stdout(eq("1\n"));
+        assert!(checkpoint.exists());
+
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(CIRC)
+            .arg("--amplitude")
+            .arg("00001")
+            .arg("--checkpoint")
+            .arg(&checkpoint)
+            .arg("--resume")
+            .assert()
+            .success()
+            .stdout(eq("1\n"));
+    }
+
+    #[rstest]
+    fn checkpoint_sample(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        cmd.arg(CIRC)
+            .arg("--checkpoint")
+            .arg(dir.path().join("checkpoint.json"))
+            .assert()
+            .failure()
+            .stderr(contains("checkpoints are not supported when sampling"));
+    }
+
+    #[rstest]
+    fn resume_missing(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        cmd.arg(CIRC)
+            .arg("--amplitude")
+            .arg("00001")
+            .arg("--checkpoint")
+            .arg(dir.path().join("checkpoint.json"))
+            .arg("--resume")
+            .assert()
+            .failure()
+            .stderr(contains("Error with checkpoint"));
+    }
+
+    #[rstest]
+    fn counts(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--shots")
+            .arg("100")
+            .arg("--counts")
+            .arg("json")
+            .assert()
+            .success()
+            .stdout(eq("{\"00001\":100}\n"));
+    }
+
+    #[rstest]
+    fn counts_csv(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("bell.qasm");
+        fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nh q[0];\nt q[0];\ncx q[0], q[1];\n",
+        )
+        .unwrap();
+        let output = cmd
+            .arg(&input)
+            .arg("--shots")
+            .arg("1000")
+            .arg("--counts")
+            .arg("csv")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("bitstring,count"));
+        let counts: Vec<(&str, usize)> = lines
+            .map(|l| {
+                let (bits, n) = l.split_once(',').unwrap();
+                (bits, n.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(counts.iter().map(|(_, n)| n).sum::<usize>(), 1000);
+        for (bits, n) in counts {
+            assert!(bits == "00" || bits == "11");
+            assert!((350..=650).contains(&n));
+        }
+    }
+
+    #[rstest]
+    fn counts_amplitude(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--amplitude")
+            .arg("0")
+            .arg("--counts")
+            .arg("json")
+            .assert()
+            .failure();
+    }
+
+    fn write_circuit(dir: &tempfile::TempDir, body: &str) -> std::path::PathBuf {
+        let input = dir.path().join("circuit.qasm");
+        fs::write(
+            &input,
+            format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[1];\n{body}"),
+        )
+        .unwrap();
+        input
+    }
+
+    #[rstest]
+    fn noisy_counts(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_circuit(&dir, "x q[0];\nt q[0];\n");
+        let output = cmd
+            .arg(&input)
+            .arg("--shots")
+            .arg("1000")
+            .arg("--counts")
+            .arg("csv")
+            .arg("--noise")
+            .arg("measure:bit-flip:0.2")
+            .arg("--noise")
+            .arg("t:phase-flip:0.5")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let (_, zeros) = stdout.lines().nth(1).unwrap().split_once(',').unwrap();
+        let zeros: usize = zeros.parse().unwrap();
+        assert!((120..=280).contains(&zeros));
+    }
+
+    #[rstest]
+    fn noisy_expval(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_circuit(&dir, "t q[0];\n");
+        let output = cmd
+            .arg(&input)
+            .arg("--expval")
+            .arg("Z")
+            .arg("--noise")
+            .arg("bit-flip:0.25")
+            .arg("--trajectories")
+            .arg("2000")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let ev: f64 = String::from_utf8(output.stdout)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!((0.4..=0.6).contains(&ev));
+
+        // phase flips commute with Z
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(&input)
+            .arg("--expval")
+            .arg("Z")
+            .arg("--noise")
+            .arg("phase-flip:0.5")
+            .assert()
+            .success()
+            .stdout(eq("1\n"));
+    }
+
+    #[rstest]
+    fn invalid_noise(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--noise")
+            .arg("foo:depolarizing:0.1")
+            .assert()
+            .failure()
+            .stderr(contains("not a valid noise channel"));
+    }
+
+    #[rstest]
+    fn noisy_amplitude(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("--amplitude")
+            .arg("0")
+            .arg("--noise")
+            .arg("depolarizing:0.1")
+            .assert()
+            .failure()
+            .stderr(contains("cannot be used with"));
+    }
+
+    #[rstest]
+    fn marginals(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("ghz.qasm");
+        fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[3];\nh q[0];\ncx q[0], q[2];\nx q[1];\nt q[2];\n",
+        )
+        .unwrap();
+        cmd.arg(&input)
+            .arg("--marginals")
+            .arg("q0,q2")
+            .assert()
+            .success()
+            .stdout(eq("00 0.5\n01 0\n10 0\n11 0.5\n"));
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(&input)
+            .arg("--marginals")
+            .arg("1,0")
+            .assert()
+            .success()
+            .stdout(eq("00 0\n01 0\n10 0.5\n11 0.5\n"));
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(&input)
+            .arg("--marginals")
+            .arg("q3")
+            .assert()
+            .failure()
+            .stderr(contains("circuit has 3 qubits, but qubit 3 was given"));
+    }
+
+    #[rstest]
+    fn hamiltonian(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("h.txt");
+        fs::write(
+            &text,
+            "# the output is 00001\n0.5 ZZZZZ\n2 Z4\n-1\n0.25 X0 Y3\n",
+        )
+        .unwrap();
+        cmd.arg(CIRC)
+            .arg("--hamiltonian")
+            .arg(&text)
+            .assert()
+            .success()
+            .stdout(eq("-3.5\n"));
+
+        let json = dir.path().join("h.json");
+        fs::write(
+            &json,
+            r#"[{"coeff": 0.5, "pauli": "ZZZZZ"}, {"coeff": 2, "pauli": "Z4"}, {"coeff": -1, "pauli": ""}]"#,
+        )
+        .unwrap();
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(CIRC)
+            .arg("--hamiltonian")
+            .arg(&json)
+            .assert()
+            .success()
+            .stdout(eq("-3.5\n"));
+
+        fs::write(&text, "1.0 Z5\n").unwrap();
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(CIRC)
+            .arg("--hamiltonian")
+            .arg(&text)
+            .assert()
+            .failure()
+            .stderr(contains(
+                "circuit has 5 qubits, but the Hamiltonian acts on 6",
+            ));
+
+        fs::write(&text, "1.0 Z0\nZ1\n").unwrap();
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(CIRC)
+            .arg("--hamiltonian")
+            .arg(&text)
+            .assert()
+            .failure()
+            .stderr(contains("line 2: 'Z1' is not a number"));
+    }
+
+    #[rstest]
+    fn distribute(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = dir.path().join("tasks");
+        cmd.arg(QFT)
+            .arg("--amplitude")
+            .arg("0")
+            .arg("--distribute")
+            .arg(&tasks)
+            .arg("--distribute-depth")
+            .arg("2")
+            .arg("--workers")
+            .arg("2")
+            .assert()
+            .success()
+            .stdout(eq(QFT_AMPLITUDE));
+        assert!(tasks.join("plan.json").exists());
+
+        // a result that went missing is computed again on resume
+        fs::remove_file(tasks.join("result-0.json")).unwrap();
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(QFT)
+            .arg("--amplitude")
+            .arg("0")
+            .arg("--distribute")
+            .arg(&tasks)
+            .arg("--resume")
+            .assert()
+            .success()
+            .stdout(eq(QFT_AMPLITUDE));
+    }
+
+    #[rstest]
+    fn distribute_reused_dir(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = dir.path().join("tasks");
+        cmd.arg(QFT)
+            .arg("--amplitude")
+            .arg("0")
+            .arg("--distribute")
+            .arg(&tasks)
+            .arg("--distribute-depth")
+            .arg("2")
+            .assert()
+            .success()
+            .stdout(eq(QFT_AMPLITUDE));
+
+        // the results for the first circuit must not be mixed into the second one
+        let input = dir.path().join("c2.qasm");
+        fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[2];\nh q[0];\nt q[0];\ncx q[0], q[1];\nt q[1];\nh q[1];\n",
+        )
+        .unwrap();
+        let sim = |tasks: &std::path::Path| {
+            let mut cmd = Command::cargo_bin("quizx").unwrap();
+            cmd.arg("sim")
+                .arg(&input)
+                .arg("--amplitude")
+                .arg("0")
+                .arg("--distribute")
+                .arg(tasks);
+            cmd
+        };
+        sim(&tasks)
+            .assert()
+            .failure()
+            .stderr(contains("already contains tasks"));
+
+        let expected = Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim")
+            .arg(&input)
+            .arg("--amplitude")
+            .arg("0")
+            .output()
+            .unwrap()
+            .stdout;
+        fs::remove_dir_all(&tasks).unwrap();
+        sim(&tasks).assert().success().stdout(eq(expected));
+    }
+
+    #[rstest]
+    fn distribute_sample(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        cmd.arg(CIRC)
+            .arg("--distribute")
+            .arg(dir.path())
+            .assert()
+            .failure()
+            .stderr(contains("not supported when sampling"));
+    }
+
+    #[rstest]
+    fn sim_worker_missing_plan() {
+        let dir = tempfile::tempdir().unwrap();
+        Command::cargo_bin("quizx")
+            .unwrap()
+            .arg("sim-worker")
+            .arg(dir.path())
+            .assert()
+            .failure()
+            .stderr(contains("Error with checkpoint"));
+    }
+
     #[rstest]
     fn parallel_sample(mut cmd: Command) {
         cmd.arg(CIRC)
@@ -187,6 +658,25 @@ mod test {
             ));
     }
 
+    #[rstest]
+    fn conditional_not_pauli(mut cmd: Command) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("conditional.qasm");
+        fs::write(
+            &input,
+            "OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[1];\ncreg c[1];\nmeasure q[0] -> c[0];\nif(c==1) h q[0];\n",
+        )
+        .unwrap();
+        cmd.arg(&input)
+            .arg("--amplitude")
+            .arg("0")
+            .assert()
+            .failure()
+            .stderr(contains(
+                "Unsupported circuit: 'h q[0]' is classically controlled",
+            ));
+    }
+
     #[rstest]
     fn bad_pauli(mut cmd: Command) {
         cmd.arg(CIRC)